@@ -7,6 +7,7 @@ use serde_json::{json, Map as JsonMap, Value as JsonValue};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 
@@ -183,6 +184,17 @@ fn lower_first(s: &str) -> String {
     }
 }
 
+/// Dotted IPv4 or RFC 5952 IPv6 text for a 4- or 16-byte address.
+fn format_ip(data: &[u8]) -> Option<String> {
+    if let Ok(v4) = <[u8; 4]>::try_from(data) {
+        return Some(Ipv4Addr::from(v4).to_string());
+    }
+    if let Ok(v6) = <[u8; 16]>::try_from(data) {
+        return Some(Ipv6Addr::from(v6).to_string());
+    }
+    None
+}
+
 /// DER / BER decoder with heuristic decoding (similar to Python)
 struct DerDecoder {
     schema: Asn1Schema,
@@ -735,34 +747,15 @@ impl DerDecoder {
     /// IP address decoding similar to Python decode_ip_address
     #[inline]
     fn decode_ip_address(&self, data: &[u8]) -> JsonValue {
-        if data.len() == 4 {
-            let s = format!("{}.{}.{}.{}", data[0], data[1], data[2], data[3]);
+        if let Some(s) = format_ip(data) {
             return json!(s);
         }
 
-        if data.len() == 16 {
-            // naive IPv6 hex groups
-            let mut parts = Vec::with_capacity(8);
-            for i in (0..16).step_by(2) {
-                parts.push(format!("{:02x}{:02x}", data[i], data[i + 1]));
-            }
-            return json!(parts.join(":"));
-        }
-
         // Try to interpret as TLV wrapping IP
         if let Some((tlv, _)) = self.parse_tlv(data, 0) {
-            let val = tlv.value;
-            if val.len() == 4 {
-                let s = format!("{}.{}.{}.{}", val[0], val[1], val[2], val[3]);
+            if let Some(s) = format_ip(tlv.value) {
                 return json!(s);
             }
-            if val.len() == 16 {
-                let mut parts = Vec::with_capacity(8);
-                for i in (0..16).step_by(2) {
-                    parts.push(format!("{:02x}{:02x}", val[i], val[i + 1]));
-                }
-                return json!(parts.join(":"));
-            }
         }
 
         json!(hex::encode(data))
@@ -53,14 +53,17 @@ struct FieldSpec {
 /// Schema representation
 #[derive(Debug, Default)]
 struct Asn1Schema {
-    // type_name -> tag -> (field_name, field_type)
-    // NOTE: untagged CHOICE alternatives are stored with synthetic tags SYNTH_CHOICE_BASE + idx
-    choices: HashMap<String, HashMap<u32, (String, String)>>,
+    // type_name -> (tag class, tag number) -> (field_name, field_type)
+    // NOTE: untagged CHOICE alternatives are stored with synthetic tags (3, SYNTH_CHOICE_BASE + idx)
+    choices: HashMap<String, HashMap<TagKey, (String, String)>>,
     sequences: HashMap<String, HashMap<u32, FieldSpec>>,
     sets: HashMap<String, HashMap<u32, FieldSpec>>,
     primitives: HashMap<String, String>, // type_name -> primitive kind
 }
 
+// (class, number): [APPLICATION 5] and [5] are different alternatives.
+type TagKey = (u8, u32);
+
 // Synthetic tags reserved for untagged CHOICE alternatives.
 const SYNTH_CHOICE_BASE: u32 = 0xFFFF_FF00;
 
@@ -79,7 +82,7 @@ impl Asn1Schema {
 
         // Allow '-' in identifiers.
         // IMPORTANT: for the right-hand side type, allow multi-word like "OCTET STRING".
-        let choice_tagged_re = Regex::new(r"([\w-]+)\s+\[(\d+)\]\s+([\w-]+(?:\s+[\w-]+)?)")?;
+        let choice_tagged_re = Regex::new(r"([\w-]+)\s+\[(?:(UNIVERSAL|APPLICATION|PRIVATE)\s+)?(\d+)\]\s+([\w-]+(?:\s+[\w-]+)?)")?;
         let choice_untagged_re = Regex::new(r"([\w-]+)\s+([\w-]+(?:\s+[\w-]+)?)")?;
 
         let sequence_body_re = Regex::new(
@@ -101,9 +104,15 @@ impl Asn1Schema {
                     // Tagged alternatives: name [n] Type
                     for c in choice_tagged_re.captures_iter(body) {
                         let field_name = c.get(1).unwrap().as_str().to_string();
-                        let tag: u32 = c.get(2).unwrap().as_str().parse()?;
-                        let field_type = c.get(3).unwrap().as_str().trim().to_string();
-                        alts.insert(tag, (field_name, field_type));
+                        let class = match c.get(2).map(|m| m.as_str()) {
+                            Some("UNIVERSAL") => 0,
+                            Some("APPLICATION") => 1,
+                            Some("PRIVATE") => 3,
+                            _ => 2,
+                        };
+                        let tag: u32 = c.get(3).unwrap().as_str().parse()?;
+                        let field_type = c.get(4).unwrap().as_str().trim().to_string();
+                        alts.insert((class, tag), (field_name, field_type));
                     }
 
                     // Untagged alternatives: name Type (store under synthetic tags)
@@ -115,7 +124,7 @@ impl Asn1Schema {
                             if field_name.is_empty() || field_type.is_empty() {
                                 continue;
                             }
-                            alts.insert(SYNTH_CHOICE_BASE + idx, (field_name, field_type));
+                            alts.insert((3, SYNTH_CHOICE_BASE + idx), (field_name, field_type));
                             idx += 1;
                             if idx >= 255 {
                                 break;
@@ -224,7 +233,7 @@ fn write_hex_json<W: Write>(w: &mut W, data: &[u8]) -> Result<()> {
 struct DerDecoder {
     schema: Asn1Schema,
     record_like_types: Vec<String>,
-    cs_choice_index: HashMap<u32, String>,
+    cs_choice_index: HashMap<TagKey, String>,
 }
 
 impl DerDecoder {
@@ -241,11 +250,11 @@ impl DerDecoder {
             }
         }
 
-        // Build context-specific CHOICE index from tagged CHOICE alts only (ignore synthetic)
-        let mut cs_choice_index = HashMap::new();
+        // Build the (class, tag) CHOICE index from tagged CHOICE alts only (ignore synthetic)
+        let mut cs_choice_index: HashMap<TagKey, String> = HashMap::new();
         for (_choice_name, alts) in &schema.choices {
             for (tag, (_fname, ftype)) in alts {
-                if is_synth_choice_tag(*tag) {
+                if is_synth_choice_tag(tag.1) {
                     continue;
                 }
                 cs_choice_index.entry(*tag).or_insert(ftype.clone());
@@ -506,7 +515,7 @@ impl DerDecoder {
         // If alt_type is a CHOICE with tagged alts (like IPBinaryAddress),
         // then the incoming tlv.tag_num should match one of its real tags.
         if let Some(sub_alts) = self.schema.choices.get(alt_type) {
-            if sub_alts.contains_key(&tlv.tag_num) {
+            if sub_alts.contains_key(&(tlv.tag_class, tlv.tag_num)) {
                 return true;
             }
         }
@@ -525,7 +534,7 @@ impl DerDecoder {
     fn write_choice<W: Write>(
         &self,
         data: &[u8],
-        alts: &HashMap<u32, (String, String)>,
+        alts: &HashMap<TagKey, (String, String)>,
         out: &mut W,
     ) -> Result<()> {
         // Parse outer TLV from data
@@ -554,7 +563,7 @@ impl DerDecoder {
 
         // 1) Tagged CHOICE resolution
         for cand in &candidates {
-            if let Some((field_name, type_name)) = alts.get(&cand.tag_num) {
+            if let Some((field_name, type_name)) = alts.get(&(cand.tag_class, cand.tag_num)) {
                 write_json_string(out, field_name)?;
                 out.write_all(b":")?;
 
@@ -569,10 +578,10 @@ impl DerDecoder {
 
         // 2) Untagged CHOICE: probe synthetic alternatives.
         // FIXED: use closure (build error you saw).
-        let mut synth_keys: Vec<u32> = alts
+        let mut synth_keys: Vec<TagKey> = alts
             .keys()
             .copied()
-            .filter(|t| is_synth_choice_tag(*t))
+            .filter(|t| is_synth_choice_tag(t.1))
             .collect();
         synth_keys.sort_unstable();
 
@@ -624,15 +633,13 @@ impl DerDecoder {
     }
 
     fn write_auto_record<W: Write>(&self, tlv: &Tlv, out: &mut W) -> Result<()> {
-        if tlv.tag_class == 2 {
-            if let Some(alt_type) = self.cs_choice_index.get(&tlv.tag_num) {
-                out.write_all(b"{")?;
-                write_json_string(out, &lower_first(alt_type))?;
-                out.write_all(b":")?;
-                self.write_type(tlv.value, alt_type, out)?;
-                out.write_all(b"}")?;
-                return Ok(());
-            }
+        if let Some(alt_type) = self.cs_choice_index.get(&(tlv.tag_class, tlv.tag_num)) {
+            out.write_all(b"{")?;
+            write_json_string(out, &lower_first(alt_type))?;
+            out.write_all(b":")?;
+            self.write_type(tlv.value, alt_type, out)?;
+            out.write_all(b"}")?;
+            return Ok(());
         }
 
         // fallback
@@ -53,9 +53,9 @@ struct FieldSpec {
 /// Schema representation
 #[derive(Debug, Default)]
 struct Asn1Schema {
-    // type_name -> tag -> (field_name, field_type)
-    // NOTE: untagged CHOICE alternatives are stored with synthetic tags SYNTH_CHOICE_BASE + idx
-    choices: HashMap<String, HashMap<u32, (String, String)>>,
+    // type_name -> (tag class, tag number) -> (field_name, field_type)
+    // NOTE: untagged CHOICE alternatives are stored with synthetic tags (3, SYNTH_CHOICE_BASE + idx)
+    choices: HashMap<String, HashMap<TagKey, (String, String)>>,
     sequences: HashMap<String, HashMap<u32, FieldSpec>>,
     sets: HashMap<String, HashMap<u32, FieldSpec>>,
     primitives: HashMap<String, String>, // type_name -> primitive kind
@@ -67,6 +67,9 @@ struct Asn1Schema {
     aliases: HashMap<String, String>,
 }
 
+// (class, number): [APPLICATION 5] and [5] are different alternatives.
+type TagKey = (u8, u32);
+
 const SYNTH_CHOICE_BASE: u32 = 0xFFFF_FF00;
 fn is_synth_choice_tag(t: u32) -> bool {
     t >= SYNTH_CHOICE_BASE
@@ -85,7 +88,7 @@ impl Asn1Schema {
         let alias_re = Regex::new(r"(?m)^\s*([\w-]+)\s*::=\s*([\w-]+)\s*$")?;
 
         // Allow '-' in identifiers
-        let choice_tagged_re = Regex::new(r"([\w-]+)\s+\[(\d+)\]\s+([\w-]+)")?;
+        let choice_tagged_re = Regex::new(r"([\w-]+)\s+\[(?:(UNIVERSAL|APPLICATION|PRIVATE)\s+)?(\d+)\]\s+([\w-]+)")?;
         let choice_untagged_re = Regex::new(r"([\w-]+)\s+([\w-]+)")?;
 
         let sequence_body_re = Regex::new(
@@ -141,9 +144,15 @@ impl Asn1Schema {
                     // Tagged CHOICE alternatives: name [n] Type
                     for c in choice_tagged_re.captures_iter(body) {
                         let field_name = c.get(1).unwrap().as_str().to_string();
-                        let tag: u32 = c.get(2).unwrap().as_str().parse()?;
-                        let field_type = c.get(3).unwrap().as_str().to_string();
-                        alts.insert(tag, (field_name, field_type));
+                        let class = match c.get(2).map(|m| m.as_str()) {
+                            Some("UNIVERSAL") => 0,
+                            Some("APPLICATION") => 1,
+                            Some("PRIVATE") => 3,
+                            _ => 2,
+                        };
+                        let tag: u32 = c.get(3).unwrap().as_str().parse()?;
+                        let field_type = c.get(4).unwrap().as_str().to_string();
+                        alts.insert((class, tag), (field_name, field_type));
                     }
 
                     // Untagged CHOICE: name Type (store synthetic tags)
@@ -155,7 +164,7 @@ impl Asn1Schema {
                             if field_name.is_empty() || field_type.is_empty() {
                                 continue;
                             }
-                            alts.insert(SYNTH_CHOICE_BASE + idx, (field_name, field_type));
+                            alts.insert((3, SYNTH_CHOICE_BASE + idx), (field_name, field_type));
                             idx += 1;
                             if idx >= 255 {
                                 break;
@@ -321,7 +330,7 @@ fn lower_first(s: &str) -> String {
 struct DerDecoder {
     schema: Asn1Schema,
     record_like_types: Vec<String>,
-    cs_choice_index: HashMap<u32, String>,
+    cs_choice_index: HashMap<TagKey, String>,
 }
 
 impl DerDecoder {
@@ -338,10 +347,10 @@ impl DerDecoder {
             }
         }
 
-        let mut cs_choice_index: HashMap<u32, String> = HashMap::new();
+        let mut cs_choice_index: HashMap<TagKey, String> = HashMap::new();
         for (_choice_name, alts) in &schema.choices {
             for (tag, (_field_name, field_type)) in alts {
-                if is_synth_choice_tag(*tag) {
+                if is_synth_choice_tag(tag.1) {
                     continue;
                 }
                 cs_choice_index.entry(*tag).or_insert(field_type.clone());
@@ -809,7 +818,7 @@ impl DerDecoder {
         let rt = self.schema.resolve_alias(alt_type);
 
         if let Some(sub_alts) = self.schema.choices.get(rt) {
-            if sub_alts.contains_key(&tlv.tag_num) {
+            if sub_alts.contains_key(&(tlv.tag_class, tlv.tag_num)) {
                 return true;
             }
         }
@@ -827,7 +836,7 @@ impl DerDecoder {
     fn write_choice<W: Write>(
         &self,
         data: &[u8],
-        alts: &HashMap<u32, (String, String)>,
+        alts: &HashMap<TagKey, (String, String)>,
         out: &mut W,
     ) -> Result<()> {
         let (outer, _) = match self.parse_tlv(data, 0) {
@@ -851,7 +860,7 @@ impl DerDecoder {
 
         // Tagged CHOICE
         for cand in &candidates {
-            if let Some((field_name, type_name)) = alts.get(&cand.tag_num) {
+            if let Some((field_name, type_name)) = alts.get(&(cand.tag_class, cand.tag_num)) {
                 write_json_string(out, field_name)?;
                 out.write_all(b":")?;
                 self.write_type(cand.value, type_name, out)?;
@@ -861,10 +870,10 @@ impl DerDecoder {
         }
 
         // Untagged CHOICE: probe synthetic alts
-        let mut synth_keys: Vec<u32> = alts
+        let mut synth_keys: Vec<TagKey> = alts
             .keys()
             .copied()
-            .filter(|t| is_synth_choice_tag(*t))
+            .filter(|t| is_synth_choice_tag(t.1))
             .collect();
         synth_keys.sort_unstable();
 
@@ -914,16 +923,14 @@ impl DerDecoder {
     }
 
     fn write_auto_record<W: Write>(&self, tlv: &Tlv, out: &mut W) -> Result<()> {
-        if tlv.tag_class == 2 {
-            if let Some(alt_type) = self.cs_choice_index.get(&tlv.tag_num) {
-                out.write_all(b"{")?;
-                let key = lower_first(alt_type);
-                write_json_string(out, &key)?;
-                out.write_all(b":")?;
-                self.write_type(tlv.value, alt_type, out)?;
-                out.write_all(b"}")?;
-                return Ok(());
-            }
+        if let Some(alt_type) = self.cs_choice_index.get(&(tlv.tag_class, tlv.tag_num)) {
+            out.write_all(b"{")?;
+            let key = lower_first(alt_type);
+            write_json_string(out, &key)?;
+            out.write_all(b":")?;
+            self.write_type(tlv.value, alt_type, out)?;
+            out.write_all(b"}")?;
+            return Ok(());
         }
 
         out.write_all(b"{")?;
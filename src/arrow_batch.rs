@@ -0,0 +1,325 @@
+//! Arrow interop (feature `arrow`): decode every root record in a buffer into one `RecordBatch`.
+//!
+//! The Arrow schema is derived from the ASN.1 schema, not from the data, so every batch for a
+//! root type has the same columns, in declaration order, including OPTIONAL fields that no
+//! record carries. The root must be a SEQUENCE or SET (one column per component) or a CHOICE
+//! (one column per alternative, in tag order). Every column is nullable. Types map as:
+//!
+//! * INTEGER => `Int64`, plus a `<field>Name` `Utf8` column for named numbers
+//! * ENUMERATED => `Struct { value: Int64, name: Utf8 }`
+//! * REAL => `Float64`, BOOLEAN => `Boolean`, NULL => `Null`
+//! * OCTET STRING => `Binary`, or `Utf8` when an option renders the field as text (`tbcd`,
+//!   `plmn_id`, `charset_fields`, an IP-named field under `semantic`, `octet_as_text`)
+//! * IA5String, UTF8String and the other strings the JSON output keeps as hex => `Utf8`, the
+//!   bytes read as UTF-8 (invalid sequences become U+FFFD)
+//! * SEQUENCE / SET / CHOICE => `Struct`, SEQUENCE OF / SET OF => `List`
+//! * everything else (decoded strings, times, OIDs, BIT STRING) => `Utf8`: the JSON string, or
+//!   the JSON text of an object value. So is a type nested inside itself.
+//!
+//! Records are decoded with the regular writers and appended column by column. Binary values
+//! are always read back from hex and numbers are never quoted, whatever the decoder's output
+//! options; `with_tags`, `with_raw` and `--repeated-tags array` are ignored. Members that are
+//! not schema components (`unknown_tag_*`, `field_2`, `_warning`) are dropped. A root record
+//! cut off by the end of the buffer, or a value that doesn't fit its column (an INTEGER beyond
+//! 64 bits), is an error.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use arrow::array::{
+    new_null_array, ArrayRef, BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, ListArray, NullArray,
+    StringBuilder, StructArray,
+};
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::datatypes::{DataType, Field, Fields, Schema};
+use arrow::record_batch::RecordBatch;
+use serde_json::Value;
+
+use crate::sink::{NumberFormat, SpecialFloats, ValueEncoding};
+use crate::{DecodeOptions, DerDecoder, FieldSpec, RepeatedTags, TagKey};
+
+/// How the values of one schema type are stored in Arrow.
+enum ColumnKind {
+    Int64,
+    Float64,
+    Boolean,
+    Null,
+    Binary,
+    Utf8,
+    /// A character string the decoder writes as hex; stored as (lossy) UTF-8 text.
+    HexText,
+    Struct(Vec<(String, ColumnKind)>),
+    List(Box<ColumnKind>),
+}
+
+impl ColumnKind {
+    fn data_type(&self) -> DataType {
+        match self {
+            ColumnKind::Int64 => DataType::Int64,
+            ColumnKind::Float64 => DataType::Float64,
+            ColumnKind::Boolean => DataType::Boolean,
+            ColumnKind::Null => DataType::Null,
+            ColumnKind::Binary => DataType::Binary,
+            ColumnKind::Utf8 | ColumnKind::HexText => DataType::Utf8,
+            ColumnKind::Struct(members) => DataType::Struct(struct_fields(members)),
+            ColumnKind::List(element) => DataType::List(Arc::new(Field::new("item", element.data_type(), true))),
+        }
+    }
+}
+
+fn struct_fields(members: &[(String, ColumnKind)]) -> Fields {
+    members.iter().map(|(name, kind)| Field::new(name, kind.data_type(), true)).collect()
+}
+
+impl DerDecoder<'_> {
+    /// The Arrow schema [`decode_to_arrow`](Self::decode_to_arrow) gives `root_type`.
+    pub fn arrow_schema(&self, root_type: &str) -> Result<Schema> {
+        Ok(Schema::new(struct_fields(&self.root_columns(root_type)?)))
+    }
+
+    pub fn decode_to_arrow(&self, data: &[u8], root_type: &str) -> Result<RecordBatch> {
+        let columns = self.root_columns(root_type)?;
+        let schema = Arc::new(Schema::new(struct_fields(&columns)));
+
+        // The JSON shape the column builders expect, whatever this decoder writes otherwise.
+        let opts = DecodeOptions {
+            value_encoding: ValueEncoding::Hex,
+            number_format: NumberFormat::Number,
+            special_floats: SpecialFloats::String,
+            pretty_indent: None,
+            with_tags: false,
+            with_raw: false,
+            repeated_tags: RepeatedTags::Suffix,
+            ..self.opts.clone()
+        };
+        let decoder = DerDecoder::with_schema_ref(&self.schema, opts);
+        let mut records: Vec<Value> = Vec::new();
+        decoder.records(data, root_type).decode_each(|json| {
+            records.push(serde_json::from_slice(json).context("decoder produced invalid JSON")?);
+            Ok(true)
+        })?;
+
+        let rows: Vec<Option<&Value>> = records.iter().map(Some).collect();
+        let arrays = columns
+            .iter()
+            .map(|(name, kind)| build_column(kind, name, &members(&rows, name)))
+            .collect::<Result<Vec<_>>>()?;
+        RecordBatch::try_new(schema, arrays).context("Failed to build Arrow RecordBatch")
+    }
+
+    fn root_columns(&self, root_type: &str) -> Result<Vec<(String, ColumnKind)>> {
+        if !self.schema.knows_type(root_type) {
+            return Err(anyhow!("root-type '{}' not found in schema", root_type));
+        }
+        match self.column_kind(root_type, None, &mut Vec::new()) {
+            ColumnKind::Struct(members) => Ok(members),
+            _ => Err(anyhow!("Arrow output needs a SEQUENCE, SET or CHOICE root type, not '{}'", root_type)),
+        }
+    }
+
+    /// Column layout of `type_name`; `field` is (parent type, component) when the value is a
+    /// SEQUENCE / SET component, for the options that apply per field. `open` holds the
+    /// constructed types being expanded, so a type nested in itself stops as `Utf8`.
+    fn column_kind(&self, type_name: &str, field: Option<(&str, &FieldSpec)>, open: &mut Vec<String>) -> ColumnKind {
+        let rt = self.schema.resolve_alias(type_name);
+        if open.iter().any(|t| t == rt) {
+            return ColumnKind::Utf8;
+        }
+        if let Some(element) = self.schema.seq_of_types.get(rt).or_else(|| self.schema.set_of_types.get(rt)) {
+            open.push(rt.to_string());
+            let element = self.column_kind(element, None, open);
+            open.pop();
+            return ColumnKind::List(Box::new(element));
+        }
+        if let Some(fields) = self.schema.fields_in_order(rt) {
+            open.push(rt.to_string());
+            let mut members = Vec::with_capacity(fields.len());
+            for f in fields {
+                let kind = match self.opts.type_overrides.get(rt).and_then(|m| m.get(&f.name)) {
+                    Some(forced) => self.column_kind(forced, None, open),
+                    None if f.is_sequence_of || f.is_set_of => {
+                        ColumnKind::List(Box::new(self.column_kind(&f.field_type, None, open)))
+                    }
+                    None => self.column_kind(&f.field_type, Some((rt, f)), open),
+                };
+                let named = matches!(kind, ColumnKind::Int64) && self.schema.named_ints(&f.field_type).is_some();
+                members.push((f.name.clone(), kind));
+                if named {
+                    members.push((format!("{}Name", f.name), ColumnKind::Utf8));
+                }
+            }
+            open.pop();
+            return ColumnKind::Struct(members);
+        }
+        if let Some(alts) = self.schema.choices.get(rt) {
+            open.push(rt.to_string());
+            let mut alts: Vec<(&TagKey, &(String, String))> = alts.iter().collect();
+            alts.sort_unstable_by_key(|(k, _)| **k);
+            let members = alts
+                .into_iter()
+                .map(|(_, (name, alt_type))| (name.clone(), self.column_kind(alt_type, None, open)))
+                .collect();
+            open.pop();
+            return ColumnKind::Struct(members);
+        }
+        match self.primitive_kind(rt) {
+            "INTEGER" => ColumnKind::Int64,
+            "ENUMERATED" => ColumnKind::Struct(vec![("value".into(), ColumnKind::Int64), ("name".into(), ColumnKind::Utf8)]),
+            "REAL" => ColumnKind::Float64,
+            "BOOLEAN" => ColumnKind::Boolean,
+            "NULL" => ColumnKind::Null,
+            "OCTET STRING" if !self.octets_as_text(rt, field) => ColumnKind::Binary,
+            "IA5String" | "UTF8String" | "GeneralString" | "TeletexString" | "T61String" | "VideotexString"
+                if !self.charset_applies(field) =>
+            {
+                ColumnKind::HexText
+            }
+            _ => ColumnKind::Utf8,
+        }
+    }
+
+    /// Whether an option writes this OCTET STRING as a JSON string instead of hex.
+    fn octets_as_text(&self, type_name: &str, field: Option<(&str, &FieldSpec)>) -> bool {
+        let ip = field.is_some_and(|(_, f)| self.opts.semantic && self.named_like_ip(&f.name));
+        ip || self.charset_applies(field)
+            || self.opts.octet_as_text
+            || (self.opts.tbcd.is_some() && self.schema.derives_from(type_name, "TBCD-STRING"))
+            || (self.opts.plmn_id.is_some() && self.schema.derives_from(type_name, "PLMN-Id"))
+    }
+}
+
+impl DerDecoder<'_> {
+    /// Whether `--charset-field` names this component, which then comes out as text.
+    fn charset_applies(&self, field: Option<(&str, &FieldSpec)>) -> bool {
+        field.is_some_and(|(parent, f)| self.opts.charset_fields.get(parent).is_some_and(|m| m.contains_key(&f.name)))
+    }
+}
+
+/// The `name` member of each object in `values`; `None` for anything else and for JSON null.
+fn members<'a>(values: &[Option<&'a Value>], name: &str) -> Vec<Option<&'a Value>> {
+    values
+        .iter()
+        .map(|v| v.and_then(Value::as_object).and_then(|o| o.get(name)).filter(|v| !v.is_null()))
+        .collect()
+}
+
+fn hex_bytes(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn mismatch(column: &str, value: &Value, expected: &str) -> anyhow::Error {
+    anyhow!("Arrow column '{}': {} is not {}", column, value, expected)
+}
+
+/// Builds the array of one column from its values, one per row.
+fn build_column(kind: &ColumnKind, column: &str, values: &[Option<&Value>]) -> Result<ArrayRef> {
+    Ok(match kind {
+        ColumnKind::Int64 => {
+            let mut b = Int64Builder::with_capacity(values.len());
+            for v in values {
+                match v {
+                    None => b.append_null(),
+                    Some(v) => b.append_value(v.as_i64().ok_or_else(|| mismatch(column, v, "a 64-bit integer"))?),
+                }
+            }
+            Arc::new(b.finish())
+        }
+        ColumnKind::Float64 => {
+            let mut b = Float64Builder::with_capacity(values.len());
+            for v in values {
+                let real = match v {
+                    None => None,
+                    Some(Value::String(s)) if matches!(s.as_str(), "NaN" | "Infinity" | "-Infinity") => s.parse().ok(),
+                    Some(v) => Some(v.as_f64().ok_or_else(|| mismatch(column, v, "a REAL"))?),
+                };
+                b.append_option(real);
+            }
+            Arc::new(b.finish())
+        }
+        ColumnKind::Boolean => {
+            let mut b = BooleanBuilder::with_capacity(values.len());
+            for v in values {
+                let flag = match v {
+                    None => None,
+                    Some(v) => {
+                        let bytes = v.as_str().and_then(hex_bytes).filter(|b| !b.is_empty());
+                        Some(bytes.ok_or_else(|| mismatch(column, v, "a BOOLEAN"))?.iter().any(|&b| b != 0))
+                    }
+                };
+                b.append_option(flag);
+            }
+            Arc::new(b.finish())
+        }
+        ColumnKind::Null => Arc::new(NullArray::new(values.len())),
+        ColumnKind::Binary => {
+            let mut b = BinaryBuilder::new();
+            for v in values {
+                match v {
+                    None => b.append_null(),
+                    Some(v) => b.append_value(v.as_str().and_then(hex_bytes).ok_or_else(|| mismatch(column, v, "hex"))?),
+                }
+            }
+            Arc::new(b.finish())
+        }
+        ColumnKind::Utf8 => {
+            let mut b = StringBuilder::new();
+            for v in values {
+                match v {
+                    None => b.append_null(),
+                    Some(Value::String(s)) => b.append_value(s),
+                    Some(v) => b.append_value(v.to_string()),
+                }
+            }
+            Arc::new(b.finish())
+        }
+        ColumnKind::HexText => {
+            let mut b = StringBuilder::new();
+            for v in values {
+                match v {
+                    None => b.append_null(),
+                    Some(v) => {
+                        let bytes = v.as_str().and_then(hex_bytes).ok_or_else(|| mismatch(column, v, "hex"))?;
+                        b.append_value(String::from_utf8_lossy(&bytes));
+                    }
+                }
+            }
+            Arc::new(b.finish())
+        }
+        ColumnKind::Struct(member_kinds) => {
+            let nulls = NullBuffer::from(values.iter().map(|v| v.is_some_and(Value::is_object)).collect::<Vec<_>>());
+            if member_kinds.is_empty() {
+                return Ok(Arc::new(StructArray::new_empty_fields(values.len(), Some(nulls))));
+            }
+            let arrays = member_kinds
+                .iter()
+                .map(|(name, kind)| build_column(kind, &format!("{}.{}", column, name), &members(values, name)))
+                .collect::<Result<Vec<ArrayRef>>>()?;
+            Arc::new(StructArray::try_new(struct_fields(member_kinds), arrays, Some(nulls))?)
+        }
+        ColumnKind::List(element) => {
+            let mut offsets = Vec::with_capacity(values.len() + 1);
+            offsets.push(0i32);
+            let mut items: Vec<Option<&Value>> = Vec::new();
+            for v in values {
+                if let Some(array) = v.and_then(Value::as_array) {
+                    // `max_array_elements` ends a long array with a `{"__truncated":true,...}` marker.
+                    let kept = array.iter().filter(|e| e.get("__truncated").is_none());
+                    items.extend(kept.map(|e| Some(e).filter(|e| !e.is_null())));
+                }
+                let len = i32::try_from(items.len()).map_err(|_| anyhow!("Arrow column '{}': list too long", column))?;
+                offsets.push(len);
+            }
+            let nulls = NullBuffer::from(values.iter().map(|v| v.is_some_and(Value::is_array)).collect::<Vec<_>>());
+            let child = if items.is_empty() {
+                new_null_array(&element.data_type(), 0)
+            } else {
+                build_column(element, &format!("{}[]", column), &items)?
+            };
+            let field = Arc::new(Field::new("item", element.data_type(), true));
+            Arc::new(ListArray::try_new(field, OffsetBuffer::new(offsets.into()), child, Some(nulls))?)
+        }
+    })
+}
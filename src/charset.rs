@@ -0,0 +1,161 @@
+//! Legacy text encodings for `--charset-field`: GSM 03.38 7-bit packed, UCS-2 and Latin-1
+//! content stored in OCTET STRING fields.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// GSM 03.38 default alphabet, septets packed LSB-first into octets (3GPP TS 23.038).
+    Gsm7,
+    /// Big-endian UCS-2 (surrogate pairs accepted, i.e. UTF-16BE).
+    Ucs2,
+    /// ISO 8859-1: every octet is the code point of the same value.
+    Latin1,
+}
+
+impl FromStr for Charset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gsm7" => Ok(Charset::Gsm7),
+            "ucs2" => Ok(Charset::Ucs2),
+            "latin1" => Ok(Charset::Latin1),
+            other => Err(anyhow!("unknown charset '{}' (expected gsm7, ucs2 or latin1)", other)),
+        }
+    }
+}
+
+impl Charset {
+    /// Decodes `data` to text; `None` if the bytes aren't valid in this charset.
+    pub fn decode(self, data: &[u8]) -> Option<String> {
+        match self {
+            Charset::Gsm7 => decode_gsm7(data),
+            Charset::Ucs2 => decode_ucs2(data),
+            Charset::Latin1 => Some(data.iter().map(|&b| b as char).collect()),
+        }
+    }
+}
+
+const GSM7_ESCAPE: u8 = 0x1B;
+
+#[rustfmt::skip]
+const GSM7_BASIC: [char; 128] = [
+    '@', '£', '$', '¥', 'è', 'é', 'ù', 'ì', 'ò', 'Ç', '\n', 'Ø', 'ø', '\r', 'Å', 'å',
+    'Δ', '_', 'Φ', 'Γ', 'Λ', 'Ω', 'Π', 'Ψ', 'Σ', 'Θ', 'Ξ', '\u{1B}', 'Æ', 'æ', 'ß', 'É',
+    ' ', '!', '"', '#', '¤', '%', '&', '\'', '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?',
+    '¡', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'Ä', 'Ö', 'Ñ', 'Ü', '§',
+    '¿', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'ä', 'ö', 'ñ', 'ü', 'à',
+];
+
+/// Extension table entries reached through the 0x1B escape.
+fn gsm7_extension(septet: u8) -> Option<char> {
+    Some(match septet {
+        0x0A => '\u{0C}',
+        0x14 => '^',
+        0x28 => '{',
+        0x29 => '}',
+        0x2F => '\\',
+        0x3C => '[',
+        0x3D => '~',
+        0x3E => ']',
+        0x40 => '|',
+        0x65 => '€',
+        _ => return None,
+    })
+}
+
+/// Unpacks 7-bit septets stored LSB-first across octets.
+fn unpack_septets(data: &[u8]) -> Vec<u8> {
+    let count = data.len() * 8 / 7;
+    let mut septets = Vec::with_capacity(count);
+    for i in 0..count {
+        let bit = i * 7;
+        let (byte, shift) = (bit / 8, bit % 8);
+        let mut v = (data[byte] >> shift) as u16;
+        if shift > 1 {
+            v |= (data.get(byte + 1).copied().unwrap_or(0) as u16) << (8 - shift);
+        }
+        septets.push((v & 0x7F) as u8);
+    }
+    // 7 characters fill 7 octets with one spare septet of padding.
+    if data.len().is_multiple_of(7) && septets.last() == Some(&0) {
+        septets.pop();
+    }
+    septets
+}
+
+fn decode_gsm7(data: &[u8]) -> Option<String> {
+    if data.is_empty() {
+        return Some(String::new());
+    }
+    let septets = unpack_septets(data);
+    let mut out = String::with_capacity(septets.len());
+    let mut it = septets.into_iter();
+    while let Some(s) = it.next() {
+        if s == GSM7_ESCAPE {
+            out.push(gsm7_extension(it.next()?)?);
+        } else {
+            out.push(GSM7_BASIC[s as usize]);
+        }
+    }
+    Some(out)
+}
+
+fn decode_ucs2(data: &[u8]) -> Option<String> {
+    if !data.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = data.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]]));
+    char::decode_utf16(units).collect::<Result<String, _>>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gsm7_unpacks_septets_and_escapes() {
+        assert_eq!(Charset::Gsm7.decode(&[0xE8, 0x32, 0x9B, 0xFD, 0x06]).as_deref(), Some("hello"));
+        // "10€": the euro sign is the 0x1B escape followed by 0x65.
+        assert_eq!(Charset::Gsm7.decode(&[0x31, 0xD8, 0xA6, 0x0C]).as_deref(), Some("10€"));
+        assert_eq!(Charset::Gsm7.decode(&[0x1B, 0x54, 0x78, 0x93, 0x02]).as_deref(), Some("{a}"));
+        // Seven characters fill seven octets; the spare septet is padding, not '@'.
+        assert_eq!(Charset::Gsm7.decode(&[0x61, 0xF1, 0x98, 0x5C, 0x36, 0x9F, 0x01]).as_deref(), Some("abcdefg"));
+        assert_eq!(Charset::Gsm7.decode(&[0x01]).as_deref(), Some("£"));
+        assert_eq!(Charset::Gsm7.decode(&[]).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn gsm7_rejects_a_bad_escape() {
+        // Escape as the last septet, and escape followed by a septet the extension table lacks.
+        assert_eq!(Charset::Gsm7.decode(&[0x1B]), None);
+        assert_eq!(Charset::Gsm7.decode(&[0x9B, 0x20]), None);
+    }
+
+    #[test]
+    fn ucs2_decodes_big_endian_units() {
+        assert_eq!(Charset::Ucs2.decode(&[0x00, 0x41, 0x04, 0x10]).as_deref(), Some("AА"));
+        assert_eq!(Charset::Ucs2.decode(&[0xD8, 0x3D, 0xDE, 0x00]).as_deref(), Some("😀"));
+        assert_eq!(Charset::Ucs2.decode(&[0x00, 0x41, 0x00]), None);
+        assert_eq!(Charset::Ucs2.decode(&[0xD8, 0x00]), None);
+    }
+
+    #[test]
+    fn latin1_maps_every_octet() {
+        assert_eq!(Charset::Latin1.decode(&[0x63, 0x61, 0x66, 0xE9]).as_deref(), Some("café"));
+        assert_eq!(Charset::Latin1.decode(&[0xFF, 0xA3]).as_deref(), Some("ÿ£"));
+    }
+
+    #[test]
+    fn charset_names_parse_case_insensitively() {
+        assert_eq!("GSM7".parse::<Charset>().unwrap(), Charset::Gsm7);
+        assert_eq!("ucs2".parse::<Charset>().unwrap(), Charset::Ucs2);
+        assert!("utf8".parse::<Charset>().is_err());
+    }
+}
@@ -0,0 +1,537 @@
+//! `--generate-rust`: turns a parsed schema into Rust source with one concrete type per schema
+//! type and a `decode(&[u8])` for each, so hot paths can skip the runtime HashMap lookups.
+//!
+//! * `SEQUENCE` / `SET` become structs; `OPTIONAL` / `DEFAULT` fields are `Option`s, a missing
+//!   mandatory field is a decode error.
+//! * `CHOICE` becomes an enum with one variant per alternative.
+//! * `SEQUENCE OF` / `SET OF` become `Vec`s.
+//! * A field or alternative that leads back to a type containing it (`next [2] Rec OPTIONAL`
+//!   inside `Rec`) is a `Box`, so recursive types have a finite size.
+//! * `INTEGER` / `ENUMERATED` are `i64`, `BOOLEAN` is `bool`, `NULL` is `()`, the character
+//!   string types are `String`; everything else keeps its content octets as `Vec<u8>`.
+//!
+//! Tags are read the same way the dynamic decoder reads them: a tagged field holds its type's
+//! content directly, except CHOICE-typed fields, whose tag wraps the chosen alternative.
+//! The generated file is self-contained (it carries a small TLV reader in `mod der`, with
+//! indefinite-length support), so it can be `include!`d from a build script's `OUT_DIR`
+//! without depending on this crate.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use anyhow::Result;
+
+use crate::{is_synth_choice_tag, Asn1Schema, FieldSpec, TagKey};
+
+/// Runtime support emitted verbatim at the top of every generated file.
+const DER_RUNTIME: &str = r#"pub mod der {
+    use std::fmt;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DecodeError(pub String);
+
+    impl fmt::Display for DecodeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&self.0)
+        }
+    }
+
+    impl std::error::Error for DecodeError {}
+
+    pub type Result<T> = std::result::Result<T, DecodeError>;
+
+    pub fn error<T>(msg: impl Into<String>) -> Result<T> {
+        Err(DecodeError(msg.into()))
+    }
+
+    pub struct Tlv<'a> {
+        pub class: u8,
+        pub tag: u32,
+        pub value: &'a [u8],
+    }
+
+    /// Reads the TLV at `*off` and advances past it. An indefinite length (constructed only)
+    /// runs to the matching end-of-contents octets, which `value` leaves out.
+    pub fn read<'a>(data: &'a [u8], off: &mut usize) -> Result<Tlv<'a>> {
+        let at = *off;
+        let (class, constructed, tag, len, pos) = header(data, at)?;
+        let (end, next) = match len {
+            Some(len) => {
+                let end = pos.checked_add(len).filter(|&end| end <= data.len()).ok_or_else(|| truncated(at))?;
+                (end, end)
+            }
+            None if constructed => {
+                let next = eoc_end(data, pos)
+                    .ok_or_else(|| DecodeError(format!("no end-of-contents for the TLV at offset {}", at)))?;
+                (next - 2, next)
+            }
+            None => return error(format!("indefinite length on a primitive TLV at offset {}", at)),
+        };
+        *off = next;
+        Ok(Tlv { class, tag, value: &data[pos..end] })
+    }
+
+    fn truncated(at: usize) -> DecodeError {
+        DecodeError(format!("truncated TLV at offset {}", at))
+    }
+
+    /// Class, constructed bit, tag number, length (`None` if indefinite) and content offset of
+    /// the header at `at`.
+    fn header(data: &[u8], at: usize) -> Result<(u8, bool, u32, Option<usize>, usize)> {
+        let mut pos = at;
+        let first = *data.get(pos).ok_or_else(|| truncated(at))?;
+        pos += 1;
+        let mut tag = (first & 0x1F) as u32;
+        if tag == 0x1F {
+            tag = 0;
+            loop {
+                let b = *data.get(pos).ok_or_else(|| truncated(at))?;
+                pos += 1;
+                tag = tag.checked_mul(128).ok_or_else(|| DecodeError(format!("tag too large at offset {}", at)))?
+                    | (b & 0x7F) as u32;
+                if b & 0x80 == 0 {
+                    break;
+                }
+            }
+        }
+        let len_byte = *data.get(pos).ok_or_else(|| truncated(at))?;
+        pos += 1;
+        let len = if len_byte == 0x80 {
+            None
+        } else if len_byte & 0x80 == 0 {
+            Some(len_byte as usize)
+        } else {
+            let n = (len_byte & 0x7F) as usize;
+            if n == 0 || n > std::mem::size_of::<usize>() {
+                return error(format!("unsupported length form at offset {}", at));
+            }
+            let bytes = data.get(pos..pos + n).ok_or_else(|| truncated(at))?;
+            pos += n;
+            Some(bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize))
+        };
+        Ok((first >> 6, first & 0x20 != 0, tag, len, pos))
+    }
+
+    /// Offset just past the end-of-contents octets closing the indefinite-length content that
+    /// starts at `off`. A port of the decoder's `find_eoc` (this file can't depend on the
+    /// crate): it counts nesting levels instead of recursing, so deep input can't overflow the
+    /// stack.
+    fn eoc_end(data: &[u8], mut off: usize) -> Option<usize> {
+        let mut depth = 1usize;
+        while off + 1 < data.len() {
+            if data[off] == 0 && data[off + 1] == 0 {
+                depth -= 1;
+                off += 2;
+                if depth == 0 {
+                    return Some(off);
+                }
+                continue;
+            }
+            let (_, constructed, _, len, content) = header(data, off).ok()?;
+            match len {
+                None if constructed => {
+                    depth += 1;
+                    off = content;
+                }
+                None => return None,
+                Some(len) => off = content.checked_add(len).filter(|&end| end <= data.len())?,
+            }
+        }
+        None
+    }
+
+    /// The single TLV inside an explicit tag.
+    pub fn inner<'a>(t: &Tlv<'a>) -> Result<Tlv<'a>> {
+        let mut off = 0;
+        read(t.value, &mut off)
+    }
+
+    pub fn each<'a, T>(content: &'a [u8], mut f: impl FnMut(&Tlv<'a>) -> Result<T>) -> Result<Vec<T>> {
+        let mut out = Vec::new();
+        let mut off = 0;
+        while off < content.len() {
+            out.push(f(&read(content, &mut off)?)?);
+        }
+        Ok(out)
+    }
+
+    pub fn int(v: &[u8]) -> Result<i64> {
+        if v.is_empty() || v.len() > 8 {
+            return error(format!("INTEGER of {} bytes does not fit in i64", v.len()));
+        }
+        let init = if v[0] & 0x80 != 0 { -1i64 } else { 0 };
+        Ok(v.iter().fold(init, |acc, &b| (acc << 8) | b as i64))
+    }
+
+    pub fn boolean(v: &[u8]) -> Result<bool> {
+        match v {
+            [b] => Ok(*b != 0),
+            _ => error("BOOLEAN content must be one byte"),
+        }
+    }
+
+    pub fn text(v: &[u8]) -> String {
+        String::from_utf8_lossy(v).into_owned()
+    }
+
+    pub fn missing<T>(type_name: &str, field: &str) -> Result<T> {
+        error(format!("{}: missing mandatory field {}", type_name, field))
+    }
+}
+"#;
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do",
+    "final", "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// `List-Of-Traffic` / `gprsRecord` => `ListOfTraffic` / `GprsRecord`.
+fn type_ident(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for part in name.split(|c: char| !c.is_ascii_alphanumeric()).filter(|p| !p.is_empty()) {
+        let mut chars = part.chars();
+        if let Some(c) = chars.next() {
+            out.push(c.to_ascii_uppercase());
+            out.extend(chars);
+        }
+    }
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, 'T');
+    }
+    out
+}
+
+/// `servedIMSI` / `list-Of-Volumes` => `served_imsi` / `list_of_volumes`.
+fn field_ident(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    let chars: Vec<char> = name.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !out.ends_with('_') {
+                out.push('_');
+            }
+            continue;
+        }
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if (prev.is_ascii_lowercase() || prev.is_ascii_digit() || (prev.is_ascii_uppercase() && next_lower))
+                && !out.ends_with('_')
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    let out = out.trim_matches('_').to_string();
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("f_{}", out)
+    } else if RUST_KEYWORDS.contains(&out.as_str()) {
+        format!("r#{}", out)
+    } else {
+        out
+    }
+}
+
+struct Gen<'s> {
+    schema: &'s Asn1Schema,
+    // (type, field or alternative) references that close a cycle of SEQUENCE / SET / CHOICE
+    // types; they are boxed so the generated types have a finite size.
+    boxed: HashSet<(&'s str, &'s str)>,
+}
+
+impl<'s> Gen<'s> {
+    fn new(schema: &'s Asn1Schema) -> Self {
+        let mut gen = Gen { schema, boxed: HashSet::new() };
+        gen.boxed = gen.back_edges();
+        gen
+    }
+
+    /// The members of `name` held inline: (member name, SEQUENCE / SET / CHOICE type). A
+    /// `SEQUENCE OF` member is a `Vec`, which already adds the indirection.
+    fn inline_refs(&self, name: &str) -> Vec<(&'s str, &'s str)> {
+        let schema = self.schema;
+        let target = |t: &'s str| {
+            let rt = schema.resolve_alias(t);
+            (self.is_struct(rt) || schema.choices.contains_key(rt)).then_some(rt)
+        };
+        let mut refs: Vec<(&'s str, &'s str)> = Vec::new();
+        if let Some(fields) = schema.sequences.get(name).or_else(|| schema.sets.get(name)) {
+            let fields = fields.values().filter(|f| !(f.is_sequence_of || f.is_set_of));
+            refs.extend(fields.filter_map(|f| Some((f.name.as_str(), target(&f.field_type)?))));
+        } else if let Some(alts) = schema.choices.get(name) {
+            refs.extend(alts.values().filter_map(|(alt, ty)| Some((alt.as_str(), target(ty)?))));
+        }
+        refs.sort_unstable();
+        refs
+    }
+
+    /// Back edges of a depth-first walk over `inline_refs`, in name order so the output is
+    /// stable. Boxing them leaves no cycle of inline members.
+    fn back_edges(&self) -> HashSet<(&'s str, &'s str)> {
+        fn walk<'s>(
+            gen: &Gen<'s>,
+            name: &'s str,
+            on_stack: &mut HashMap<&'s str, bool>,
+            out: &mut HashSet<(&'s str, &'s str)>,
+        ) {
+            on_stack.insert(name, true);
+            for (member, target) in gen.inline_refs(name) {
+                match on_stack.get(target) {
+                    Some(true) => {
+                        out.insert((name, member));
+                    }
+                    Some(false) => {}
+                    None => walk(gen, target, on_stack, out),
+                }
+            }
+            on_stack.insert(name, false);
+        }
+
+        let schema = self.schema;
+        let mut names: Vec<&'s str> =
+            schema.sequences.keys().chain(schema.sets.keys()).chain(schema.choices.keys()).map(String::as_str).collect();
+        names.sort_unstable();
+        let mut on_stack = HashMap::new();
+        let mut out = HashSet::new();
+        for name in names {
+            if !on_stack.contains_key(name) {
+                walk(self, name, &mut on_stack, &mut out);
+            }
+        }
+        out
+    }
+
+    /// `ty`, boxed when `member` of `owner` closes a cycle.
+    fn boxed_type(&self, owner: &str, member: &str, ty: String) -> String {
+        if self.boxed.contains(&(owner, member)) {
+            format!("Box<{}>", ty)
+        } else {
+            ty
+        }
+    }
+
+    /// `expr` to go with [`Gen::boxed_type`].
+    fn boxed_expr(&self, owner: &str, member: &str, expr: String) -> String {
+        if self.boxed.contains(&(owner, member)) {
+            format!("Box::new({})", expr)
+        } else {
+            expr
+        }
+    }
+
+    fn is_struct(&self, rt: &str) -> bool {
+        self.schema.sequences.contains_key(rt) || self.schema.sets.contains_key(rt)
+    }
+
+    fn list_element(&self, rt: &str) -> Option<&'s str> {
+        self.schema.seq_of_types.get(rt).or_else(|| self.schema.set_of_types.get(rt)).map(String::as_str)
+    }
+
+    fn rust_type(&self, t: &str) -> String {
+        let rt = self.schema.resolve_alias(t);
+        if self.is_struct(rt) || self.schema.choices.contains_key(rt) {
+            return type_ident(rt);
+        }
+        if let Some(elem) = self.list_element(rt) {
+            return format!("Vec<{}>", self.rust_type(elem));
+        }
+        match self.schema.primitive_kind(rt) {
+            "INTEGER" | "ENUMERATED" => "i64".into(),
+            "BOOLEAN" => "bool".into(),
+            "NULL" => "()".into(),
+            "IA5String" | "UTF8String" | "VisibleString" | "GraphicString" | "PrintableString" | "NumericString" => {
+                "String".into()
+            }
+            _ => "Vec<u8>".into(),
+        }
+    }
+
+    /// Expression decoding `t`, a TLV whose tag is `t`'s own or a field / alternative tag.
+    /// `wrapped`: the tag is a field or alternative tag, which wraps a CHOICE explicitly.
+    fn decode_expr(&self, t: &str, tlv: &str, wrapped: bool) -> String {
+        let rt = self.schema.resolve_alias(t);
+        if self.is_struct(rt) {
+            return format!("{}::from_content({}.value)?", type_ident(rt), tlv);
+        }
+        if self.schema.choices.contains_key(rt) {
+            return if wrapped {
+                format!("{}::from_tlv(&der::inner({})?)?", type_ident(rt), tlv)
+            } else {
+                format!("{}::from_tlv({})?", type_ident(rt), tlv)
+            };
+        }
+        if let Some(elem) = self.list_element(rt) {
+            return self.list_expr(elem, tlv);
+        }
+        match self.schema.primitive_kind(rt) {
+            "INTEGER" | "ENUMERATED" => format!("der::int({}.value)?", tlv),
+            "BOOLEAN" => format!("der::boolean({}.value)?", tlv),
+            "NULL" => "()".into(),
+            "IA5String" | "UTF8String" | "VisibleString" | "GraphicString" | "PrintableString" | "NumericString" => {
+                format!("der::text({}.value)", tlv)
+            }
+            _ => format!("{}.value.to_vec()", tlv),
+        }
+    }
+
+    fn list_expr(&self, elem: &str, tlv: &str) -> String {
+        format!("der::each({}.value, |e| Ok({}))?", tlv, self.decode_expr(elem, "e", false))
+    }
+
+    fn field_expr(&self, f: &FieldSpec) -> String {
+        if f.is_sequence_of || f.is_set_of {
+            self.list_expr(&f.field_type, "t")
+        } else {
+            self.decode_expr(&f.field_type, "t", true)
+        }
+    }
+
+    fn field_type(&self, f: &FieldSpec) -> String {
+        if f.is_sequence_of || f.is_set_of {
+            format!("Vec<{}>", self.rust_type(&f.field_type))
+        } else {
+            self.rust_type(&f.field_type)
+        }
+    }
+
+    fn write_struct(&self, out: &mut String, name: &str, fields: &std::collections::HashMap<TagKey, FieldSpec>) {
+        let ident = type_ident(name);
+        let mut fields: Vec<(&TagKey, &FieldSpec)> = fields.iter().collect();
+        fields.sort_by_key(|(k, f)| (f.order, **k));
+        let optional = |f: &FieldSpec| f.optional || f.has_default;
+
+        let _ = writeln!(out, "/// `{}`", name);
+        let _ = writeln!(out, "#[derive(Debug, Clone, PartialEq)]");
+        let _ = writeln!(out, "pub struct {} {{", ident);
+        for (_, f) in &fields {
+            let ty = self.boxed_type(name, &f.name, self.field_type(f));
+            if optional(f) {
+                let _ = writeln!(out, "    pub {}: Option<{}>,", field_ident(&f.name), ty);
+            } else {
+                let _ = writeln!(out, "    pub {}: {},", field_ident(&f.name), ty);
+            }
+        }
+        let _ = writeln!(out, "}}\n");
+
+        let _ = writeln!(out, "impl {} {{", ident);
+        let _ = writeln!(out, "    pub fn decode(data: &[u8]) -> der::Result<Self> {{");
+        let _ = writeln!(out, "        let mut off = 0;");
+        let _ = writeln!(out, "        Self::from_content(der::read(data, &mut off)?.value)");
+        let _ = writeln!(out, "    }}\n");
+        let _ = writeln!(out, "    pub fn from_content(content: &[u8]) -> der::Result<Self> {{");
+        for (i, _) in fields.iter().enumerate() {
+            let _ = writeln!(out, "        let mut f{} = None;", i);
+        }
+        let _ = writeln!(out, "        let mut off = 0;");
+        let _ = writeln!(out, "        while off < content.len() {{");
+        let _ = writeln!(out, "            let t = &der::read(content, &mut off)?;");
+        let _ = writeln!(out, "            match (t.class, t.tag) {{");
+        for (i, (k, f)) in fields.iter().enumerate() {
+            let expr = self.boxed_expr(name, &f.name, self.field_expr(f));
+            let _ = writeln!(out, "                ({}, {}) => f{} = Some({}),", k.0, k.1, i, expr);
+        }
+        let _ = writeln!(out, "                _ => {{}}");
+        let _ = writeln!(out, "            }}");
+        let _ = writeln!(out, "        }}");
+        let _ = writeln!(out, "        Ok(Self {{");
+        for (i, (_, f)) in fields.iter().enumerate() {
+            if optional(f) {
+                let _ = writeln!(out, "            {}: f{},", field_ident(&f.name), i);
+            } else {
+                let _ = writeln!(
+                    out,
+                    "            {}: match f{} {{ Some(v) => v, None => return der::missing({:?}, {:?}) }},",
+                    field_ident(&f.name),
+                    i,
+                    name,
+                    f.name
+                );
+            }
+        }
+        let _ = writeln!(out, "        }})");
+        let _ = writeln!(out, "    }}");
+        let _ = writeln!(out, "}}\n");
+    }
+
+    fn write_choice(&self, out: &mut String, name: &str, alts: &std::collections::HashMap<TagKey, (String, String)>) {
+        let ident = type_ident(name);
+        let mut alts: Vec<(&TagKey, &(String, String))> = alts.iter().collect();
+        alts.sort_by_key(|(k, _)| **k);
+
+        let _ = writeln!(out, "/// `{}`", name);
+        let _ = writeln!(out, "#[derive(Debug, Clone, PartialEq)]");
+        let _ = writeln!(out, "pub enum {} {{", ident);
+        for (_, (alt, ty)) in &alts {
+            let _ = writeln!(out, "    {}({}),", type_ident(alt), self.boxed_type(name, alt, self.rust_type(ty)));
+        }
+        let _ = writeln!(out, "}}\n");
+
+        let _ = writeln!(out, "impl {} {{", ident);
+        let _ = writeln!(out, "    pub fn decode(data: &[u8]) -> der::Result<Self> {{");
+        let _ = writeln!(out, "        let mut off = 0;");
+        let _ = writeln!(out, "        Self::from_tlv(&der::read(data, &mut off)?)");
+        let _ = writeln!(out, "    }}\n");
+        let _ = writeln!(out, "    pub fn from_tlv(t: &der::Tlv) -> der::Result<Self> {{");
+        let _ = writeln!(out, "        match (t.class, t.tag) {{");
+        let mut nested: Vec<(&str, &str)> = Vec::new();
+        for (k, (alt, ty)) in &alts {
+            let variant = type_ident(alt);
+            if k.0 == 3 && is_synth_choice_tag(k.1) {
+                // Untagged alternative: it carries its type's own tag, if it has one.
+                match self.schema.tag_for_type(ty) {
+                    Some((cls, tag)) => {
+                        let expr = self.boxed_expr(name, alt, self.decode_expr(ty, "t", false));
+                        let _ = writeln!(out, "            ({}, {}) => return Ok(Self::{}({})),", cls, tag, variant, expr);
+                    }
+                    None if self.schema.choices.contains_key(self.schema.resolve_alias(ty)) => nested.push((alt, ty)),
+                    None => {}
+                }
+            } else {
+                let expr = self.boxed_expr(name, alt, self.decode_expr(ty, "t", true));
+                let _ = writeln!(out, "            ({}, {}) => return Ok(Self::{}({})),", k.0, k.1, variant, expr);
+            }
+        }
+        let _ = writeln!(out, "            _ => {{}}");
+        let _ = writeln!(out, "        }}");
+        for (alt, ty) in nested {
+            let _ = writeln!(out, "        if let Ok(v) = {}::from_tlv(t) {{", type_ident(self.schema.resolve_alias(ty)));
+            let _ = writeln!(out, "            return Ok(Self::{}({}));", type_ident(alt), self.boxed_expr(name, alt, "v".into()));
+            let _ = writeln!(out, "        }}");
+        }
+        let _ = writeln!(
+            out,
+            "        der::error(format!(\"{}: no alternative for tag class {{}} number {{}}\", t.class, t.tag))",
+            name
+        );
+        let _ = writeln!(out, "    }}");
+        let _ = writeln!(out, "}}\n");
+    }
+}
+
+/// Generates the Rust source for every SEQUENCE, SET and CHOICE in `schema`.
+pub fn generate(schema: &Asn1Schema, source: &str) -> Result<String> {
+    let gen = Gen::new(schema);
+    let mut out = String::new();
+    let _ = writeln!(out, "// @generated by asn1-der-decoder --generate-rust from {}. Do not edit.", source);
+    out.push('\n');
+    out.push_str(DER_RUNTIME);
+    out.push('\n');
+
+    let mut idents: HashSet<String> = HashSet::new();
+    let mut names: Vec<&String> = schema.sequences.keys().chain(schema.sets.keys()).chain(schema.choices.keys()).collect();
+    names.sort_unstable();
+    for name in names {
+        if !idents.insert(type_ident(name)) {
+            return Err(anyhow::anyhow!("types '{}' and another one map to the same Rust name {}", name, type_ident(name)));
+        }
+        if let Some(fields) = schema.sequences.get(name).or_else(|| schema.sets.get(name)) {
+            gen.write_struct(&mut out, name, fields);
+        } else if let Some(alts) = schema.choices.get(name) {
+            gen.write_choice(&mut out, name, alts);
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,133 @@
+//! Schema coverage (`--coverage-out`): which schema types were ever decoded and which of their
+//! fields / CHOICE alternatives were ever present, across every record of every input file.
+//!
+//! Flags are preallocated per schema type and field, so the rayon workers only ever flip
+//! atomics; nothing needs merging once decoding is done.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+
+use crate::{write_json_key, Asn1Schema};
+
+pub struct Coverage {
+    types: HashMap<String, AtomicBool>,
+    // type name -> field / alternative name -> seen
+    fields: HashMap<String, HashMap<String, AtomicBool>>,
+}
+
+#[inline]
+fn mark(flag: &AtomicBool) {
+    // Check first so hot types don't keep bouncing the cache line between workers.
+    if !flag.load(Ordering::Relaxed) {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Coverage {
+    pub fn for_schema(schema: &Asn1Schema) -> Self {
+        let mut types: HashMap<String, AtomicBool> = HashMap::new();
+        let mut fields: HashMap<String, HashMap<String, AtomicBool>> = HashMap::new();
+
+        for name in schema
+            .choices
+            .keys()
+            .chain(schema.sequences.keys())
+            .chain(schema.sets.keys())
+            .chain(schema.seq_of_types.keys())
+            .chain(schema.set_of_types.keys())
+            .chain(schema.primitives.keys())
+        {
+            types.insert(name.clone(), AtomicBool::new(false));
+        }
+
+        for (name, spec) in schema.sequences.iter().chain(schema.sets.iter()) {
+            let entry = fields.entry(name.clone()).or_default();
+            for f in spec.values() {
+                entry.insert(f.name.clone(), AtomicBool::new(false));
+            }
+        }
+        for (name, alts) in &schema.choices {
+            let entry = fields.entry(name.clone()).or_default();
+            for (alt_name, _) in alts.values() {
+                entry.insert(alt_name.clone(), AtomicBool::new(false));
+            }
+        }
+
+        Self { types, fields }
+    }
+
+    #[inline]
+    pub fn note_type(&self, type_name: &str) {
+        if let Some(flag) = self.types.get(type_name) {
+            mark(flag);
+        }
+    }
+
+    #[inline]
+    pub fn note_field(&self, type_name: &str, field: &str) {
+        if let Some(flag) = self.fields.get(type_name).and_then(|m| m.get(field)) {
+            mark(flag);
+        }
+    }
+
+    /// Writes the report as JSON: overall totals, then one line per type (sorted by name).
+    pub fn write_report<W: Write>(&self, out: &mut W) -> Result<()> {
+        let seen = |f: &AtomicBool| f.load(Ordering::Relaxed);
+
+        let mut names: Vec<&String> = self.types.keys().collect();
+        names.sort_unstable();
+
+        let (types_touched, types_total, fields_touched, fields_total) = self.totals();
+
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"typesTouched\": {},", types_touched)?;
+        writeln!(out, "  \"typesTotal\": {},", types_total)?;
+        writeln!(out, "  \"fieldsTouched\": {},", fields_touched)?;
+        writeln!(out, "  \"fieldsTotal\": {},", fields_total)?;
+        writeln!(out, "  \"types\": {{")?;
+
+        for (i, name) in names.iter().enumerate() {
+            out.write_all(b"    ")?;
+            write_json_key(out, name)?;
+            write!(out, ": {{\"decoded\": {}", seen(&self.types[*name]))?;
+
+            if let Some(fields) = self.fields.get(*name) {
+                let mut uncovered: Vec<&String> = fields.iter().filter(|(_, f)| !seen(f)).map(|(n, _)| n).collect();
+                uncovered.sort_unstable();
+                write!(
+                    out,
+                    ", \"fieldsTouched\": {}, \"fieldsTotal\": {}, \"uncoveredFields\": [",
+                    fields.len() - uncovered.len(),
+                    fields.len()
+                )?;
+                for (j, field) in uncovered.iter().enumerate() {
+                    if j > 0 {
+                        out.write_all(b", ")?;
+                    }
+                    write_json_key(out, field)?;
+                }
+                out.write_all(b"]")?;
+            }
+
+            out.write_all(if i + 1 < names.len() { b"},\n" } else { b"}\n" })?;
+        }
+
+        writeln!(out, "  }}")?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+
+    /// (types touched, types total, fields touched, fields total) for the console summary.
+    pub fn totals(&self) -> (usize, usize, usize, usize) {
+        let seen = |f: &&AtomicBool| f.load(Ordering::Relaxed);
+        (
+            self.types.values().filter(seen).count(),
+            self.types.len(),
+            self.fields.values().map(|m| m.values().filter(seen).count()).sum(),
+            self.fields.values().map(HashMap::len).sum(),
+        )
+    }
+}
@@ -0,0 +1,3900 @@
+//! Schema-driven ASN.1 DER/BER decoder: parses a textual ASN.1 module into an [`Asn1Schema`]
+//! and decodes buffers of root records against it.
+//!
+//! The `asn1_der_schema_fast` binary is a thin CLI over this crate. To embed the decoder:
+//!
+//! * [`decode_buffer`] returns every record as a `serde_json::Value`;
+//! * [`DerDecoder::decode_to_writer`] writes JSON Lines straight to any `Write`, the same fast
+//!   path the CLI uses;
+//! * [`DerDecoder::decode_to_sink`] drives an [`sink::OutputSink`] for custom output;
+//! * [`DerDecoder::records`] streams records one at a time as a [`records::RecordIter`].
+
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+#[cfg(feature = "arrow")]
+pub mod arrow_batch;
+pub mod charset;
+pub mod codegen;
+pub mod coverage;
+pub mod output;
+pub mod records;
+pub mod sink;
+
+use charset::Charset;
+use coverage::Coverage;
+use sink::{JsonSink, NumberFormat, OutputSink, SinkValue, SpecialFloats, ValueEncoding};
+
+/// Decodes every `root_type` record in `data` against `schema` with default options.
+pub fn decode_buffer(schema: &Asn1Schema, root_type: &str, data: &[u8]) -> Result<Vec<serde_json::Value>> {
+    if !schema.knows_type(root_type) {
+        return Err(anyhow!("root-type '{}' not found in schema", root_type));
+    }
+    let decoder = DerDecoder::with_schema_ref(schema, DecodeOptions::default());
+    let mut out = Vec::new();
+    decoder.decode_to_writer(data, root_type, &mut out)?;
+    out.split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).context("decoder produced invalid JSON"))
+        .collect()
+}
+
+/// Byte offset of `sub` within `data`; `sub` must be a subslice of `data`.
+#[inline]
+pub fn offset_in(data: &[u8], sub: &[u8]) -> usize {
+    sub.as_ptr() as usize - data.as_ptr() as usize
+}
+
+/// (class, tag number). Lookups always use both, so `[APPLICATION 5]` and `[5]` never collide.
+pub type TagKey = (u8, u32);
+const SYNTH_CHOICE_BASE: u32 = 0xFFFF_FF00;
+pub const DEFAULT_MAX_ARRAY_ELEMENTS: usize = 1_000_000;
+pub const DEFAULT_UNWRAP_DEPTH: usize = 3;
+// Levels of schema-driven nesting decoded before a subtree is cut off.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+// UTCTime two-digit years map into [pivot, pivot + 99]: 50 => 1950, 49 => 2049.
+pub const DEFAULT_UTCTIME_PIVOT: u16 = 1950;
+pub const DEFAULT_IP_NAME_PATTERNS: &[&str] = &["address", "addr", "ip"];
+// Nesting beyond this in a `--generic-unknown` dump is left as hex.
+const MAX_GENERIC_DEPTH: usize = 64;
+// Constructed OCTET STRING segments nested deeper than this are not reassembled.
+const MAX_SEGMENT_DEPTH: usize = 8;
+
+#[inline]
+fn is_synth_choice_tag(t: u32) -> bool {
+    t >= SYNTH_CHOICE_BASE
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSpec {
+    pub name: String,
+    pub field_type: String,
+    pub optional: bool,
+    pub has_default: bool,
+    // Subtype constraint text without the parentheses, e.g. "0..65535"
+    pub constraint: Option<String>,
+    // SIZE constraint of the field, or of its type when the field has none
+    pub size: Option<SizeConstraint>,
+    // Value range of an INTEGER field, or of its type when the field has none
+    pub range: Option<ValueConstraint>,
+    // How the field's own tag relates to its type's; untagged fields are `Implicit`
+    pub tagging: Tagging,
+    pub is_sequence_of: bool,
+    pub is_set_of: bool,
+    // Declaration position: component index << 16, plus the source's index for COMPONENTS OF fields
+    pub order: u32,
+}
+
+/// A `SIZE(...)` constraint as inclusive length ranges; an upper bound of `None` is `MAX`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeConstraint {
+    pub ranges: Vec<(u64, Option<u64>)>,
+}
+
+impl SizeConstraint {
+    /// Reads `SIZE(4)`, `SIZE(1..9)`, `SIZE(1..MAX)` or `SIZE(2 | 4..6)` out of constraint text.
+    /// Extensible constraints (`SIZE(1..9, ...)`) give `None`, since any length is legal for them.
+    pub fn parse(constraint: &str) -> Option<Self> {
+        let at = constraint.find("SIZE")?;
+        let rest = constraint[at + 4..].trim_start().strip_prefix('(')?;
+        let inner = &rest[..rest.find(')').unwrap_or(rest.len())];
+        if inner.contains("...") {
+            return None;
+        }
+        let ranges = inner
+            .split('|')
+            .map(|alt| {
+                let (lo, hi) = alt.split_once("..").unwrap_or((alt, alt));
+                let hi = hi.trim();
+                let hi = if hi == "MAX" { None } else { Some(hi.parse().ok()?) };
+                Some((lo.trim().parse().ok()?, hi))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(SizeConstraint { ranges })
+    }
+
+    pub fn allows(&self, len: u64) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| len >= lo && hi.is_none_or(|hi| len <= hi))
+    }
+}
+
+impl fmt::Display for SizeConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        for (i, (lo, hi)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" | ")?;
+            }
+            match hi {
+                Some(hi) if hi == lo => write!(f, "{}", lo)?,
+                Some(hi) => write!(f, "{}..{}", lo, hi)?,
+                None => write!(f, "{}..MAX", lo)?,
+            }
+        }
+        f.write_str(")")
+    }
+}
+
+/// A value-range constraint such as `(0..255)`, `(5)` or `(MIN..-1 | 1..MAX)` as inclusive
+/// ranges; a bound of `None` is `MIN` / `MAX`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueConstraint {
+    pub ranges: Vec<(Option<i64>, Option<i64>)>,
+}
+
+impl ValueConstraint {
+    /// `None` for SIZE constraints, extensible ranges and anything that isn't numbers, `MIN` and `MAX`.
+    pub fn parse(constraint: &str) -> Option<Self> {
+        if constraint.contains("SIZE") || constraint.contains("...") {
+            return None;
+        }
+        let bound = |s: &str, open: &str| -> Option<Option<i64>> {
+            let s = s.trim();
+            if s == open {
+                Some(None)
+            } else {
+                s.parse().ok().map(Some)
+            }
+        };
+        let ranges = constraint
+            .split('|')
+            .map(|alt| match alt.split_once("..") {
+                Some((lo, hi)) => Some((bound(lo, "MIN")?, bound(hi, "MAX")?)),
+                None => {
+                    let v = alt.trim().parse().ok()?;
+                    Some((Some(v), Some(v)))
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(ValueConstraint { ranges })
+    }
+
+    /// Checks INTEGER content bytes. Content too long for 64 bits lies beyond every finite bound,
+    /// so it only passes a range left open on its side.
+    pub fn allows_content(&self, data: &[u8], unsigned: bool) -> bool {
+        let value = if unsigned {
+            decode_uint_u64(data).map(i128::from)
+        } else {
+            decode_int_i64(data).map(i128::from)
+        };
+        match value {
+            Some(v) => self.ranges.iter().any(|&(lo, hi)| {
+                lo.is_none_or(|lo| v >= lo as i128) && hi.is_none_or(|hi| v <= hi as i128)
+            }),
+            None if !unsigned && data[0] & 0x80 != 0 => self.ranges.iter().any(|(lo, _)| lo.is_none()),
+            None => self.ranges.iter().any(|(_, hi)| hi.is_none()),
+        }
+    }
+}
+
+impl fmt::Display for ValueConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        for (i, (lo, hi)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" | ")?;
+            }
+            match (lo, hi) {
+                (Some(lo), Some(hi)) if lo == hi => write!(f, "{}", lo)?,
+                _ => {
+                    match lo {
+                        Some(lo) => write!(f, "{}..", lo)?,
+                        None => f.write_str("MIN..")?,
+                    }
+                    match hi {
+                        Some(hi) => write!(f, "{}", hi)?,
+                        None => f.write_str("MAX")?,
+                    }
+                }
+            }
+        }
+        f.write_str(")")
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Asn1Schema {
+    pub choices: HashMap<String, HashMap<TagKey, (String, String)>>,
+    pub sequences: HashMap<String, HashMap<TagKey, FieldSpec>>,
+    pub sets: HashMap<String, HashMap<TagKey, FieldSpec>>,
+
+    pub seq_of_types: HashMap<String, String>,
+    pub set_of_types: HashMap<String, String>,
+
+    pub primitives: HashMap<String, String>,
+    pub aliases: HashMap<String, String>,
+
+    pub type_outer_tag: HashMap<String, TagKey>,
+    // type name -> subtype constraint text, e.g. "0..MAX"
+    pub constraints: HashMap<String, String>,
+
+    // From the module header `DEFINITIONS <mode> TAGS ::=`
+    pub tag_default: TagDefault,
+
+    // `--universal-overrides`: type kind or name -> universal tag number used instead of X.680's
+    pub universal_overrides: HashMap<String, u32>,
+
+    // ENUMERATED / INTEGER named values: type name -> value -> name
+    pub named_ints: HashMap<String, HashMap<i64, String>>,
+
+    // BIT STRING named bits: type name -> bit number -> name
+    pub bitstrings: HashMap<String, HashMap<u32, String>>,
+
+    // (type, tag, component kept, component dropped) for each tag claimed twice in one SEQUENCE / SET / CHOICE
+    pub duplicate_tags: Vec<(String, TagKey, String, String)>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TagDefault {
+    #[default]
+    Explicit,
+    Implicit,
+    Automatic,
+}
+
+/// Whether a component's tag replaces its type's tag (IMPLICIT) or wraps the complete
+/// encoding of the type (EXPLICIT). From the keyword, else the module's tagging default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tagging {
+    #[default]
+    Implicit,
+    Explicit,
+}
+
+/// ASN.1 notation of a tag: `[3]`, `[APPLICATION 5]`, `[UNIVERSAL 4]`, `[PRIVATE 1]`.
+pub fn tag_notation((class, num): TagKey) -> String {
+    match class {
+        0 => format!("[UNIVERSAL {}]", num),
+        1 => format!("[APPLICATION {}]", num),
+        3 => format!("[PRIVATE {}]", num),
+        _ => format!("[{}]", num),
+    }
+}
+
+#[inline]
+fn tag_class_from_word(word: Option<&str>) -> u8 {
+    match word.map(|s| s.to_ascii_uppercase()) {
+        Some(w) if w == "APPLICATION" => 1,
+        Some(w) if w == "UNIVERSAL" => 0,
+        Some(w) if w == "PRIVATE" => 3,
+        Some(w) if w == "CONTEXT" || w == "CONTEXT-SPECIFIC" || w == "CONTEXTSPECIFIC" => 2,
+        None => 2, // Default to Context-Specific if only a number is given [x]
+        _ => 2,
+    }
+}
+
+// Schema-parsing regexes are compiled once per process and shared by every `Asn1Schema::parse` call.
+type SchemaRegex = std::result::Result<Regex, regex::Error>;
+
+static BLOCK_COMMENT_RE: Lazy<SchemaRegex> = Lazy::new(|| Regex::new(r"(?s)/\*.*?\*/"));
+static SNACC_DIRECTIVE_RE: Lazy<SchemaRegex> = Lazy::new(|| Regex::new(r"(?is)--\s*snacc\b.*?--"));
+static COMMENT_STRIP_RE: Lazy<SchemaRegex> = Lazy::new(|| Regex::new(r"(?m)--.*?$"));
+// Handles (IMPLICIT|EXPLICIT) and any identifier type
+static TYPE_ASSIGN_RE: Lazy<SchemaRegex> = Lazy::new(|| {
+    Regex::new(
+        r"(?s)([\w-]+)\s*::=\s*(?:\[\s*(?:(APPLICATION|UNIVERSAL|PRIVATE|CONTEXT|CONTEXT-SPECIFIC)\s+)?(\d+)\s*\]\s*)?(?:IMPLICIT|EXPLICIT)?\s*(CHOICE|SEQUENCE|SET|ENUMERATED|INTEGER|OCTET STRING|BIT STRING|IA5String|UTF8String|NumericString|PrintableString|BMPString|UniversalString|BOOLEAN|NULL|TBCD-STRING|OBJECT IDENTIFIER|[\w-]+)\s*(?:OF\s+([\w-]+))?\s*(?:\(([^)]*)\))?\s*(\{.*?\})?",
+    )
+});
+static ALIAS_RE: Lazy<SchemaRegex> = Lazy::new(|| Regex::new(r"(?m)^\s*([\w-]+)\s*::=\s*([\w-]+)\s*$"));
+// Allows 0 whitespace before '[' e.g. "sIP-URI[0]"
+static CHOICE_TAGGED_RE: Lazy<SchemaRegex> = Lazy::new(|| {
+    Regex::new(
+        r"([\w-]+)\s*\[\s*(?:(APPLICATION|UNIVERSAL|PRIVATE|CONTEXT|CONTEXT-SPECIFIC)\s+)?(\d+)\s*\]\s*(?:IMPLICIT\s+|EXPLICIT\s+)?(OCTET\s+STRING|BIT\s+STRING|OBJECT\s+IDENTIFIER|[\w-]+)",
+    )
+});
+static CHOICE_UNTAGGED_RE: Lazy<SchemaRegex> =
+    Lazy::new(|| Regex::new(r"([\w-]+)\s+(OCTET\s+STRING|BIT\s+STRING|OBJECT\s+IDENTIFIER|[\w-]+)"));
+// Handles optional IMPLICIT/EXPLICIT and tags
+static FIELD_RE: Lazy<SchemaRegex> = Lazy::new(|| {
+    Regex::new(
+        r"(?m)^\s*([\w-]+)\s*(?:\[\s*(?:(APPLICATION|UNIVERSAL|PRIVATE|CONTEXT|CONTEXT-SPECIFIC)\s+)?(\d+)\s*\])?\s*(IMPLICIT|EXPLICIT)?\s+((?:SET|SEQUENCE)\s+OF\s+[\w-]+|OCTET\s+STRING|BIT\s+STRING|OBJECT\s+IDENTIFIER|[\w-]+)\s*(?:\(((?:[^()]|\([^()]*\))*)\))?\s*(DEFAULT\s+[^,\n]+)?\s*(OPTIONAL)?",
+    )
+});
+// Module header tagging default: DEFINITIONS AUTOMATIC TAGS ::=
+static MODULE_TAGS_RE: Lazy<SchemaRegex> = Lazy::new(|| Regex::new(r"\bDEFINITIONS\s+(AUTOMATIC|IMPLICIT|EXPLICIT)\s+TAGS\b"));
+// One `name` or `name(number)` item of an ENUMERATED / INTEGER body
+static NAMED_NUMBER_RE: Lazy<SchemaRegex> =
+    Lazy::new(|| Regex::new(r"^([A-Za-z][\w-]*)\s*(?:\(\s*(-?\d+)\s*\))?$"));
+// COMPONENTS OF (simple inheritance)
+static COMPONENTS_OF_RE: Lazy<SchemaRegex> = Lazy::new(|| Regex::new(r"(?m)^\s*COMPONENTS\s+OF\s+([\w-]+)"));
+
+/// Named values of an ENUMERATED or INTEGER body. ENUMERATED items without a number take the
+/// smallest non-negative values not used by numbered items (X.680 20.3); INTEGER items must be numbered.
+fn parse_named_numbers(body: &str, enumerated: bool, named_number_re: &Regex) -> HashMap<i64, String> {
+    let items: Vec<(String, Option<i64>)> = split_components(body)
+        .into_iter()
+        .filter_map(|item| named_number_re.captures(item))
+        .map(|c| (c[1].to_string(), c.get(2).and_then(|m| m.as_str().parse().ok())))
+        .collect();
+    let mut values: HashMap<i64, String> =
+        items.iter().filter_map(|(name, v)| v.map(|v| (v, name.clone()))).collect();
+    if enumerated {
+        let mut next = 0i64;
+        for (name, _) in items.iter().filter(|(_, v)| v.is_none()) {
+            while values.contains_key(&next) {
+                next += 1;
+            }
+            values.insert(next, name.clone());
+        }
+    }
+    values
+}
+
+/// Splits a `{ a ..., b ... }` body into its top-level components.
+fn split_components(body: &str) -> Vec<&str> {
+    let body = body.trim();
+    let inner = body.strip_prefix('{').unwrap_or(body);
+    let inner = inner.strip_suffix('}').unwrap_or(inner);
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0usize;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(inner[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts
+}
+
+#[inline]
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'_'
+}
+
+/// The identifier `s` ends with, ignoring trailing whitespace (the name left of a `::=`).
+fn trailing_ident(s: &str) -> &str {
+    let s = s.trim_end();
+    &s[s.bytes().rposition(|b| !is_ident_byte(b)).map_or(0, |p| p + 1)..]
+}
+
+/// Index of the `}` closing the brace block that starts at `open`.
+fn matching_brace(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (k, b) in s.bytes().enumerate().skip(open) {
+        match b {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(k);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Moves every `SEQUENCE { ... }` / `SET { ... }` / `CHOICE { ... }` written inline in a component
+/// into its own assignment `Owner-component ::= ...` at the end of the text and refers to it by
+/// that name. Lifted bodies are scanned in turn, so deeper nesting is lifted as well.
+fn lift_inline_types(text: &str) -> String {
+    let mut src = text.to_string();
+    let mut taken: HashSet<String> =
+        src.match_indices("::=").map(|(i, _)| trailing_ident(&src[..i]).to_string()).collect();
+    let mut owner = String::new();
+    let (mut depth, mut component_start, mut i) = (0usize, 0usize, 0usize);
+    while i < src.len() {
+        let b = src.as_bytes();
+        match b[i] {
+            b'{' => {
+                if depth == 0 {
+                    component_start = i + 1;
+                }
+                depth += 1;
+            }
+            b'}' => depth = depth.saturating_sub(1),
+            b',' if depth == 1 => component_start = i + 1,
+            b':' if depth == 0 && src[i..].starts_with("::=") => owner = trailing_ident(&src[..i]).to_string(),
+            c if depth == 1 && c.is_ascii_uppercase() && !is_ident_byte(b[i - 1]) => {
+                let kw_end = b[i..].iter().position(|&c| !is_ident_byte(c)).map_or(b.len(), |p| i + p);
+                let open = kw_end + (src.len() - kw_end - src[kw_end..].trim_start().len());
+                let close = match &src[i..kw_end] {
+                    "SEQUENCE" | "SET" | "CHOICE" if b.get(open) == Some(&b'{') => matching_brace(&src, open),
+                    _ => None,
+                };
+                let Some(close) = close else {
+                    i = kw_end;
+                    continue;
+                };
+                let component = src[component_start..i]
+                    .split(|c: char| !c.is_ascii() || !is_ident_byte(c as u8))
+                    .find(|w| w.starts_with(|c: char| c.is_ascii_alphabetic()))
+                    .unwrap_or("component");
+                let base = format!("{}-{}", owner, component);
+                let mut name = base.clone();
+                let mut n = 2;
+                while !taken.insert(name.clone()) {
+                    name = format!("{}{}", base, n);
+                    n += 1;
+                }
+                // One component per line, as FIELD_RE expects
+                let body = split_components(&src[open..=close]).join(",\n");
+                let def = format!("\n{} ::= {} {{\n{}\n}}\n", name, &src[i..kw_end], body);
+                src.replace_range(i..=close, &name);
+                src.push_str(&def);
+                i += name.len();
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    src
+}
+
+#[inline]
+fn schema_regex<'a>(re: &'a Lazy<SchemaRegex>, name: &str) -> Result<&'a Regex> {
+    re.as_ref()
+        .map_err(|e| anyhow!("failed to compile {} schema regex: {}", name, e))
+}
+
+/// Schema text with block comments, SNACC directives and `--` line comments removed.
+fn strip_schema_comments(schema_text: &str) -> Result<String> {
+    let block_comment_re = schema_regex(&BLOCK_COMMENT_RE, "block comment")?;
+    let snacc_directive_re = schema_regex(&SNACC_DIRECTIVE_RE, "snacc directive")?;
+    let comment_strip_re = schema_regex(&COMMENT_STRIP_RE, "line comment")?;
+    let no_block = block_comment_re.replace_all(schema_text, " ");
+    let no_snacc = snacc_directive_re.replace_all(&no_block, " ");
+    Ok(comment_strip_re.replace_all(&no_snacc, "").into_owned())
+}
+
+impl Asn1Schema {
+    /// Parses several schema files as one: the texts are concatenated in order, so a module can
+    /// use types defined in any other. IMPORTS clauses are not resolved. A type defined in more
+    /// than one file is reported on stderr; the last definition wins.
+    pub fn parse_modules(modules: &[(String, String)], universal_overrides: HashMap<String, u32>) -> Result<Self> {
+        let type_assign_re = schema_regex(&TYPE_ASSIGN_RE, "type assignment")?;
+        let alias_re = schema_regex(&ALIAS_RE, "alias")?;
+        let mut defined_in: HashMap<String, &str> = HashMap::new();
+        let mut combined = String::new();
+        for (source, text) in modules {
+            let stripped = strip_schema_comments(text)?;
+            let mut names: Vec<&str> = type_assign_re
+                .captures_iter(&stripped)
+                .filter(|c| c[4].trim() != "BEGIN")
+                .map(|c| c.get(1).unwrap().as_str())
+                .chain(alias_re.captures_iter(&stripped).map(|c| c.get(1).unwrap().as_str()))
+                .collect();
+            names.sort_unstable();
+            names.dedup();
+            for name in names {
+                if let Some(prev) = defined_in.insert(name.to_string(), source) {
+                    eprintln!(
+                        "Warning: type '{}' is defined in both {} and {}; using the definition from {}",
+                        name, prev, source, source
+                    );
+                }
+            }
+            combined.push_str(&stripped);
+            combined.push('\n');
+        }
+        Self::parse(&combined, universal_overrides)
+    }
+
+    pub fn parse(schema_text: &str, universal_overrides: HashMap<String, u32>) -> Result<Self> {
+        let stripped = lift_inline_types(&strip_schema_comments(schema_text)?);
+
+        let type_assign_re = schema_regex(&TYPE_ASSIGN_RE, "type assignment")?;
+        let alias_re = schema_regex(&ALIAS_RE, "alias")?;
+        let choice_tagged_re = schema_regex(&CHOICE_TAGGED_RE, "tagged CHOICE alternative")?;
+        let choice_untagged_re = schema_regex(&CHOICE_UNTAGGED_RE, "untagged CHOICE alternative")?;
+        let field_re = schema_regex(&FIELD_RE, "SEQUENCE/SET field")?;
+        let components_of_re = schema_regex(&COMPONENTS_OF_RE, "COMPONENTS OF")?;
+        let named_number_re = schema_regex(&NAMED_NUMBER_RE, "named number")?;
+        let module_tags_re = schema_regex(&MODULE_TAGS_RE, "module tagging default")?;
+
+        let mut schema = Asn1Schema { universal_overrides, ..Default::default() };
+
+        // 0. Module tagging default (the first module header wins)
+        if let Some(cap) = module_tags_re.captures(&stripped) {
+            schema.tag_default = match &cap[1] {
+                "AUTOMATIC" => TagDefault::Automatic,
+                "IMPLICIT" => TagDefault::Implicit,
+                _ => TagDefault::Explicit,
+            };
+        }
+        let automatic = schema.tag_default == TagDefault::Automatic;
+
+        // 1. Parse Aliases
+        for cap in alias_re.captures_iter(&stripped) {
+            let lhs = cap.get(1).unwrap().as_str().to_string();
+            let rhs = cap.get(2).unwrap().as_str().to_string();
+            let rhs_upper = rhs.to_ascii_uppercase();
+            // Filter out keywords
+            let is_keyword = matches!(
+                rhs_upper.as_str(),
+                "CHOICE" | "SEQUENCE" | "SET" | "ENUMERATED" | "INTEGER" | "OCTET" | "BIT" 
+                | "IA5STRING" | "UTF8STRING" | "NUMERICSTRING" | "PRINTABLESTRING" | "BMPSTRING"
+                | "UNIVERSALSTRING" | "BOOLEAN" | "NULL" | "OBJECT" | "IDENTIFIER" | "BEGIN" | "END"
+                | "TIME" | "DATE" | "TIME-OF-DAY" | "DATE-TIME" | "DURATION" | "RELATIVE-OID"
+            );
+            if !is_keyword && lhs != rhs {
+                schema.aliases.insert(lhs, rhs);
+            }
+        }
+
+        let alias_defs = schema.aliases.clone();
+
+        #[derive(Clone)]
+        struct Def {
+            type_name: String,
+            type_kind: String,
+            of_type: Option<String>,
+            body: String,
+        }
+        let mut defs: Vec<Def> = Vec::new();
+
+        // 2. Parse Type Definitions
+        for caps in type_assign_re.captures_iter(&stripped) {
+            let type_name = caps.get(1).unwrap().as_str().to_string();
+            let tag_class_word = caps.get(2).map(|m| m.as_str());
+            let tag_num_opt = caps.get(3).map(|m| m.as_str());
+            let type_kind = caps.get(4).unwrap().as_str().trim().to_string();
+            let of_type = caps.get(5).map(|m| m.as_str().to_string());
+            let body = caps.get(7).map(|m| m.as_str()).unwrap_or("").to_string();
+
+            // `Module DEFINITIONS ... TAGS ::= BEGIN` also looks like an assignment
+            if type_kind == "BEGIN" {
+                continue;
+            }
+
+            // With merged modules a name can be defined twice; the later definition decides whether it's an alias.
+            let is_alias = tag_num_opt.is_none() && of_type.is_none() && caps.get(6).is_none() && body.is_empty()
+                && alias_defs.get(&type_name) == Some(&type_kind);
+            if is_alias {
+                schema.aliases.insert(type_name.clone(), type_kind.clone());
+            } else {
+                schema.aliases.remove(&type_name);
+            }
+
+            if let Some(c) = caps.get(6) {
+                schema.constraints.insert(type_name.clone(), c.as_str().trim().to_string());
+            }
+
+            if let Some(tag_num_str) = tag_num_opt {
+                if let Ok(num) = tag_num_str.parse::<u32>() {
+                    let cls = tag_class_from_word(tag_class_word);
+                    schema.type_outer_tag.insert(type_name.clone(), (cls, num));
+                }
+            }
+
+            match type_kind.as_str() {
+                "CHOICE" | "SEQUENCE" | "SET" => {}
+                kind => {
+                    schema.primitives.insert(type_name.clone(), kind.to_string());
+                    if matches!(kind, "ENUMERATED" | "INTEGER") && !body.is_empty() {
+                        let named = parse_named_numbers(&body, kind == "ENUMERATED", named_number_re);
+                        if !named.is_empty() {
+                            schema.named_ints.insert(type_name.clone(), named);
+                        }
+                    } else if kind == "BIT STRING" && !body.is_empty() {
+                        let bits: HashMap<u32, String> = parse_named_numbers(&body, false, named_number_re)
+                            .into_iter()
+                            .filter_map(|(bit, name)| Some((u32::try_from(bit).ok()?, name)))
+                            .collect();
+                        if !bits.is_empty() {
+                            schema.bitstrings.insert(type_name.clone(), bits);
+                        }
+                    }
+                }
+            }
+
+            defs.push(Def {
+                type_name,
+                type_kind,
+                of_type,
+                body,
+            });
+        }
+
+        // (target type, source type, declaration position of the COMPONENTS OF line)
+        let mut components_queue: Vec<(String, String, u32)> = Vec::new();
+
+        // 3. Process Structures
+        for d in defs {
+            match d.type_kind.as_str() {
+                "SEQUENCE" | "SET" => {
+                    let is_set = d.type_kind == "SET";
+                    if let Some(elem) = d.of_type.clone() {
+                        if is_set {
+                            schema.set_of_types.insert(d.type_name, elem);
+                        } else {
+                            schema.seq_of_types.insert(d.type_name, elem);
+                        }
+                        continue;
+                    }
+
+                    let mut fields: HashMap<TagKey, FieldSpec> = HashMap::new();
+                    let field_caps: Vec<_> = field_re
+                        .captures_iter(&d.body)
+                        .filter(|c| &c[1] != "COMPONENTS")
+                        .collect();
+                    // AUTOMATIC TAGS only applies when no component carries a tag of its own.
+                    let auto_tag = automatic && field_caps.iter().all(|c| c.get(3).is_none());
+
+                    // Declaration positions count fields and COMPONENTS OF lines in textual order.
+                    let components_at: Vec<usize> =
+                        components_of_re.find_iter(&d.body).map(|m| m.start()).collect();
+                    let position_of = |start: usize, own: usize| {
+                        let before = components_at.iter().filter(|&&at| at < start).count();
+                        ((own + before) as u32) << 16
+                    };
+
+                    for (idx, c) in field_caps.iter().enumerate() {
+                        let field_name = c.get(1).unwrap().as_str().to_string();
+                        let cls_word = c.get(2).map(|m| m.as_str());
+                        let tag_opt = c.get(3).map(|m| m.as_str());
+                        let tagging = match c.get(4).map(|m| m.as_str()) {
+                            Some("EXPLICIT") => Tagging::Explicit,
+                            Some(_) => Tagging::Implicit,
+                            None if tag_opt.is_some() && schema.tag_default == TagDefault::Explicit => Tagging::Explicit,
+                            None => Tagging::Implicit,
+                        };
+                        let type_spec = c.get(5).unwrap().as_str().split_whitespace().collect::<Vec<_>>().join(" ");
+                        let constraint = c.get(6).map(|m| m.as_str().trim().to_string());
+                        let has_default = c.get(7).is_some();
+                        let optional = c.get(8).is_some();
+                        let order = position_of(c.get(0).unwrap().start(), idx);
+
+                        let mut is_sequence_of = false;
+                        let mut is_set_of = false;
+                        let mut element_type = type_spec.clone();
+
+                        if let Some(rest) = type_spec.strip_prefix("SEQUENCE OF ") {
+                            is_sequence_of = true;
+                            element_type = rest.trim().to_string();
+                        } else if let Some(rest) = type_spec.strip_prefix("SET OF ") {
+                            is_set_of = true;
+                            element_type = rest.trim().to_string();
+                        }
+
+                        let key: TagKey = if let Some(tag_str) = tag_opt {
+                            let cls = tag_class_from_word(cls_word);
+                            (cls, tag_str.parse::<u32>()?)
+                        } else if auto_tag {
+                            (2u8, idx as u32)
+                        } else {
+                            match schema.tag_for_type(&element_type) {
+                                Some(tk) => tk,
+                                None => continue,
+                            }
+                        };
+
+                        let single = !is_sequence_of && !is_set_of;
+                        let size = match constraint.as_deref().and_then(SizeConstraint::parse) {
+                            None if single => schema.type_constraint(&element_type, SizeConstraint::parse),
+                            size => size,
+                        };
+                        let range = match constraint.as_deref().and_then(ValueConstraint::parse) {
+                            None if single => schema.type_constraint(&element_type, ValueConstraint::parse),
+                            range => range,
+                        };
+
+                        if let Some(prev) = fields.get(&key) {
+                            schema.duplicate_tags.push((d.type_name.clone(), key, field_name.clone(), prev.name.clone()));
+                        }
+                        fields.insert(
+                            key,
+                            FieldSpec {
+                                name: field_name,
+                                field_type: element_type,
+                                optional,
+                                has_default,
+                                size,
+                                range,
+                                tagging,
+                                constraint,
+                                is_sequence_of,
+                                is_set_of,
+                                order,
+                            },
+                        );
+                    }
+                    
+                    for c in components_of_re.captures_iter(&d.body) {
+                        let source_type = c.get(1).unwrap().as_str().to_string();
+                        let start = c.get(0).unwrap().start();
+                        let fields_before = field_caps.iter().filter(|f| f.get(0).unwrap().start() < start).count();
+                        let order = position_of(start, fields_before);
+                        components_queue.push((d.type_name.clone(), source_type, order));
+                    }
+
+                    if is_set {
+                        schema.sets.insert(d.type_name, fields);
+                    } else {
+                        schema.sequences.insert(d.type_name, fields);
+                    }
+                }
+                "CHOICE" => {
+                    let mut alts: HashMap<TagKey, (String, String)> = HashMap::new();
+
+                    // Alternatives may mix `[n]`-tagged ones with untagged ones whose type
+                    // carries its own tag (e.g. `smsRecord SMSRecord` with `SMSRecord ::= [APPLICATION 5] SET`).
+                    let components = split_components(&d.body);
+                    let any_tagged = components.iter().any(|c| choice_tagged_re.is_match(c));
+                    let mut idx: u32 = 0;
+
+                    for comp in components {
+                        if let Some(c) = choice_tagged_re.captures(comp) {
+                            let field_name = c.get(1).unwrap().as_str().to_string();
+                            let cls_word = c.get(2).map(|m| m.as_str());
+                            let tag: u32 = c.get(3).unwrap().as_str().parse()?;
+                            let field_type = c.get(4).unwrap().as_str().split_whitespace().collect::<Vec<_>>().join(" ");
+                            let cls = tag_class_from_word(cls_word);
+                            if let Some((prev, _)) = alts.get(&(cls, tag)) {
+                                schema.duplicate_tags.push((d.type_name.clone(), (cls, tag), field_name.clone(), prev.clone()));
+                            }
+                            alts.insert((cls, tag), (field_name, field_type));
+                        } else if let Some(c) = choice_untagged_re.captures(comp) {
+                            let field_name = c.get(1).unwrap().as_str().to_string();
+                            let field_type = c.get(2).unwrap().as_str().split_whitespace().collect::<Vec<_>>().join(" ");
+                            if field_name == "isPdu" || field_name == "TRUE" { continue; }
+                            if !field_name.is_empty() && !field_type.is_empty() {
+                                let key = if automatic && !any_tagged { (2u8, idx) } else { (3u8, SYNTH_CHOICE_BASE + idx) };
+                                alts.insert(key, (field_name, field_type));
+                                idx += 1;
+                            }
+                        }
+                    }
+
+                    schema.choices.insert(d.type_name, alts);
+                }
+                _ => {}
+            }
+        }
+        
+        // 4. Resolve COMPONENTS OF
+        for (target, source, order) in components_queue {
+            let source_fields = schema
+                .sequences
+                .get(&source)
+                .or_else(|| schema.sets.get(&source))
+                .cloned()
+                .map(|mut fields| {
+                    for f in fields.values_mut() {
+                        f.order = order | (f.order >> 16);
+                    }
+                    fields
+                });
+
+            if let Some(src) = source_fields {
+                let tgt = match schema.sequences.get_mut(&target) {
+                    Some(tgt) => Some(tgt),
+                    None => schema.sets.get_mut(&target),
+                };
+                if let Some(tgt) = tgt {
+                    for (key, field) in src {
+                        if let Some(prev) = tgt.get(&key) {
+                            schema.duplicate_tags.push((target.clone(), key, field.name.clone(), prev.name.clone()));
+                        }
+                        tgt.insert(key, field);
+                    }
+                }
+            }
+        }
+
+        schema.check_reference_cycles()?;
+        Ok(schema)
+    }
+
+    /// Fails on a cycle of type references (`A ::= B`, `B ::= A`). Lookups give up after 32
+    /// steps and would otherwise quietly decode such types as whatever they stopped on.
+    fn check_reference_cycles(&self) -> Result<()> {
+        let next = |t: &str| self.aliases.get(t).or_else(|| self.primitives.get(t)).filter(|n| n.as_str() != t);
+        let mut names: Vec<&String> = self.aliases.keys().chain(self.primitives.keys()).collect();
+        names.sort();
+        names.dedup();
+        for start in names {
+            let mut path: Vec<&str> = vec![start];
+            let mut cur: &str = start;
+            while let Some(n) = next(cur) {
+                if let Some(at) = path.iter().position(|&p| p == n.as_str()) {
+                    // Report each cycle once, from its alphabetically first member.
+                    if at == 0 {
+                        path.push(n);
+                        return Err(anyhow!("cyclic type reference: {}", path.join(" -> ")));
+                    }
+                    break;
+                }
+                path.push(n);
+                cur = n;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn resolve_alias<'a>(&'a self, mut t: &'a str) -> &'a str {
+        for _ in 0..32 {
+            if let Some(next) = self.aliases.get(t) {
+                t = next;
+            } else {
+                break;
+            }
+        }
+        t
+    }
+
+    #[inline]
+    pub fn knows_type(&self, t: &str) -> bool {
+        let rt = self.resolve_alias(t);
+        self.choices.contains_key(rt)
+            || self.sequences.contains_key(rt)
+            || self.sets.contains_key(rt)
+            || self.seq_of_types.contains_key(rt)
+            || self.set_of_types.contains_key(rt)
+            || self.primitives.contains_key(rt)
+    }
+
+    /// Whether `t` names a type this schema can resolve: one it defines, a built-in kind, or a
+    /// kind given a tag by `--universal-overrides`.
+    fn is_defined(&self, t: &str) -> bool {
+        let rt = self.resolve_alias(t);
+        self.knows_type(rt)
+            || standard_universal_tag(rt).is_some()
+            || BUILTIN_TYPES_WITHOUT_DECODER.contains(&rt)
+            || self.universal_overrides.contains_key(rt)
+    }
+
+    /// Type names used by components, CHOICE alternatives, SEQUENCE OF / SET OF elements and
+    /// type references that the schema never defines. Each comes with the place it is first
+    /// used (`Type.component` or `Type`, the first in sorted order), sorted by name.
+    pub fn undefined_references(&self) -> Vec<(&str, String)> {
+        let mut uses: Vec<(&str, String)> = Vec::new();
+        for (owner, fields) in self.sequences.iter().chain(&self.sets) {
+            for f in fields.values() {
+                uses.push((&f.field_type, format!("{}.{}", owner, f.name)));
+            }
+        }
+        for (owner, alts) in &self.choices {
+            for (name, alt_type) in alts.values() {
+                uses.push((alt_type, format!("{}.{}", owner, name)));
+            }
+        }
+        for (owner, target) in self.seq_of_types.iter().chain(&self.set_of_types).chain(&self.aliases).chain(&self.primitives) {
+            uses.push((target, owner.clone()));
+        }
+        uses.retain(|(t, _)| !self.is_defined(t));
+        uses.sort();
+        uses.dedup_by(|later, first| later.0 == first.0);
+        uses
+    }
+
+    /// Components of SEQUENCE / SET `t` in declaration order; `None` for any other type.
+    pub fn fields_in_order(&self, t: &str) -> Option<Vec<&FieldSpec>> {
+        let rt = self.resolve_alias(t);
+        let fields = self.sequences.get(rt).or_else(|| self.sets.get(rt))?;
+        let mut fields: Vec<(&TagKey, &FieldSpec)> = fields.iter().collect();
+        fields.sort_by_key(|(k, f)| (f.order, **k));
+        Some(fields.into_iter().map(|(_, f)| f).collect())
+    }
+
+    /// Primitive kind of `t` (e.g. "INTEGER"), following aliases and type references such as
+    /// `Serial ::= [APPLICATION 5] SerialNumber`. Returns the resolved name itself if it isn't a primitive.
+    pub fn primitive_kind<'a>(&'a self, t: &'a str) -> &'a str {
+        let mut rt = self.resolve_alias(t);
+        for _ in 0..32 {
+            match self.primitives.get(rt) {
+                Some(kind) if kind != rt && (self.primitives.contains_key(kind) || self.aliases.contains_key(kind)) => {
+                    rt = self.resolve_alias(kind);
+                }
+                Some(kind) => return kind,
+                None => return rt,
+            }
+        }
+        rt
+    }
+
+    /// Whether `t` is `base` or defined (through any chain of references) as one. Schemas often
+    /// declare e.g. `TBCD-STRING ::= OCTET STRING` themselves, so `primitive_kind` can't tell.
+    pub fn derives_from(&self, t: &str, base: &str) -> bool {
+        let mut rt = t;
+        for _ in 0..32 {
+            if rt == base {
+                return true;
+            }
+            match self.aliases.get(rt).or_else(|| self.primitives.get(rt)) {
+                Some(next) if next != rt => rt = next,
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    /// Named values of `t`, or of the ENUMERATED / INTEGER type it references.
+    fn named_ints(&self, t: &str) -> Option<&HashMap<i64, String>> {
+        self.lookup_through_refs(t, &self.named_ints)
+    }
+
+    /// Named bits of `t`, or of the BIT STRING type it references.
+    pub fn named_bits(&self, t: &str) -> Option<&HashMap<u32, String>> {
+        self.lookup_through_refs(t, &self.bitstrings)
+    }
+
+    /// Entry of `map` for `t` or the nearest type it references (aliases and `A ::= B`) that has one.
+    fn lookup_through_refs<'a, V>(&'a self, t: &str, map: &'a HashMap<String, V>) -> Option<&'a V> {
+        let mut rt = self.resolve_alias(t);
+        for _ in 0..32 {
+            if let Some(found) = map.get(rt) {
+                return Some(found);
+            }
+            match self.primitives.get(rt) {
+                Some(next) if next != rt => rt = self.resolve_alias(next),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Constraint of one kind (SIZE or value range) on `t` or the nearest type it references that has one.
+    fn type_constraint<T>(&self, t: &str, parse: fn(&str) -> Option<T>) -> Option<T> {
+        let mut rt = self.resolve_alias(t);
+        for _ in 0..32 {
+            if let Some(found) = self.constraints.get(rt).and_then(|c| parse(c)) {
+                return Some(found);
+            }
+            match self.primitives.get(rt) {
+                Some(next) if next != rt => rt = self.resolve_alias(next),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// True if `t`, or a type it references, carries a constraint whose values are all >= 0.
+    fn is_non_negative_int(&self, t: &str) -> bool {
+        let mut rt = self.resolve_alias(t);
+        for _ in 0..32 {
+            if self.constraints.get(rt).is_some_and(|c| is_non_negative_range(c)) {
+                return true;
+            }
+            match self.primitives.get(rt) {
+                Some(next) if next != rt => rt = self.resolve_alias(next),
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    #[inline]
+    pub fn tag_for_type(&self, t: &str) -> Option<TagKey> {
+        let rt = self.resolve_alias(t);
+        if let Some(tk) = self.type_outer_tag.get(rt) {
+            return Some(*tk);
+        }
+        self.universal_tag_for_type(rt)
+    }
+
+    #[inline]
+    pub fn universal_tag_for_type(&self, t: &str) -> Option<TagKey> {
+        let rt = self.resolve_alias(t);
+
+        if self.sequences.contains_key(rt) || self.seq_of_types.contains_key(rt) {
+            return Some((0u8, 16u32));
+        }
+        if self.sets.contains_key(rt) || self.set_of_types.contains_key(rt) {
+            return Some((0u8, 17u32));
+        }
+        if self.choices.contains_key(rt) {
+            return None;
+        }
+
+        if let Some(tag) = self.overridden_universal_tag(rt) {
+            return Some((0u8, tag));
+        }
+        standard_universal_tag(self.primitive_kind(rt)).map(|tag| (0u8, tag))
+    }
+
+    /// Universal tag from `--universal-overrides` for `t` or any type it references.
+    fn overridden_universal_tag(&self, t: &str) -> Option<u32> {
+        if self.universal_overrides.is_empty() {
+            return None;
+        }
+        let mut rt = self.resolve_alias(t);
+        for _ in 0..32 {
+            if let Some(&tag) = self.universal_overrides.get(rt) {
+                return Some(tag);
+            }
+            match self.primitives.get(rt) {
+                Some(next) if next != rt => rt = self.resolve_alias(next),
+                _ => return None,
+            }
+        }
+        None
+    }
+}
+
+/// Universal types DER requires to use the primitive encoding (X.690 10.2): BOOLEAN through
+/// OBJECT IDENTIFIER, REAL, ENUMERATED, RELATIVE-OID, the string and the time types.
+const DER_PRIMITIVE_UNIVERSAL_TAGS: &[u32] = &[1, 2, 3, 4, 5, 6, 9, 10, 12, 13, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30];
+
+/// X.680 universal tag numbers of the primitive kinds the decoder knows.
+const STANDARD_UNIVERSAL_TAGS: &[(&str, u32)] = &[
+    ("BOOLEAN", 1),
+    ("INTEGER", 2),
+    ("BIT STRING", 3),
+    ("OCTET STRING", 4),
+    ("NULL", 5),
+    ("OBJECT IDENTIFIER", 6),
+    ("REAL", 9),
+    ("ENUMERATED", 10),
+    ("UTF8String", 12),
+    ("RELATIVE-OID", 13),
+    ("TIME", 14),
+    ("NumericString", 18),
+    ("PrintableString", 19),
+    ("IA5String", 22),
+    ("UTCTime", 23),
+    ("GeneralizedTime", 24),
+    ("GraphicString", 25),
+    ("VisibleString", 26),
+    ("UniversalString", 28),
+    ("BMPString", 30),
+    ("DATE", 31),
+    ("TIME-OF-DAY", 32),
+    ("DATE-TIME", 33),
+    ("DURATION", 34),
+    // Not a universal type, but conventionally encoded as an OCTET STRING.
+    ("TBCD-STRING", 4),
+];
+
+/// ASN.1 built-in types the decoder has no universal tag or text decoder for; values of these
+/// types come out as hex, but a schema using them is not referencing anything undefined.
+const BUILTIN_TYPES_WITHOUT_DECODER: &[&str] = &[
+    "ANY",
+    "EXTERNAL",
+    "EMBEDDED PDV",
+    "CHARACTER STRING",
+    "ObjectDescriptor",
+    "TeletexString",
+    "T61String",
+    "VideotexString",
+    "GeneralString",
+    "ISO646String",
+];
+
+#[inline]
+pub fn standard_universal_tag(kind: &str) -> Option<u32> {
+    STANDARD_UNIVERSAL_TAGS.iter().find(|(k, _)| *k == kind).map(|&(_, tag)| tag)
+}
+
+/// Reads a `--universal-overrides` file: one `KIND = TAG` per line, `--` starts a comment.
+pub fn load_universal_overrides(path: &Path) -> Result<HashMap<String, u32>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read universal overrides {:?}", path))?;
+    let mut overrides = HashMap::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.split("--").next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let at = || format!("{:?} line {}", path, lineno + 1);
+        let (kind, tag) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{}: expected KIND = TAG, got '{}'", at(), line))?;
+        let kind = kind.split_whitespace().collect::<Vec<_>>().join(" ");
+        let tag: u32 = tag
+            .trim()
+            .parse()
+            .with_context(|| format!("{}: '{}' is not a tag number", at(), tag.trim()))?;
+        if kind.is_empty() {
+            return Err(anyhow!("{}: missing type kind", at()));
+        }
+        // 0 is end-of-contents; 16/17 are what SEQUENCE/SET matching relies on.
+        if matches!(tag, 0 | 16 | 17) {
+            return Err(anyhow!("{}: universal tag {} cannot be reassigned", at(), tag));
+        }
+        if let Some(&(other, _)) = STANDARD_UNIVERSAL_TAGS.iter().find(|&&(k, t)| t == tag && k != kind) {
+            eprintln!(
+                "Warning: universal override {} = {} collides with the standard tag of {}",
+                kind, tag, other
+            );
+        }
+        if overrides.insert(kind.clone(), tag).is_some_and(|prev| prev != tag) {
+            eprintln!("Warning: universal override for {} given more than once; using {}", kind, tag);
+        }
+    }
+    Ok(overrides)
+}
+
+/// Tag and length of a TLV; `length` is `None` for the indefinite form.
+pub struct TlvHeader {
+    pub tag_class: u8,
+    pub constructed: bool,
+    pub tag_num: u32,
+    /// `None` for the indefinite form.
+    pub length: Option<usize>,
+    pub content_start: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tlv<'a> {
+    pub tag_class: u8,
+    pub constructed: bool,
+    pub tag_num: u32,
+    pub length: usize,
+    /// Content octets.
+    pub value: &'a [u8],
+    /// The whole encoding: identifier, length and content octets.
+    pub raw: &'a [u8],
+}
+
+#[inline]
+pub fn write_json_key<W: Write>(w: &mut W, key: &str) -> Result<()> {
+    w.write_all(b"\"")?;
+    for &b in key.as_bytes() {
+        match b {
+            b'"' => w.write_all(b"\\\"")?,
+            b'\\' => w.write_all(b"\\\\")?,
+            b'\n' => w.write_all(b"\\n")?,
+            b'\r' => w.write_all(b"\\r")?,
+            b'\t' => w.write_all(b"\\t")?,
+            c if c < 0x20 => {
+                const HEX: &[u8; 16] = b"0123456789abcdef";
+                let esc = [b'\\', b'u', b'0', b'0', HEX[(c >> 4) as usize], HEX[(c & 0x0F) as usize]];
+                w.write_all(&esc)?;
+            }
+            c => w.write_all(&[c])?,
+        }
+    }
+    w.write_all(b"\"")?;
+    Ok(())
+}
+
+const HEX_LOWER: &[u8; 16] = b"0123456789abcdef";
+const HEX_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Hex digits of `bytes`. The case is a const parameter rather than an argument, so each
+/// variant compiles to its own constant-table loop and lowercase costs what it always did.
+#[inline(always)]
+fn hex_encode_into<'a, const UPPER: bool>(bytes: &[u8], scratch: &'a mut Vec<u8>) -> &'a [u8] {
+    let digits = if UPPER { HEX_UPPER } else { HEX_LOWER };
+    scratch.clear();
+    scratch.resize(bytes.len() * 2, 0);
+    let mut j = 0usize;
+    for &b in bytes {
+        scratch[j] = digits[(b >> 4) as usize];
+        scratch[j + 1] = digits[(b & 0x0F) as usize];
+        j += 2;
+    }
+    &scratch[..j]
+}
+
+/// Standard Base64 (RFC 4648) with padding. `scratch` is sized once to `4 * ceil(len / 3)`.
+#[inline]
+fn base64_encode_into<'a>(bytes: &[u8], scratch: &'a mut Vec<u8>) -> &'a [u8] {
+    const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    scratch.clear();
+    scratch.resize(4 * bytes.len().div_ceil(3), 0);
+    let mut j = 0usize;
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as usize) << 16
+            | (chunk.get(1).copied().unwrap_or(0) as usize) << 8
+            | chunk.get(2).copied().unwrap_or(0) as usize;
+        scratch[j] = B64[n >> 18];
+        scratch[j + 1] = B64[(n >> 12) & 0x3F];
+        scratch[j + 2] = if chunk.len() > 1 { B64[(n >> 6) & 0x3F] } else { b'=' };
+        scratch[j + 3] = if chunk.len() > 2 { B64[n & 0x3F] } else { b'=' };
+        j += 4;
+    }
+    &scratch[..j]
+}
+
+/// Decodes DER INTEGER content (two's complement, big-endian). `None` above 8 bytes.
+#[inline]
+fn decode_int_i64(bytes: &[u8]) -> Option<i64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    let mut v: i64 = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+    for &b in bytes {
+        v = (v << 8) | b as i64;
+    }
+    Some(v)
+}
+
+/// Decodes INTEGER content as unsigned; a leading 0x00 pad byte is allowed on top of 8 bytes.
+#[inline]
+fn decode_uint_u64(bytes: &[u8]) -> Option<u64> {
+    let bytes = match bytes {
+        [0, rest @ ..] if rest.len() == 8 => rest,
+        _ => bytes,
+    };
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    Some(bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+}
+
+/// Decimal text of a big-endian unsigned magnitude of any length.
+fn big_decimal(magnitude: &[u8]) -> String {
+    const CHUNK: u64 = 1_000_000_000;
+    let mut digits = magnitude.to_vec();
+    // Base-10^9 chunks, least significant first, from repeated long division.
+    let mut chunks: Vec<u32> = Vec::new();
+    while digits.iter().any(|&b| b != 0) {
+        let mut rem: u64 = 0;
+        for b in digits.iter_mut() {
+            let cur = (rem << 8) | *b as u64;
+            *b = (cur / CHUNK) as u8;
+            rem = cur % CHUNK;
+        }
+        chunks.push(rem as u32);
+    }
+    let mut out = chunks.last().map_or_else(|| "0".to_string(), |c| c.to_string());
+    for c in chunks.iter().rev().skip(1) {
+        out.push_str(&format!("{:09}", c));
+    }
+    out
+}
+
+/// Decimal text of INTEGER content (two's complement) too wide for `decode_int_i64`: `i128`
+/// up to 16 bytes, long division beyond.
+fn decode_big_int(bytes: &[u8]) -> Option<String> {
+    if bytes.is_empty() {
+        return None;
+    }
+    let negative = bytes[0] & 0x80 != 0;
+    if bytes.len() <= 16 {
+        let mut v: i128 = if negative { -1 } else { 0 };
+        for &b in bytes {
+            v = (v << 8) | b as i128;
+        }
+        return Some(v.to_string());
+    }
+    if !negative {
+        return Some(big_decimal(bytes));
+    }
+    // |v| = !v + 1, computed from the least significant byte up.
+    let mut magnitude: Vec<u8> = bytes.iter().map(|b| !b).collect();
+    for b in magnitude.iter_mut().rev() {
+        let (sum, carry) = b.overflowing_add(1);
+        *b = sum;
+        if !carry {
+            break;
+        }
+    }
+    Some(format!("-{}", big_decimal(&magnitude)))
+}
+
+/// Decodes REAL content (X.690 8.5): binary, decimal (ISO 6093) or the special values.
+/// `None` for reserved or malformed encodings.
+fn decode_real(data: &[u8]) -> Option<f64> {
+    let (&first, rest) = match data.split_first() {
+        None => return Some(0.0),
+        Some(split) => split,
+    };
+    if first & 0x80 != 0 {
+        let log2_base = match (first >> 4) & 0x03 {
+            0 => 1,
+            1 => 3,
+            2 => 4,
+            _ => return None,
+        };
+        let scale = ((first >> 2) & 0x03) as i64;
+        let (exp_len, rest) = match first & 0x03 {
+            3 => {
+                let (&len, rest) = rest.split_first()?;
+                (len as usize, rest)
+            }
+            n => (n as usize + 1, rest),
+        };
+        if exp_len == 0 || exp_len > 4 || rest.len() <= exp_len {
+            return None;
+        }
+        let exponent = decode_int_i64(&rest[..exp_len])?;
+        let mantissa = rest[exp_len..].iter().fold(0f64, |acc, &b| acc * 256.0 + b as f64);
+        let value = scale_pow2(mantissa, exponent * log2_base + scale);
+        return Some(if first & 0x40 != 0 { -value } else { value });
+    }
+    if first & 0x40 != 0 {
+        return match first {
+            0x40 => Some(f64::INFINITY),
+            0x41 => Some(f64::NEG_INFINITY),
+            0x42 => Some(f64::NAN),
+            0x43 => Some(-0.0),
+            _ => None,
+        };
+    }
+    // Decimal: NR1/NR2/NR3 text; only digits, sign, point/comma, exponent and spaces.
+    if !(1..=3).contains(&(first & 0x3F)) {
+        return None;
+    }
+    let text = std::str::from_utf8(rest).ok()?.trim();
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit() || b"+-.,eE".contains(&b)) {
+        return None;
+    }
+    text.replace(',', ".").parse().ok()
+}
+
+/// `mantissa * 2^exp`, applied in steps so large mantissas with very negative exponents
+/// don't underflow in an intermediate power.
+fn scale_pow2(mut mantissa: f64, mut exp: i64) -> f64 {
+    while exp != 0 && mantissa != 0.0 && mantissa.is_finite() {
+        let step = exp.clamp(-1000, 1000);
+        mantissa *= 2f64.powi(step as i32);
+        exp -= step;
+    }
+    mantissa
+}
+
+/// Length of a primitive string value in the units its SIZE constraint counts: octets, characters
+/// or (for BIT STRING) bits. `None` for kinds that have no length or content that can't be measured.
+fn constrained_length(kind: &str, data: &[u8]) -> Option<u64> {
+    let len = data.len() as u64;
+    match kind {
+        "OCTET STRING" | "TBCD-STRING" | "IA5String" | "VisibleString" | "NumericString" | "PrintableString"
+        | "GraphicString" | "GeneralString" | "TeletexString" | "T61String" | "VideotexString" => Some(len),
+        "UTF8String" => std::str::from_utf8(data).ok().map(|s| s.chars().count() as u64),
+        "BMPString" => Some(len / 2),
+        "UniversalString" => Some(len / 4),
+        "BIT STRING" => match data.split_first() {
+            Some((&unused, rest)) if unused < 8 && !(rest.is_empty() && unused != 0) => {
+                Some(rest.len() as u64 * 8 - unused as u64)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// True for value-range constraints such as `0..MAX`, `1..255` or `0 | 5..10` whose every
+/// alternative has a non-negative lower bound.
+fn is_non_negative_range(constraint: &str) -> bool {
+    constraint.split('|').all(|alt| {
+        let lower = alt.split("..").next().unwrap_or("").trim().trim_end_matches('<');
+        lower.parse::<i64>().is_ok_and(|v| v >= 0)
+    })
+}
+
+/// Splits a field name into lowercase words: `servingNodeIPAddress` => serving, node, ip, address.
+fn name_words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words: Vec<String> = Vec::new();
+    let mut cur = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !cur.is_empty() {
+                words.push(std::mem::take(&mut cur));
+            }
+            continue;
+        }
+        let prev = if i > 0 { chars[i - 1] } else { ' ' };
+        let next = chars.get(i + 1).copied().unwrap_or(' ');
+        let boundary = c.is_ascii_uppercase()
+            && (prev.is_ascii_lowercase() || prev.is_ascii_digit() || (prev.is_ascii_uppercase() && next.is_ascii_lowercase()));
+        if boundary && !cur.is_empty() {
+            words.push(std::mem::take(&mut cur));
+        }
+        cur.push(c.to_ascii_lowercase());
+    }
+    if !cur.is_empty() {
+        words.push(cur);
+    }
+    words
+}
+
+/// 4 bytes => dotted quad, 16 bytes => RFC 5952 IPv6 text (with `::` compression).
+fn format_ip(data: &[u8]) -> Option<String> {
+    if let Ok(v4) = <[u8; 4]>::try_from(data) {
+        return Some(Ipv4Addr::from(v4).to_string());
+    }
+    if let Ok(v6) = <[u8; 16]>::try_from(data) {
+        return Some(Ipv6Addr::from(v6).to_string());
+    }
+    None
+}
+
+#[inline]
+fn all_digits(b: &[u8]) -> bool {
+    !b.is_empty() && b.iter().all(u8::is_ascii_digit)
+}
+
+/// DATE content (`YYYYMMDD`) => `YYYY-MM-DD`.
+fn decode_date(b: &[u8]) -> Option<String> {
+    if b.len() != 8 || !all_digits(b) {
+        return None;
+    }
+    let s = std::str::from_utf8(b).ok()?;
+    Some(format!("{}-{}-{}", &s[0..4], &s[4..6], &s[6..8]))
+}
+
+/// TIME-OF-DAY content (`HHMMSS`) => `HH:MM:SS`.
+fn decode_time_of_day(b: &[u8]) -> Option<String> {
+    if b.len() != 6 || !all_digits(b) {
+        return None;
+    }
+    let s = std::str::from_utf8(b).ok()?;
+    Some(format!("{}:{}:{}", &s[0..2], &s[2..4], &s[4..6]))
+}
+
+/// DATE-TIME content (`YYYYMMDDHHMMSS`) => `YYYY-MM-DDTHH:MM:SS`.
+fn decode_date_time(b: &[u8]) -> Option<String> {
+    if b.len() != 14 {
+        return None;
+    }
+    Some(format!("{}T{}", decode_date(&b[..8])?, decode_time_of_day(&b[8..])?))
+}
+
+/// Time zone suffix of GeneralizedTime/UTCTime: empty (local time), `Z`, or `+HHMM`/`-HHMM`
+/// rendered as `+HH:MM`.
+fn decode_time_zone(b: &[u8]) -> Option<String> {
+    match b {
+        [] => Some(String::new()),
+        [b'Z'] => Some("Z".to_string()),
+        [sign @ (b'+' | b'-'), hm @ ..] if hm.len() == 4 && all_digits(hm) => Some(format!(
+            "{}{}:{}",
+            *sign as char,
+            std::str::from_utf8(&hm[..2]).ok()?,
+            std::str::from_utf8(&hm[2..]).ok()?
+        )),
+        _ => None,
+    }
+}
+
+/// GeneralizedTime content (`YYYYMMDDHHMMSS[.f...][Z|+-HHMM]`) => `YYYY-MM-DDTHH:MM:SS[.f...][Z|+-HH:MM]`.
+/// Fractional seconds keep every digit; a `,` decimal mark becomes `.`.
+fn decode_generalized_time(b: &[u8]) -> Option<String> {
+    let mut out = decode_date_time(b.get(..14)?)?;
+    let mut rest = &b[14..];
+    if let [b'.' | b',', tail @ ..] = rest {
+        let digits = tail.iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        out.push('.');
+        out.push_str(std::str::from_utf8(&tail[..digits]).ok()?);
+        rest = &tail[digits..];
+    }
+    out.push_str(&decode_time_zone(rest)?);
+    Some(out)
+}
+
+/// UTCTime content (`YYMMDDHHMM[SS](Z|+-HHMM)`) => `YYYY-MM-DDTHH:MM:SS(Z|+-HH:MM)`. The
+/// year is the one in `[pivot, pivot + 99]` ending in `YY`; omitted seconds become `:00`.
+fn decode_utc_time(b: &[u8], pivot: u16) -> Option<String> {
+    let seconds = b.len() > 12 && b[10..12].iter().all(u8::is_ascii_digit);
+    let (digits, zone) = b.split_at_checked(if seconds { 12 } else { 10 })?;
+    if !all_digits(digits) || zone.is_empty() {
+        return None;
+    }
+    let yy = u16::from(digits[0] - b'0') * 10 + u16::from(digits[1] - b'0');
+    let year = pivot + (yy + 100 - pivot % 100) % 100;
+    let mut full = format!("{:04}", year).into_bytes();
+    full.extend_from_slice(&digits[2..]);
+    if !seconds {
+        full.extend_from_slice(b"00");
+    }
+    Some(format!("{}{}", decode_date_time(&full)?, decode_time_zone(zone)?))
+}
+
+/// Days from 1970-01-01 to the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Unix seconds of a decoded GeneralizedTime/UTCTime string (`YYYY-MM-DDTHH:MM:SS[.f...]`
+/// plus `Z` or `+-HH:MM`). Fractions are dropped. `None` for local time (no zone) or an
+/// impossible date such as February 30.
+fn iso_epoch(iso: &str) -> Option<i64> {
+    let b = iso.as_bytes();
+    let num = |r: std::ops::Range<usize>| -> Option<u32> { std::str::from_utf8(b.get(r)?).ok()?.parse().ok() };
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if !(1..=12).contains(&month) || day == 0 || day > month_days[month as usize - 1] || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let mut zone = &b[19..];
+    if let [b'.', tail @ ..] = zone {
+        zone = &tail[tail.iter().take_while(|c| c.is_ascii_digit()).count()..];
+    }
+    let offset = match zone {
+        [b'Z'] => 0,
+        [sign @ (b'+' | b'-'), ..] if zone.len() == 6 => {
+            let minutes = i64::from(num(b.len() - 5..b.len() - 3)? * 60 + num(b.len() - 2..b.len())?);
+            if *sign == b'+' { minutes * 60 } else { -minutes * 60 }
+        }
+        _ => return None,
+    };
+    let days = days_from_civil(i64::from(year), month, day);
+    Some(days * 86_400 + i64::from(hour * 3600 + minute * 60 + second) - offset)
+}
+
+/// Splits OID content into its base-128 subidentifiers. `None` if one is cut off (last byte
+/// has the high bit set), padded with a leading 0x80, or too large for 64 bits.
+fn decode_subidentifiers(b: &[u8]) -> Option<Vec<u64>> {
+    if b.is_empty() || b[b.len() - 1] & 0x80 != 0 {
+        return None;
+    }
+    let mut arcs = Vec::new();
+    let mut v: u64 = 0;
+    let mut fresh = true;
+    for &byte in b {
+        if fresh && byte == 0x80 {
+            return None;
+        }
+        if v > u64::MAX >> 7 {
+            return None;
+        }
+        v = (v << 7) | (byte & 0x7F) as u64;
+        fresh = byte & 0x80 == 0;
+        if fresh {
+            arcs.push(v);
+            v = 0;
+        }
+    }
+    Some(arcs)
+}
+
+/// OBJECT IDENTIFIER content => dotted decimal, e.g. `1.2.840.113549.1.1.11`. The first
+/// subidentifier packs the first two arcs as `X * 40 + Y` (X is 2 for anything >= 80).
+fn decode_oid(b: &[u8]) -> Option<String> {
+    let arcs = decode_subidentifiers(b)?;
+    let (x, y) = match arcs[0] {
+        v @ 0..=39 => (0, v),
+        v @ 40..=79 => (1, v - 40),
+        v => (2, v - 80),
+    };
+    let mut out = format!("{}.{}", x, y);
+    for arc in &arcs[1..] {
+        out.push('.');
+        out.push_str(itoa::Buffer::new().format(*arc));
+    }
+    Some(out)
+}
+
+/// RELATIVE-OID content => dotted decimal of its subidentifiers as encoded; unlike an
+/// absolute OID the first one is a single arc.
+fn decode_relative_oid(b: &[u8]) -> Option<String> {
+    let arcs = decode_subidentifiers(b)?;
+    let mut out = String::new();
+    for (i, arc) in arcs.iter().enumerate() {
+        if i > 0 {
+            out.push('.');
+        }
+        out.push_str(itoa::Buffer::new().format(*arc));
+    }
+    Some(out)
+}
+
+/// DURATION content (`nYnMnDTnHnMnS`, leading `P` optional) => `PnYnMnDTnHnMnS`.
+fn decode_duration(b: &[u8]) -> Option<String> {
+    let body = b.strip_prefix(b"P").unwrap_or(b);
+    let valid = body
+        .iter()
+        .all(|c| c.is_ascii_digit() || matches!(c, b'Y' | b'M' | b'W' | b'D' | b'T' | b'H' | b'S' | b'.' | b','));
+    if body.is_empty() || !valid || !body.iter().any(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(format!("P{}", std::str::from_utf8(body).ok()?))
+}
+
+/// TIME content is already an ISO 8601 string; accept it if it is printable ASCII.
+fn decode_iso_time(b: &[u8]) -> Option<String> {
+    if b.is_empty() || !b.iter().all(|c| c.is_ascii_graphic()) {
+        return None;
+    }
+    Some(std::str::from_utf8(b).ok()?.to_string())
+}
+
+/// VisibleString content: printable ASCII (0x20..=0x7E) only.
+fn decode_visible_string(b: &[u8]) -> Option<String> {
+    if !b.iter().all(|&c| (0x20..=0x7E).contains(&c)) {
+        return None;
+    }
+    Some(std::str::from_utf8(b).ok()?.to_string())
+}
+
+/// TBCD-STRING content => digit string, two digits per octet.
+fn decode_tbcd(b: &[u8], opts: &TbcdOptions) -> String {
+    let mut s = String::with_capacity(b.len() * 2);
+    for &byte in b {
+        let (first, second) = match opts.nibble_order {
+            NibbleOrder::LowFirst => (byte & 0x0F, byte >> 4),
+            NibbleOrder::HighFirst => (byte >> 4, byte & 0x0F),
+        };
+        for nibble in [first, second] {
+            match nibble {
+                0..=9 => s.push(char::from(b'0' + nibble)),
+                0xF if opts.stop_at_filler => return s,
+                0xA..=0xE if opts.extended_digits => s.push(char::from(b"*#abc"[usize::from(nibble - 0xA)])),
+                _ => {}
+            }
+        }
+    }
+    s
+}
+
+/// 3GPP TS 24.008 PLMN identity (3 octets) => (MCC, MNC). Octet 1 holds MCC digits 2|1
+/// (high|low nibble), octet 2 MNC digit 3|MCC digit 3, octet 3 MNC digits 2|1. An MNC digit 3
+/// of 0xF means a 2-digit MNC. `None` for any other length or non-digit nibble.
+fn decode_plmn_id(b: &[u8]) -> Option<(String, String)> {
+    let &[o1, o2, o3] = b else { return None };
+    let digit = |n: u8| (n <= 9).then(|| char::from(b'0' + n));
+    let mcc: String = [o1 & 0x0F, o1 >> 4, o2 & 0x0F].into_iter().map(digit).collect::<Option<_>>()?;
+    let mut mnc: String = [o3 & 0x0F, o3 >> 4].into_iter().map(digit).collect::<Option<_>>()?;
+    if o2 >> 4 != 0xF {
+        mnc.push(digit(o2 >> 4)?);
+    }
+    Some((mcc, mnc))
+}
+
+/// NumericString content: digits and space only.
+fn decode_numeric_string(b: &[u8]) -> Option<String> {
+    if !b.iter().all(|&c| c.is_ascii_digit() || c == b' ') {
+        return None;
+    }
+    Some(std::str::from_utf8(b).ok()?.to_string())
+}
+
+/// PrintableString content: letters, digits, space and `'()+,-./:=?` (X.680 41.4).
+fn decode_printable_string(b: &[u8]) -> Option<String> {
+    let printable = |c: &u8| c.is_ascii_alphanumeric() || b" '()+,-./:=?".contains(c);
+    if !b.iter().all(printable) {
+        return None;
+    }
+    Some(std::str::from_utf8(b).ok()?.to_string())
+}
+
+/// UniversalString content: UTF-32BE code points. `None` for a partial code point, a
+/// surrogate or anything above U+10FFFF.
+fn decode_universal_string(b: &[u8]) -> Option<String> {
+    if !b.len().is_multiple_of(4) {
+        return None;
+    }
+    b.chunks_exact(4)
+        .map(|c| char::from_u32(u32::from_be_bytes([c[0], c[1], c[2], c[3]])))
+        .collect()
+}
+
+/// GraphicString content: printable ASCII (0x20..=0x7E) plus the printable Latin-1 range
+/// (0xA0..=0xFF), read as ISO 8859-1. C0/C1 control bytes and DEL leave it as hex.
+fn decode_graphic_string(b: &[u8]) -> Option<String> {
+    if !b.iter().all(|&c| (0x20..=0x7E).contains(&c) || c >= 0xA0) {
+        return None;
+    }
+    Charset::Latin1.decode(b)
+}
+
+/// `--octet-as-text`: valid UTF-8 without control characters.
+fn decode_printable_utf8(b: &[u8]) -> Option<String> {
+    let s = std::str::from_utf8(b).ok()?;
+    if s.chars().any(char::is_control) {
+        return None;
+    }
+    Some(s.to_string())
+}
+
+/// Decodes primitive kinds that render as JSON strings; `None` means emit hex.
+fn decode_text_primitive(kind: &str, data: &[u8]) -> Option<String> {
+    match kind {
+        "VisibleString" => decode_visible_string(data),
+        "NumericString" => decode_numeric_string(data),
+        "PrintableString" => decode_printable_string(data),
+        // UTF-16BE; odd lengths and unpaired surrogates fall back to hex.
+        "BMPString" => Charset::Ucs2.decode(data),
+        "UniversalString" => decode_universal_string(data),
+        "GraphicString" => decode_graphic_string(data),
+        "DATE" => decode_date(data),
+        "TIME-OF-DAY" => decode_time_of_day(data),
+        "DATE-TIME" => decode_date_time(data),
+        "DURATION" => decode_duration(data),
+        "TIME" => decode_iso_time(data),
+        "GeneralizedTime" => decode_generalized_time(data),
+        "OBJECT IDENTIFIER" => decode_oid(data),
+        "RELATIVE-OID" => decode_relative_oid(data),
+        _ => None,
+    }
+}
+
+#[inline(always)]
+fn find_eoc(data: &[u8], mut off: usize) -> Option<usize> {
+    let mut depth: i32 = 1;
+    while off + 1 < data.len() {
+        if data[off] == 0x00 && data[off + 1] == 0x00 {
+            depth -= 1;
+            off += 2;
+            if depth == 0 {
+                return Some(off);
+            }
+            continue;
+        }
+
+        let start = off;
+        let tag_byte = *data.get(off)?;
+        off += 1;
+
+        let constructed = ((tag_byte >> 5) & 0x01) != 0;
+        let mut tag_num = (tag_byte & 0x1F) as u32;
+
+        if tag_num == 0x1F {
+            tag_num = 0;
+            while off < data.len() {
+                let b = data[off];
+                off += 1;
+                // Same overflow guard as `parse_tlv_header`.
+                if tag_num > u32::MAX >> 7 {
+                    return None;
+                }
+                tag_num = (tag_num << 7) | (b & 0x7F) as u32;
+                if (b & 0x80) == 0 {
+                    break;
+                }
+            }
+        }
+
+        let len_byte = *data.get(off)?;
+        off += 1;
+
+        if len_byte == 0x80 {
+            if !constructed {
+                return None;
+            }
+            depth += 1;
+            continue;
+        }
+
+        let len: usize;
+        if (len_byte & 0x80) != 0 {
+            let n = (len_byte & 0x7F) as usize;
+            // More length octets than a usize holds would shift the high ones out.
+            if n == 0 || n > std::mem::size_of::<usize>() || off + n > data.len() {
+                return None;
+            }
+            let mut l = 0usize;
+            for _ in 0..n {
+                l = (l << 8) | data[off] as usize;
+                off += 1;
+            }
+            len = l;
+        } else {
+            len = len_byte as usize;
+        }
+
+        if len > data.len() - off {
+            return None;
+        }
+        off += len;
+
+        if off <= start {
+            return None;
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    pub max_array_elements: usize,
+    pub bitstring_int: bool,
+    // BIT STRING rendering when `bitstring_int` is off
+    pub bitstring_format: BitStringFormat,
+    pub emit_null_optionals: bool,
+    // parent type -> field name -> forced type
+    pub type_overrides: HashMap<String, HashMap<String, String>>,
+    pub unwrap_depth: usize,
+    // SEQUENCE / SET / CHOICE / SEQUENCE OF nesting deeper than this becomes {"_error":"max_depth_exceeded"}
+    pub max_depth: usize,
+    pub track_coverage: bool,
+    pub unsigned_constrained: bool,
+    pub semantic: bool,
+    // words that mark an OCTET STRING field as an IP address under `semantic`
+    pub ip_name_patterns: Vec<String>,
+    pub number_format: NumberFormat,
+    pub special_floats: SpecialFloats,
+    // spaces per level for `--pretty` output; None writes compact JSON
+    pub pretty_indent: Option<usize>,
+    pub value_encoding: ValueEncoding,
+    // wrap each field as {"_tag":{...},"value":...} with the tag it was encoded with
+    pub with_tags: bool,
+    // add a "_raw" hex copy of the encoding to every SEQUENCE / SET / CHOICE object
+    pub with_raw: bool,
+    pub utctime_pivot: u16,
+    // parent type -> field name -> text encoding of its OCTET STRING content
+    pub charset_fields: HashMap<String, HashMap<String, Charset>>,
+    // report SEQUENCE components encoded out of declaration order
+    pub check_der: bool,
+    // reject records whose encoding DER forbids instead of decoding them
+    pub strict_der: bool,
+    // decode the structure of an unmatched constructed CHOICE value instead of dumping hex
+    pub generic_unknown: bool,
+    // render TBCD-STRING values as digit strings; None keeps them as hex
+    pub tbcd: Option<TbcdOptions>,
+    // render PLMN-Id values as MCC/MNC; None keeps them as hex
+    pub plmn_id: Option<PlmnFormat>,
+    // write GeneralizedTime/UTCTime as {"iso":...,"epoch":...} instead of the ISO string
+    pub timestamp_epoch: bool,
+    // add a "_warning" to objects whose fields break their SIZE or value-range constraint
+    pub validate_constraints: bool,
+    // how a component tag that occurs more than once in one SEQUENCE / SET is written
+    pub repeated_tags: RepeatedTags,
+    // write OCTET STRING content that is printable UTF-8 as a string instead of hex
+    pub octet_as_text: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            max_array_elements: DEFAULT_MAX_ARRAY_ELEMENTS,
+            bitstring_int: false,
+            bitstring_format: BitStringFormat::Named,
+            emit_null_optionals: false,
+            type_overrides: HashMap::new(),
+            unwrap_depth: DEFAULT_UNWRAP_DEPTH,
+            max_depth: DEFAULT_MAX_DEPTH,
+            track_coverage: false,
+            unsigned_constrained: false,
+            semantic: false,
+            ip_name_patterns: DEFAULT_IP_NAME_PATTERNS.iter().map(|s| s.to_string()).collect(),
+            number_format: NumberFormat::Number,
+            special_floats: SpecialFloats::String,
+            pretty_indent: None,
+            value_encoding: ValueEncoding::Hex,
+            with_tags: false,
+            with_raw: false,
+            utctime_pivot: DEFAULT_UTCTIME_PIVOT,
+            charset_fields: HashMap::new(),
+            check_der: false,
+            strict_der: false,
+            generic_unknown: false,
+            tbcd: None,
+            plmn_id: None,
+            timestamp_epoch: false,
+            validate_constraints: false,
+            repeated_tags: RepeatedTags::Suffix,
+            octet_as_text: false,
+        }
+    }
+}
+
+/// Which half of each TBCD octet holds the earlier digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum NibbleOrder {
+    /// Low nibble first, as in 3GPP TS 29.002 (MAP) and CAMEL
+    #[default]
+    LowFirst,
+    /// High nibble first, as in some ISUP-style and vendor encodings
+    HighFirst,
+}
+
+/// How BIT STRING values are written (unless `--bitstring-int` asks for the integer form).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BitStringFormat {
+    /// {"name":true,...} for types that declare named bits, hex for the rest
+    #[default]
+    Named,
+    /// Always hex, unused-bits octet included
+    Hex,
+    /// {"bits":"10110","length":5}, without the unused trailing bits
+    Bits,
+}
+
+/// How a component tag that occurs more than once in one SEQUENCE / SET is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RepeatedTags {
+    /// `field`, `field_2`, `field_3`, ... in encoding order
+    #[default]
+    Suffix,
+    /// One `field` array holding every occurrence, at the position of the first
+    Array,
+}
+
+/// How a decoded PLMN-Id is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlmnFormat {
+    /// "262-02"
+    String,
+    /// {"mcc":"262","mnc":"02"}
+    Object,
+}
+
+/// How TBCD-STRING content becomes digits. The default reads low nibble first and drops
+/// every nibble above 9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TbcdOptions {
+    pub nibble_order: NibbleOrder,
+    // a 0xF nibble ends the string instead of being dropped
+    pub stop_at_filler: bool,
+    // render nibbles 0xA-0xE as `*`, `#`, `a`, `b`, `c` instead of dropping them
+    pub extended_digits: bool,
+}
+
+/// How a `--force-type` override decodes a field's content.
+enum ForcedDecode<'a> {
+    Primitive(&'a [u8]),
+    Root(Tlv<'a>),
+    Constructed(&'a [u8]),
+    Opaque(&'a [u8]),
+}
+
+/// Tags already emitted in one SEQUENCE / SET object. Context tags below 64 (nearly all
+/// fields) live in a bitmask, so the common no-repeat case never allocates.
+#[derive(Default)]
+struct SeenTags {
+    context_low: u64,
+    other: Vec<TagKey>,
+    // tag -> occurrences so far, only for tags that repeated
+    repeats: Vec<(TagKey, u32)>,
+}
+
+impl SeenTags {
+    /// Records one occurrence of `key`; returns how many times it was seen before.
+    #[inline]
+    fn record(&mut self, key: TagKey) -> u32 {
+        if !self.contains(key) {
+            match key {
+                (2, n) if n < 64 => self.context_low |= 1 << n,
+                _ => self.other.push(key),
+            }
+            return 0;
+        }
+        match self.repeats.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                self.repeats.push((key, 1));
+                1
+            }
+        }
+    }
+
+    #[inline]
+    fn contains(&self, key: TagKey) -> bool {
+        match key {
+            (2, n) if n < 64 => self.context_low & (1 << n) != 0,
+            _ => self.other.contains(&key),
+        }
+    }
+}
+
+/// Result of scanning for the next root record.
+pub enum RootScan<'a> {
+    Record(Tlv<'a>, usize),
+    Truncated { offset: usize, need: usize },
+    End,
+}
+
+/// Decodes records against a schema it owns or borrows; see [`DerDecoder::new`] and
+/// [`DerDecoder::with_schema_ref`].
+pub struct DerDecoder<'s> {
+    pub schema: Cow<'s, Asn1Schema>,
+    pub opts: DecodeOptions,
+    pub coverage: Option<Coverage>,
+}
+
+impl DerDecoder<'static> {
+    pub fn new(schema: Asn1Schema, opts: DecodeOptions) -> Self {
+        DerDecoder::from_cow(Cow::Owned(schema), opts)
+    }
+}
+
+impl<'s> DerDecoder<'s> {
+    /// A decoder over a schema owned elsewhere, e.g. one shared by several decoders.
+    pub fn with_schema_ref(schema: &'s Asn1Schema, opts: DecodeOptions) -> Self {
+        DerDecoder::from_cow(Cow::Borrowed(schema), opts)
+    }
+
+    fn from_cow(schema: Cow<'s, Asn1Schema>, opts: DecodeOptions) -> Self {
+        let coverage = opts.track_coverage.then(|| Coverage::for_schema(&schema));
+        Self { schema, opts, coverage }
+    }
+}
+
+impl DerDecoder<'_> {
+    #[inline]
+    fn note_type(&self, rt: &str) {
+        if let Some(cov) = &self.coverage {
+            cov.note_type(rt);
+        }
+    }
+
+    #[inline]
+    fn note_field(&self, rt: &str, field: &str) {
+        if let Some(cov) = &self.coverage {
+            cov.note_field(rt, field);
+        }
+    }
+
+    /// Parses a tag and length at `offset` without requiring the content to be present.
+    #[inline(always)]
+    pub fn parse_tlv_header(&self, data: &[u8], mut offset: usize) -> Option<TlvHeader> {
+        let data_len = data.len();
+        if offset >= data_len {
+            return None;
+        }
+
+        let tag_byte = data[offset];
+        offset += 1;
+
+        let tag_class = (tag_byte >> 6) & 0x03;
+        let constructed = ((tag_byte >> 5) & 0x01) != 0;
+        let mut tag_num = (tag_byte & 0x1F) as u32;
+
+        if tag_num == 0x1F {
+            tag_num = 0;
+            while offset < data_len {
+                let b = data[offset];
+                offset += 1;
+                // Five continuation bytes already fill 35 bits; an overlong tag must not wrap
+                // around into a small number that happens to match a schema tag.
+                if tag_num > u32::MAX >> 7 {
+                    return None;
+                }
+                tag_num = (tag_num << 7) | (b & 0x7F) as u32;
+                if (b & 0x80) == 0 {
+                    break;
+                }
+            }
+            if offset >= data_len {
+                return None;
+            }
+        }
+
+        if offset >= data_len {
+            return None;
+        }
+
+        let length_byte = data[offset];
+        offset += 1;
+
+        let length = if length_byte == 0x80 {
+            if !constructed {
+                return None;
+            }
+            None
+        } else if (length_byte & 0x80) != 0 {
+            let num_octets = (length_byte & 0x7F) as usize;
+            // More length octets than a usize holds would shift the high ones out and leave a
+            // small, plausible length.
+            if num_octets == 0 || num_octets > std::mem::size_of::<usize>() || offset + num_octets > data_len {
+                return None;
+            }
+            let mut l: usize = 0;
+            let end_len = offset + num_octets;
+            while offset < end_len {
+                l = (l << 8) | data[offset] as usize;
+                offset += 1;
+            }
+            Some(l)
+        } else {
+            Some(length_byte as usize)
+        };
+
+        Some(TlvHeader {
+            tag_class,
+            constructed,
+            tag_num,
+            length,
+            content_start: offset,
+        })
+    }
+
+    #[inline(always)]
+    pub fn parse_tlv<'a>(&self, data: &'a [u8], offset: usize) -> Option<(Tlv<'a>, usize)> {
+        let start = offset;
+        let hdr = self.parse_tlv_header(data, offset)?;
+        let offset = hdr.content_start;
+
+        let length = match hdr.length {
+            Some(l) => l,
+            None => {
+                let eoc_end = find_eoc(data, offset)?;
+                let content_end = eoc_end.checked_sub(2)?;
+                let length = content_end.checked_sub(offset)?;
+                return Some((
+                    Tlv {
+                        tag_class: hdr.tag_class,
+                        constructed: hdr.constructed,
+                        tag_num: hdr.tag_num,
+                        length,
+                        value: &data[offset..content_end],
+                        raw: &data[start..eoc_end],
+                    },
+                    eoc_end,
+                ));
+            }
+        };
+
+        if length > data.len() - offset {
+            return None;
+        }
+
+        let end = offset + length;
+        Some((
+            Tlv {
+                tag_class: hdr.tag_class,
+                constructed: hdr.constructed,
+                tag_num: hdr.tag_num,
+                length,
+                value: &data[offset..end],
+                raw: &data[start..end],
+            },
+            end,
+        ))
+    }
+
+    fn choice_alt_matches_tlv(&self, alt_type: &str, tlv: &Tlv) -> bool {
+        let rt = self.schema.resolve_alias(alt_type);
+
+        if let Some((cls, tag)) = self.schema.type_outer_tag.get(rt) {
+            return tlv.tag_class == *cls && tlv.tag_num == *tag;
+        }
+
+        if let Some(sub_alts) = self.schema.choices.get(rt) {
+            if sub_alts.contains_key(&(tlv.tag_class, tlv.tag_num)) {
+                return true;
+            }
+        }
+
+        if self.schema.sequences.contains_key(rt) || self.schema.seq_of_types.contains_key(rt) {
+            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 16;
+        }
+        if self.schema.sets.contains_key(rt) || self.schema.set_of_types.contains_key(rt) {
+            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 17;
+        }
+        
+        // Match Universal tags
+        if let Some((cls, tag)) = self.schema.universal_tag_for_type(rt) {
+             if tlv.tag_class == cls && tlv.tag_num == tag {
+                 return true;
+             }
+        }
+
+        false
+    }
+
+    #[inline]
+    pub fn tlv_matches_root(&self, tlv: &Tlv, root_type: &str) -> bool {
+        let rt = self.schema.resolve_alias(root_type);
+
+        if let Some((cls, num)) = self.schema.type_outer_tag.get(rt) {
+            return tlv.tag_class == *cls && tlv.tag_num == *num;
+        }
+
+        if let Some(alts) = self.schema.choices.get(rt) {
+            if alts.contains_key(&(tlv.tag_class, tlv.tag_num)) {
+                return true;
+            }
+            for ((cls, tag), (_fname, ftype)) in alts {
+                if *cls == 3u8 && is_synth_choice_tag(*tag) && self.choice_alt_matches_tlv(ftype, tlv) {
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        if self.schema.sequences.contains_key(rt) || self.schema.seq_of_types.contains_key(rt) {
+            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 16;
+        }
+        if self.schema.sets.contains_key(rt) || self.schema.set_of_types.contains_key(rt) {
+            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 17;
+        }
+
+        self.schema.primitives.contains_key(rt)
+    }
+
+    pub fn find_next_root_tlv<'a>(&self, data: &'a [u8], mut start: usize, root_type: &str) -> Option<(Tlv<'a>, usize)> {
+        while start < data.len() {
+            if let Some((tlv, end)) = self.parse_tlv(data, start) {
+                if end > start && self.tlv_matches_root(&tlv, root_type) {
+                    return Some((tlv, end));
+                }
+            }
+            start += 1;
+        }
+        None
+    }
+
+    /// Like `find_next_root_tlv`, but also stops at a root-matching header whose declared
+    /// length runs past the end of `data`.
+    pub fn scan_root_tlv<'a>(&self, data: &'a [u8], mut start: usize, root_type: &str) -> RootScan<'a> {
+        while start < data.len() {
+            match self.parse_tlv(data, start) {
+                Some((tlv, end)) => {
+                    if end > start && self.tlv_matches_root(&tlv, root_type) {
+                        return RootScan::Record(tlv, end);
+                    }
+                }
+                None => {
+                    if let Some(need) = self.truncated_root_at(data, start, root_type) {
+                        return RootScan::Truncated { offset: start, need };
+                    }
+                }
+            }
+            start += 1;
+        }
+        RootScan::End
+    }
+
+    /// Total encoded size of a root record starting at `offset` whose definite length
+    /// exceeds the bytes left in `data`.
+    pub fn truncated_root_at(&self, data: &[u8], offset: usize, root_type: &str) -> Option<usize> {
+        let hdr = self.parse_tlv_header(data, offset)?;
+        let length = hdr.length?;
+        let header_len = hdr.content_start - offset;
+        if length <= data.len() - hdr.content_start {
+            return None;
+        }
+        let probe = Tlv {
+            tag_class: hdr.tag_class,
+            constructed: hdr.constructed,
+            tag_num: hdr.tag_num,
+            length,
+            value: &[],
+            raw: &data[offset..hdr.content_start],
+        };
+        self.tlv_matches_root(&probe, root_type).then(|| header_len.saturating_add(length))
+    }
+
+    /// `--strict-der`: the first DER rule broken inside `raw` (one complete TLV), in encoding
+    /// order, as the offset of the offending TLV (counted from `base`) and what is wrong with it.
+    pub fn strict_der_violation(&self, raw: &[u8], base: usize) -> Option<(usize, String)> {
+        // (next offset, end) of the TLV runs still to check; the innermost run is on top.
+        let mut pending = vec![(0usize, raw.len())];
+        while let Some((off, end)) = pending.pop() {
+            if off >= end {
+                continue;
+            }
+            let at = base + off;
+            let Some(hdr) = self.parse_tlv_header(&raw[..end], off) else {
+                return Some((at, "malformed TLV header".to_string()));
+            };
+            let Some(length) = hdr.length else {
+                return Some((at, "indefinite length".to_string()));
+            };
+            let Some(content_end) = hdr.content_start.checked_add(length).filter(|&e| e <= end) else {
+                return Some((at, format!("length {} runs past the enclosing value", length)));
+            };
+            if raw[off] & 0x1F == 0x1F && (hdr.tag_num < 31 || raw[off + 1] == 0x80) {
+                return Some((at, format!("tag number {} not encoded in the fewest octets", hdr.tag_num)));
+            }
+            // Short form below 128, otherwise 0x80|n followed by n octets without leading zeros.
+            let tag_octets = if raw[off] & 0x1F == 0x1F {
+                2 + raw[off + 1..hdr.content_start].iter().take_while(|b| *b & 0x80 != 0).count()
+            } else {
+                1
+            };
+            let length_octets = hdr.content_start - off - tag_octets;
+            let minimal = if length < 0x80 { 1 } else { 1 + (usize::BITS - length.leading_zeros()).div_ceil(8) as usize };
+            if length_octets != minimal {
+                return Some((
+                    at,
+                    format!("length {} encoded in {} octets where {} would do", length, length_octets, minimal),
+                ));
+            }
+            if hdr.tag_class == 0 {
+                if hdr.constructed && DER_PRIMITIVE_UNIVERSAL_TAGS.contains(&hdr.tag_num) {
+                    return Some((at, format!("constructed encoding of universal type {}", hdr.tag_num)));
+                }
+                if !hdr.constructed && matches!(hdr.tag_num, 16 | 17) {
+                    return Some((at, format!("primitive encoding of universal type {}", hdr.tag_num)));
+                }
+            }
+            pending.push((content_end, end));
+            if hdr.constructed {
+                pending.push((hdr.content_start, content_end));
+            }
+        }
+        None
+    }
+
+    #[inline]
+    /// `raw` is the whole encoding `data` came from, for `--with-raw`; CHOICEs find their own.
+    fn visit_type<S: OutputSink + ?Sized>(&self, data: &[u8], raw: &[u8], type_name: &str, depth: usize, sink: &mut S) -> Result<()> {
+        if depth >= self.opts.max_depth {
+            // A recursive schema type, or a CHOICE whose alternative matches its own TLV again,
+            // would otherwise recurse until the stack overflows.
+            sink.begin_object()?;
+            sink.field("_error", SinkValue::Str("max_depth_exceeded"))?;
+            return sink.end_object();
+        }
+        let rt = self.schema.resolve_alias(type_name);
+        self.note_type(rt);
+
+        if let Some(elem) = self.schema.seq_of_types.get(rt) {
+            return self.visit_sequence_of(data, elem, depth, sink);
+        }
+        if let Some(elem) = self.schema.set_of_types.get(rt) {
+            return self.visit_sequence_of(data, elem, depth, sink);
+        }
+
+        if let Some(alts) = self.schema.choices.get(rt) {
+            return self.visit_choice(data, rt, alts, depth, sink);
+        }
+        if let Some(fields) = self.schema.sequences.get(rt) {
+            return self.visit_sequence(data, raw, rt, fields, depth, sink);
+        }
+        if let Some(fields) = self.schema.sets.get(rt) {
+            return self.visit_sequence(data, raw, rt, fields, depth, sink);
+        }
+
+        self.visit_primitive(data, type_name, sink)
+    }
+
+    #[inline]
+    pub fn primitive_kind<'a>(&'a self, type_name: &'a str) -> &'a str {
+        self.schema.primitive_kind(type_name)
+    }
+
+    /// Emits primitive content according to its schema kind. Hex unless the kind has a decoder.
+    fn visit_primitive<S: OutputSink + ?Sized>(&self, data: &[u8], type_name: &str, sink: &mut S) -> Result<()> {
+        self.note_type(self.schema.resolve_alias(type_name));
+        if let Some(tbcd) = &self.opts.tbcd {
+            if self.schema.derives_from(type_name, "TBCD-STRING") {
+                return sink.value(SinkValue::Str(&decode_tbcd(data, tbcd)));
+            }
+        }
+        if let Some(format) = self.opts.plmn_id {
+            if self.schema.derives_from(type_name, "PLMN-Id") {
+                if let Some((mcc, mnc)) = decode_plmn_id(data) {
+                    return match format {
+                        PlmnFormat::String => sink.value(SinkValue::Str(&format!("{}-{}", mcc, mnc))),
+                        PlmnFormat::Object => {
+                            sink.begin_object()?;
+                            sink.field("mcc", SinkValue::Str(&mcc))?;
+                            sink.field("mnc", SinkValue::Str(&mnc))?;
+                            sink.end_object()
+                        }
+                    };
+                }
+            }
+        }
+        let kind = self.primitive_kind(type_name);
+        match kind {
+            "BIT STRING" if self.opts.bitstring_int => return self.visit_bit_string_obj(data, sink),
+            "BIT STRING" => match self.opts.bitstring_format {
+                BitStringFormat::Named => {
+                    if let Some(names) = self.schema.named_bits(type_name) {
+                        return self.visit_named_bits(data, names, sink);
+                    }
+                }
+                BitStringFormat::Bits => return self.visit_bit_digits(data, sink),
+                BitStringFormat::Hex => {}
+            },
+            "NULL" => return sink.value(SinkValue::Null),
+            // TBCD digits often happen to be printable bytes, so they never count as text.
+            "OCTET STRING" if self.opts.octet_as_text && !self.schema.derives_from(type_name, "TBCD-STRING") => {
+                if let Some(text) = decode_printable_utf8(data) {
+                    return sink.value(SinkValue::Str(&text));
+                }
+            }
+            "INTEGER" if self.opts.unsigned_constrained && self.schema.is_non_negative_int(type_name) => {
+                return self.visit_uint(data, sink);
+            }
+            "UTCTime" | "GeneralizedTime" if self.opts.timestamp_epoch => {
+                let iso = match kind {
+                    "UTCTime" => decode_utc_time(data, self.opts.utctime_pivot),
+                    _ => decode_generalized_time(data),
+                };
+                if let Some(iso) = iso {
+                    sink.begin_object()?;
+                    sink.field("iso", SinkValue::Str(&iso))?;
+                    if let Some(epoch) = iso_epoch(&iso) {
+                        sink.field("epoch", SinkValue::Int(epoch))?;
+                    }
+                    return sink.end_object();
+                }
+            }
+            "UTCTime" => {
+                if let Some(s) = decode_utc_time(data, self.opts.utctime_pivot) {
+                    return sink.value(SinkValue::Str(&s));
+                }
+            }
+            "REAL" => {
+                if let Some(v) = decode_real(data) {
+                    return sink.value(SinkValue::Real(v));
+                }
+            }
+            "ENUMERATED" => {
+                if let Some(v) = decode_int_i64(data) {
+                    return self.visit_enumerated(v, type_name, sink);
+                }
+            }
+            "INTEGER" => {
+                if let Some(v) = decode_int_i64(data) {
+                    return sink.value(SinkValue::Int(v));
+                }
+                // Beyond i64 no JSON number is safe, so the exact decimal goes out as a string.
+                if let Some(s) = decode_big_int(data) {
+                    return sink.value(SinkValue::Str(&s));
+                }
+            }
+            _ => {}
+        }
+        match decode_text_primitive(kind, data) {
+            Some(s) => sink.value(SinkValue::Str(&s)),
+            None => sink.value(SinkValue::Bytes(data)),
+        }
+    }
+
+    /// `--semantic`: a 4- or 16-byte OCTET STRING whose field name has an address-like word
+    /// renders as an IP address. Both conditions must hold, so a 4-byte counter stays hex.
+    fn semantic_ip(&self, field_name: &str, type_name: &str, data: &[u8]) -> Option<String> {
+        if !self.opts.semantic || !(data.len() == 4 || data.len() == 16) || self.primitive_kind(type_name) != "OCTET STRING" {
+            return None;
+        }
+        if !self.named_like_ip(field_name) {
+            return None;
+        }
+        format_ip(data)
+    }
+
+    /// Whether a word of `field_name` starts with one of the `--ip-name-patterns`.
+    fn named_like_ip(&self, field_name: &str) -> bool {
+        name_words(field_name)
+            .iter()
+            .any(|w| self.opts.ip_name_patterns.iter().any(|p| w.starts_with(p.as_str())))
+    }
+
+    /// ENUMERATED => {"value":N,"name":"..."}; `name` omitted for a value the schema doesn't list.
+    /// The content is a signed INTEGER encoding, so -1 and 0 come out as such.
+    fn visit_enumerated<S: OutputSink + ?Sized>(&self, v: i64, type_name: &str, sink: &mut S) -> Result<()> {
+        sink.begin_object()?;
+        sink.field("value", SinkValue::Int(v))?;
+        if let Some(name) = self.schema.named_ints(type_name).and_then(|m| m.get(&v)) {
+            sink.field("name", SinkValue::Str(name))?;
+        }
+        sink.end_object()
+    }
+
+    /// Schema name of an INTEGER value, for types with a `{ name(n), ... }` list.
+    fn integer_name(&self, type_name: &str, data: &[u8], unsigned: bool) -> Option<&str> {
+        if self.primitive_kind(type_name) != "INTEGER" {
+            return None;
+        }
+        let named = self.schema.named_ints(type_name)?;
+        let v = if unsigned {
+            i64::try_from(decode_uint_u64(data)?).ok()?
+        } else {
+            decode_int_i64(data)?
+        };
+        named.get(&v).map(String::as_str)
+    }
+
+    /// INTEGER known to be non-negative: content read as unsigned, a decimal string above 64 bits.
+    fn visit_uint<S: OutputSink + ?Sized>(&self, data: &[u8], sink: &mut S) -> Result<()> {
+        match decode_uint_u64(data) {
+            Some(v) => sink.value(SinkValue::UInt(v)),
+            None if data.is_empty() => sink.value(SinkValue::Bytes(data)),
+            None => sink.value(SinkValue::Str(&big_decimal(data))),
+        }
+    }
+
+    /// BIT STRING => {"valueHex":"...","unusedBits":N,"value":I}; `value` omitted above 64 bits.
+    fn visit_bit_string_obj<S: OutputSink + ?Sized>(&self, data: &[u8], sink: &mut S) -> Result<()> {
+        let (unused_bits, bits) = match data.split_first() {
+            Some((&u, rest)) => (u, rest),
+            None => (0u8, data),
+        };
+
+        sink.begin_object()?;
+        sink.field("valueHex", SinkValue::Bytes(bits))?;
+        sink.field("unusedBits", SinkValue::UInt(unused_bits as u64))?;
+        if bits.len() <= 8 && unused_bits < 8 {
+            let v = bits.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64) >> unused_bits;
+            sink.field("value", SinkValue::UInt(v))?;
+        }
+        sink.end_object()
+    }
+
+    /// BIT STRING => {"bits":"10110","length":5}: one digit per bit, MSB first, unused trailing
+    /// bits left out. Malformed content stays hex.
+    fn visit_bit_digits<S: OutputSink + ?Sized>(&self, data: &[u8], sink: &mut S) -> Result<()> {
+        let Some((&unused, bits)) = data.split_first().filter(|(&u, rest)| u < 8 && !(rest.is_empty() && u != 0)) else {
+            return sink.value(SinkValue::Bytes(data));
+        };
+        let len = bits.len() * 8 - unused as usize;
+        let digits: String = (0..len).map(|i| if bits[i / 8] & (0x80 >> (i % 8)) != 0 { '1' } else { '0' }).collect();
+
+        sink.begin_object()?;
+        sink.field("bits", SinkValue::Str(&digits))?;
+        sink.field("length", SinkValue::UInt(len as u64))?;
+        sink.end_object()
+    }
+
+    /// BIT STRING with named bits => {"name":true,...} for every declared bit, in bit order. Bit 0
+    /// is the most significant bit of the first octet; bits past the end are false. Under
+    /// `--with-raw` a `"_raw"` member keeps the content octets. Malformed content stays hex.
+    fn visit_named_bits<S: OutputSink + ?Sized>(&self, data: &[u8], names: &HashMap<u32, String>, sink: &mut S) -> Result<()> {
+        let Some((&unused, bits)) = data.split_first().filter(|(&u, rest)| u < 8 && !(rest.is_empty() && u != 0)) else {
+            return sink.value(SinkValue::Bytes(data));
+        };
+        let len = bits.len() as u64 * 8 - unused as u64;
+        let mut named: Vec<(&u32, &String)> = names.iter().collect();
+        named.sort_unstable();
+
+        sink.begin_object()?;
+        for (&bit, name) in named {
+            let set = (bit as u64) < len && bits[bit as usize / 8] & (0x80 >> (bit % 8)) != 0;
+            sink.field(name, SinkValue::Bool(set))?;
+        }
+        if self.opts.with_raw {
+            sink.field("_raw", SinkValue::Bytes(data))?;
+        }
+        sink.end_object()
+    }
+
+    fn visit_sequence<S: OutputSink + ?Sized>(
+        &self,
+        data: &[u8],
+        raw: &[u8],
+        type_name: &str,
+        field_spec: &HashMap<TagKey, FieldSpec>,
+        depth: usize,
+        sink: &mut S,
+    ) -> Result<()> {
+        let overrides = self.opts.type_overrides.get(type_name);
+        let charsets = self.opts.charset_fields.get(type_name);
+
+        sink.begin_object()?;
+        if self.opts.with_raw {
+            sink.field("_raw", SinkValue::Bytes(raw))?;
+        }
+        let mut offset = 0usize;
+        let mut seen = SeenTags::default();
+        // `--check-der`: DER keeps SEQUENCE (not SET) components in declaration order.
+        let check_order = self.opts.check_der && self.schema.sequences.contains_key(type_name);
+        let mut last_field: Option<&FieldSpec> = None;
+        let mut position = 0usize;
+        let mut order_violations: Vec<(&str, usize, &str)> = Vec::new();
+        let mut warnings: Vec<String> = Vec::new();
+        let repeated = (self.opts.repeated_tags == RepeatedTags::Array).then(|| self.repeated_tags(data));
+
+        while offset < data.len() {
+            let (tlv, new_off) = match self.parse_tlv(data, offset) {
+                Some(t) => t,
+                None => break,
+            };
+            if new_off <= offset {
+                break;
+            }
+
+            let key: TagKey = (tlv.tag_class, tlv.tag_num);
+            let repeat = seen.record(key);
+
+            if let Some(field) = field_spec.get(&key) {
+                self.note_field(type_name, &field.name);
+                if check_order {
+                    if let Some(prev) = last_field.filter(|prev| field.order < prev.order) {
+                        order_violations.push((&field.name, position, &prev.name));
+                    }
+                    last_field = Some(field);
+                }
+                if let Some(group) = repeated.as_ref().and_then(|r| r.get(&key)) {
+                    // `--repeated-tags array`: every occurrence goes into one array at the first one.
+                    // Named INTEGER labels go in a parallel `<field>Name` array, null where a value has none.
+                    if repeat == 0 {
+                        sink.key(&field.name)?;
+                        sink.begin_array()?;
+                        let mut value_names = Vec::with_capacity(group.len());
+                        for t in group {
+                            value_names.push(self.visit_field(field, t, &field.name, overrides, charsets, depth, &mut warnings, sink)?);
+                        }
+                        sink.end_array()?;
+                        if value_names.iter().any(Option::is_some) {
+                            sink.key(&format!("{}Name", field.name))?;
+                            sink.begin_array()?;
+                            for name in value_names {
+                                sink.value(name.map_or(SinkValue::Null, SinkValue::Str))?;
+                            }
+                            sink.end_array()?;
+                        }
+                    }
+                } else {
+                    // A tag that repeats (encoder quirk, extension) gets `field_2`, `field_3`, ...
+                    let key_name: Cow<str> = if repeat == 0 {
+                        Cow::Borrowed(&field.name)
+                    } else {
+                        Cow::Owned(format!("{}_{}", field.name, repeat + 1))
+                    };
+                    sink.key(&key_name)?;
+                    let value_name = self.visit_field(field, &tlv, &key_name, overrides, charsets, depth, &mut warnings, sink)?;
+                    if let Some(name) = value_name {
+                        sink.field(&format!("{}Name", key_name), SinkValue::Str(name))?;
+                    }
+                }
+            } else if let Some(group) = repeated.as_ref().and_then(|r| r.get(&key)) {
+                if repeat == 0 {
+                    sink.key(&format!("unknown_tag_{}_{}", tlv.tag_class, tlv.tag_num))?;
+                    sink.begin_array()?;
+                    for t in group {
+                        self.visit_unknown(t, sink)?;
+                    }
+                    sink.end_array()?;
+                }
+            } else {
+                let name = if repeat == 0 {
+                    format!("unknown_tag_{}_{}", tlv.tag_class, tlv.tag_num)
+                } else {
+                    format!("unknown_tag_{}_{}_{}", tlv.tag_class, tlv.tag_num, repeat + 1)
+                };
+                sink.key(&name)?;
+                self.visit_unknown(&tlv, sink)?;
+            }
+
+            offset = new_off;
+            position += 1;
+        }
+
+        if self.opts.emit_null_optionals {
+            let mut missing: Vec<(&TagKey, &FieldSpec)> = field_spec
+                .iter()
+                .filter(|(k, f)| !f.has_default && !seen.contains(**k))
+                .collect();
+            // Declaration order, as in `fields_in_order`, so the null members don't depend on tag numbers.
+            missing.sort_unstable_by_key(|(k, f)| (f.order, **k));
+
+            for (_, field) in missing {
+                sink.field(&field.name, SinkValue::Null)?;
+            }
+        }
+
+        if !warnings.is_empty() {
+            sink.field("_warning", SinkValue::Str(&warnings.join("; ")))?;
+        }
+
+        if !order_violations.is_empty() {
+            sink.key("__derViolations")?;
+            sink.begin_array()?;
+            for (field, at, after) in order_violations {
+                sink.begin_object()?;
+                sink.field("field", SinkValue::Str(field))?;
+                sink.field("position", SinkValue::UInt(at as u64))?;
+                sink.field("after", SinkValue::Str(after))?;
+                sink.end_object()?;
+            }
+            sink.end_array()?;
+        }
+
+        sink.end_object()
+    }
+
+    /// Component TLVs of a SEQUENCE / SET body grouped by tag, only for tags that occur more than once.
+    fn repeated_tags<'a>(&self, data: &'a [u8]) -> HashMap<TagKey, Vec<Tlv<'a>>> {
+        let mut groups: HashMap<TagKey, Vec<Tlv<'a>>> = HashMap::new();
+        let mut offset = 0usize;
+        while let Some((tlv, next)) = self.parse_tlv(data, offset).filter(|(_, next)| *next > offset) {
+            groups.entry((tlv.tag_class, tlv.tag_num)).or_default().push(tlv);
+            offset = next;
+        }
+        groups.retain(|_, g| g.len() > 1);
+        groups
+    }
+
+    /// A component whose tag the schema doesn't know: its content as hex.
+    fn visit_unknown<S: OutputSink + ?Sized>(&self, tlv: &Tlv, sink: &mut S) -> Result<()> {
+        if self.opts.with_tags {
+            self.begin_tagged(tlv, sink)?;
+            sink.value(SinkValue::Bytes(tlv.value))?;
+            sink.end_object()
+        } else {
+            sink.value(SinkValue::Bytes(tlv.value))
+        }
+    }
+
+    /// Writes the value of one SEQUENCE / SET component (its key is already written) and
+    /// returns the name of a named INTEGER value for the `<field>Name` sibling.
+    #[allow(clippy::too_many_arguments)]
+    fn visit_field<S: OutputSink + ?Sized>(
+        &self,
+        field: &FieldSpec,
+        tlv: &Tlv,
+        key_name: &str,
+        overrides: Option<&HashMap<String, String>>,
+        charsets: Option<&HashMap<String, Charset>>,
+        depth: usize,
+        warnings: &mut Vec<String>,
+        sink: &mut S,
+    ) -> Result<Option<&str>> {
+        if self.opts.with_tags {
+            self.begin_tagged(tlv, sink)?;
+        }
+        // Named INTEGER label, written as a `<field>Name` sibling after the value.
+        let mut value_name = None;
+
+        let resolved_field_type = self.schema.resolve_alias(&field.field_type);
+        let outer = tlv;
+        let tlv = self.explicit_inner(outer, field).unwrap_or_else(|| outer.clone());
+        if self.opts.validate_constraints && !tlv.constructed {
+            self.check_constraints(field, key_name, tlv.value, warnings);
+        }
+
+        if let Some(forced) = overrides.and_then(|m| m.get(&field.name)) {
+            self.visit_forced_type(outer, forced, depth + 1, sink)?;
+        } else if let Some(charset) = charsets.and_then(|m| m.get(&field.name)).filter(|_| !tlv.constructed) {
+            match charset.decode(tlv.value) {
+                Some(text) => sink.value(SinkValue::Str(&text))?,
+                None => sink.value(SinkValue::Bytes(tlv.value))?,
+            }
+        } else if field.is_sequence_of || field.is_set_of {
+            self.visit_sequence_of(tlv.value, &field.field_type, depth + 1, sink)?;
+        } else if self.schema.choices.contains_key(resolved_field_type) {
+            // A tagged CHOICE field is always EXPLICIT: the chosen alternative is the single TLV
+            // inside the wrapper. Hand over that TLV, not the wrapper, so a field tag that
+            // equals an alternative's tag (e.g. `f [1] C` with `C ::= CHOICE { a [1] .. }`)
+            // can't be mistaken for the alternative. Anything else keeps the raw encoding
+            // and lets `visit_choice` search its candidates.
+            let inner = if tlv.constructed {
+                self.parse_tlv(tlv.value, 0).filter(|(_, end)| *end == tlv.value.len()).map(|(inner, _)| inner)
+            } else {
+                None
+            };
+            let choice_raw = inner.map_or(tlv.raw, |t| t.raw);
+            self.visit_type(choice_raw, choice_raw, &field.field_type, depth + 1, sink)?;
+        } else if tlv.constructed {
+            if !self.visit_segmented_octets(&tlv, &field.field_type, sink)? {
+                self.visit_type(tlv.value, tlv.raw, &field.field_type, depth + 1, sink)?;
+            }
+        } else if self.opts.unsigned_constrained
+            && field.constraint.as_deref().is_some_and(is_non_negative_range)
+            && self.primitive_kind(&field.field_type) == "INTEGER"
+        {
+            self.visit_uint(tlv.value, sink)?;
+            value_name = self.integer_name(&field.field_type, tlv.value, true);
+        } else if let Some(ip) = self.semantic_ip(&field.name, &field.field_type, tlv.value) {
+            sink.value(SinkValue::Str(&ip))?;
+        } else {
+            self.visit_primitive(tlv.value, &field.field_type, sink)?;
+            // A named INTEGER keeps its number and adds the name as a `<field>Name` sibling.
+            let unsigned = self.opts.unsigned_constrained && self.schema.is_non_negative_int(&field.field_type);
+            value_name = self.integer_name(&field.field_type, tlv.value, unsigned);
+        }
+
+        if self.opts.with_tags {
+            sink.end_object()?;
+        }
+        Ok(value_name)
+    }
+
+    /// The TLV inside an EXPLICIT field's tag: the field's content must be exactly one TLV that
+    /// carries the tag of the field's type. CHOICE fields are unwrapped by the CHOICE logic, and
+    /// anything else (including IMPLICIT encodings under an EXPLICIT schema) is left as it is.
+    fn explicit_inner<'a>(&self, tlv: &Tlv<'a>, field: &FieldSpec) -> Option<Tlv<'a>> {
+        if field.tagging != Tagging::Explicit || !tlv.constructed {
+            return None;
+        }
+        let expected = if field.is_sequence_of {
+            (0, 16)
+        } else if field.is_set_of {
+            (0, 17)
+        } else {
+            self.schema.tag_for_type(&field.field_type)?
+        };
+        self.parse_tlv(tlv.value, 0)
+            .filter(|(inner, end)| *end == tlv.value.len() && (inner.tag_class, inner.tag_num) == expected)
+            .map(|(inner, _)| inner)
+    }
+
+    /// `--validate-constraints`: adds a message to `warnings` for each SIZE or value-range
+    /// constraint of `field` that its primitive content `data` breaks.
+    fn check_constraints(&self, field: &FieldSpec, key_name: &str, data: &[u8], warnings: &mut Vec<String>) {
+        let kind = self.primitive_kind(&field.field_type);
+        if let Some(size) = &field.size {
+            if let Some(len) = constrained_length(kind, data).filter(|&len| !size.allows(len)) {
+                warnings.push(format!("{}: length {} violates SIZE{}", key_name, len, size));
+            }
+        }
+        if let Some(range) = field.range.as_ref().filter(|_| kind == "INTEGER" && !data.is_empty()) {
+            // Read the value the way it is written out, i.e. unsigned under --unsigned-constrained.
+            let unsigned = self.opts.unsigned_constrained
+                && (field.constraint.as_deref().is_some_and(is_non_negative_range)
+                    || self.schema.is_non_negative_int(&field.field_type));
+            if !range.allows_content(data, unsigned) {
+                let value = if unsigned {
+                    decode_uint_u64(data).map_or_else(|| big_decimal(data), |v| v.to_string())
+                } else {
+                    decode_int_i64(data).map(|v| v.to_string()).or_else(|| decode_big_int(data)).unwrap_or_default()
+                };
+                warnings.push(format!("{}: value {} violates {}", key_name, value, range));
+            }
+        }
+    }
+
+    /// Decodes a field's content as a `--force-type` override instead of its declared type.
+    fn visit_forced_type<S: OutputSink + ?Sized>(&self, tlv: &Tlv, forced: &str, depth: usize, sink: &mut S) -> Result<()> {
+        match self.forced_decode(tlv, forced) {
+            ForcedDecode::Primitive(v) => self.visit_primitive(v, forced, sink),
+            ForcedDecode::Root(inner) => self.visit_root(&inner, forced, depth, sink),
+            ForcedDecode::Constructed(v) => self.visit_type(v, tlv.raw, forced, depth, sink),
+            ForcedDecode::Opaque(v) => sink.value(SinkValue::Bytes(v)),
+        }
+    }
+
+    fn forced_decode<'a>(&self, tlv: &Tlv<'a>, forced: &str) -> ForcedDecode<'a> {
+        let rt = self.schema.resolve_alias(forced);
+        if self.schema.primitives.contains_key(rt) || self.schema.universal_tag_for_type(rt).is_some_and(|(_, n)| n != 16 && n != 17) {
+            return ForcedDecode::Primitive(tlv.value);
+        }
+        // Content that is itself one complete encoding of the forced type (e.g. a SEQUENCE inside an OCTET STRING).
+        if let Some((inner, end)) = self.parse_tlv(tlv.value, 0) {
+            if end == tlv.value.len() {
+                return ForcedDecode::Root(inner);
+            }
+        }
+        if tlv.constructed {
+            return ForcedDecode::Constructed(tlv.value);
+        }
+        ForcedDecode::Opaque(tlv.value)
+    }
+
+    fn visit_sequence_of<S: OutputSink + ?Sized>(&self, data: &[u8], element_type: &str, depth: usize, sink: &mut S) -> Result<()> {
+        sink.begin_array()?;
+        let mut offset = 0usize;
+        let mut seen = 0usize;
+
+        let is_choice = self.schema.choices.contains_key(self.schema.resolve_alias(element_type));
+
+        while offset < data.len() {
+            let (tlv, new_off) = match self.parse_tlv(data, offset) {
+                Some(t) => t,
+                None => break,
+            };
+            if new_off <= offset {
+                break;
+            }
+
+            // Stop at the cap; the caller already knows where this container ends.
+            if seen >= self.opts.max_array_elements {
+                sink.begin_object()?;
+                sink.field("__truncated", SinkValue::Bool(true))?;
+                sink.field("__seen", SinkValue::UInt(seen as u64))?;
+                sink.end_object()?;
+                break;
+            }
+            seen += 1;
+
+            self.visit_collection_element(&tlv, element_type, is_choice, depth + 1, sink)?;
+
+            offset = new_off;
+        }
+
+        sink.end_array()
+    }
+
+    #[inline]
+    fn visit_collection_element<S: OutputSink + ?Sized>(
+        &self,
+        tlv: &Tlv,
+        element_type: &str,
+        is_choice: bool,
+        depth: usize,
+        sink: &mut S,
+    ) -> Result<()> {
+        if is_choice {
+            // For Sequence Of Choice, the items are direct choices.
+            // We pass `tlv.raw` because the tag we found (e.g. [1]) IS the choice tag.
+            self.visit_type(tlv.raw, tlv.raw, element_type, depth, sink)
+        } else if tlv.constructed {
+            if self.visit_segmented_octets(tlv, element_type, sink)? {
+                return Ok(());
+            }
+            self.visit_type(tlv.value, tlv.raw, element_type, depth, sink)
+        } else {
+            self.visit_primitive(tlv.value, element_type, sink)
+        }
+    }
+
+    /// A constructed TLV for an `OCTET STRING` type: BER segments (or an EXPLICIT wrapper),
+    /// i.e. universal OCTET STRING TLVs, possibly constructed themselves. Emits the concatenated
+    /// content and returns true; false if the content isn't made of such segments.
+    fn visit_segmented_octets<S: OutputSink + ?Sized>(&self, tlv: &Tlv, type_name: &str, sink: &mut S) -> Result<bool> {
+        if self.primitive_kind(type_name) != "OCTET STRING" {
+            return Ok(false);
+        }
+        let mut joined = Vec::with_capacity(tlv.value.len());
+        if !self.join_octet_segments(tlv.value, 0, &mut joined) {
+            return Ok(false);
+        }
+        self.visit_primitive(&joined, type_name, sink)?;
+        Ok(true)
+    }
+
+    fn join_octet_segments(&self, data: &[u8], depth: usize, out: &mut Vec<u8>) -> bool {
+        let mut offset = 0usize;
+        while offset < data.len() {
+            let Some((seg, next)) = self.parse_tlv(data, offset).filter(|(_, next)| *next > offset) else {
+                return false;
+            };
+            if (seg.tag_class, seg.tag_num) != (0, 4) {
+                return false;
+            }
+            if !seg.constructed {
+                out.extend_from_slice(seg.value);
+            } else if depth + 1 >= MAX_SEGMENT_DEPTH || !self.join_octet_segments(seg.value, depth + 1, out) {
+                return false;
+            }
+            offset = next;
+        }
+        true
+    }
+
+    /// Element type of a SEQUENCE OF / SET OF root, if the root is a collection.
+    pub fn container_element_type(&self, root_type: &str) -> Option<&str> {
+        let rt = self.schema.resolve_alias(root_type);
+        self.schema
+            .seq_of_types
+            .get(rt)
+            .or_else(|| self.schema.set_of_types.get(rt))
+            .map(|s| s.as_str())
+    }
+
+    /// Visits one output record: the root TLV itself, or an element of a `--container-root` collection.
+    pub fn visit_record<S: OutputSink + ?Sized>(
+        &self,
+        tlv: &Tlv,
+        root_type: &str,
+        container_elem: Option<&str>,
+        sink: &mut S,
+    ) -> Result<()> {
+        match container_elem {
+            Some(elem) => {
+                let is_choice = self.schema.choices.contains_key(self.schema.resolve_alias(elem));
+                self.visit_collection_element(tlv, elem, is_choice, 0, sink)
+            }
+            None => self.visit_root(tlv, root_type, 0, sink),
+        }
+    }
+
+    /// Writes one output record as compact JSON (no trailing newline).
+    #[inline]
+    pub fn write_record<W: Write>(
+        &self,
+        tlv: &Tlv,
+        root_type: &str,
+        container_elem: Option<&str>,
+        out: &mut W,
+        scratch: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut sink = JsonSink::with_scratch(out, std::mem::take(scratch))
+            .number_format(self.opts.number_format)
+            .special_floats(self.opts.special_floats)
+            .indent(self.opts.pretty_indent)
+            .value_encoding(self.opts.value_encoding);
+        let res = self.visit_record(tlv, root_type, container_elem, &mut sink);
+        *scratch = sink.into_scratch();
+        res
+    }
+
+    /// Decodes every root record in `data` to `out` as JSON Lines, without building any
+    /// intermediate values. Returns the number of records.
+    pub fn decode_to_writer<W: Write>(&self, data: &[u8], root_type: &str, out: &mut W) -> Result<usize> {
+        let mut scratch = Vec::new();
+        let mut offset = 0usize;
+        let mut count = 0usize;
+        while let Some((tlv, new_off)) = self.find_next_root_tlv(data, offset, root_type) {
+            self.write_record(&tlv, root_type, None, out, &mut scratch)
+                .with_context(|| format!("root record at offset {}", offset_in(data, tlv.raw)))?;
+            out.write_all(b"\n")?;
+            offset = new_off;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Peels one universal OCTET STRING layer if its content parses as a TLV.
+    #[inline]
+    fn unwrap_octet_string<'a>(&self, tlv: &Tlv<'a>) -> Option<Tlv<'a>> {
+        if tlv.tag_class == 0 && !tlv.constructed && tlv.tag_num == 4 && tlv.value.first().is_some_and(|&b| b != 0x00) {
+            return self.parse_tlv(tlv.value, 0).map(|(inner, _)| inner);
+        }
+        None
+    }
+
+    /// TLVs to try against CHOICE alternatives, in order: the outer TLV, the content of a
+    /// constructed (EXPLICIT) wrapper, then up to `unwrap_depth` nested OCTET STRING layers
+    /// below either of them (some TAP encodings double- or triple-wrap values).
+    fn choice_candidates<'s, 'a>(&'s self, outer: &Tlv<'a>) -> impl Iterator<Item = Tlv<'a>> + 's
+    where
+        'a: 's,
+    {
+        let explicit_inner = if outer.constructed {
+            self.parse_tlv(outer.value, 0).map(|(inner, _)| inner)
+        } else {
+            None
+        };
+        let first_layer = self
+            .unwrap_octet_string(outer)
+            .or_else(|| explicit_inner.as_ref().and_then(|t| self.unwrap_octet_string(t)));
+        let octet_layers = std::iter::successors(first_layer, move |cur| self.unwrap_octet_string(cur))
+            .take(self.opts.unwrap_depth);
+
+        std::iter::once(outer.clone()).chain(explicit_inner).chain(octet_layers)
+    }
+
+    /// Picks the CHOICE alternative matching `outer`: (field name, type name, bytes to decode it from).
+    fn select_choice_alt<'s, 'a>(
+        &'s self,
+        outer: &Tlv<'a>,
+        alts: &'s HashMap<TagKey, (String, String)>,
+    ) -> Option<(&'s str, &'s str, &'a [u8], &'a [u8])>
+    where
+        'a: 's,
+    {
+        // 1. Tagged CHOICE: direct match
+        for cand in self.choice_candidates(outer) {
+            if let Some((field_name, type_name)) = alts.get(&(cand.tag_class, cand.tag_num)) {
+                return Some((field_name, type_name, cand.value, cand.raw));
+            }
+        }
+
+        // 2. Untagged CHOICE (Synthetic)
+        let mut synth_keys: Vec<u32> = alts
+            .keys()
+            .filter(|(cls, tag)| *cls == 3u8 && is_synth_choice_tag(*tag))
+            .map(|(_, tag)| *tag)
+            .collect();
+        synth_keys.sort_unstable();
+
+        for k in synth_keys {
+            let (fname, ftype) = &alts[&(3u8, k)];
+            let f_rt = self.schema.resolve_alias(ftype);
+
+            for cand in self.choice_candidates(outer) {
+                if self.choice_alt_matches_tlv(ftype, &cand) {
+                    // A nested CHOICE needs its own tag to pick its alternative.
+                    let payload = if !self.schema.type_outer_tag.contains_key(f_rt) && self.schema.choices.contains_key(f_rt) {
+                        cand.raw
+                    } else {
+                        cand.value
+                    };
+                    return Some((fname, ftype, payload, cand.raw));
+                }
+            }
+        }
+        None
+    }
+
+    fn visit_choice<S: OutputSink + ?Sized>(
+        &self,
+        data: &[u8],
+        type_name: &str,
+        alts: &HashMap<TagKey, (String, String)>,
+        depth: usize,
+        sink: &mut S,
+    ) -> Result<()> {
+        let (outer, _) = match self.parse_tlv(data, 0) {
+            Some(t) => t,
+            None => return sink.value(SinkValue::Null),
+        };
+
+        sink.begin_object()?;
+        if self.opts.with_raw {
+            sink.field("_raw", SinkValue::Bytes(outer.raw))?;
+        }
+
+        if let Some((field_name, alt_type, payload, alt_raw)) = self.select_choice_alt(&outer, alts) {
+            self.note_field(type_name, field_name);
+            sink.key(field_name)?;
+            if self.opts.with_tags {
+                self.begin_tagged(&outer, sink)?;
+            }
+            match self.semantic_ip(field_name, alt_type, payload) {
+                Some(ip) => sink.value(SinkValue::Str(&ip))?,
+                None => self.visit_type(payload, alt_raw, alt_type, depth + 1, sink)?,
+            }
+            if self.opts.with_tags {
+                sink.end_object()?;
+            }
+        } else if self.opts.generic_unknown && outer.constructed {
+            sink.key("unknown_alternative")?;
+            self.visit_generic(&outer, 0, sink)?;
+        } else {
+            sink.field("unknown_alternative", SinkValue::Bytes(outer.raw))?;
+        }
+        sink.end_object()
+    }
+
+    /// `--with-tags`: opens `{"_tag":{"class":..,"num":..,"constructed":..},"value":` around a
+    /// field value as encoded in `tlv`. The caller writes the value, then closes the object.
+    fn begin_tagged<S: OutputSink + ?Sized>(&self, tlv: &Tlv, sink: &mut S) -> Result<()> {
+        sink.begin_object()?;
+        sink.key("_tag")?;
+        sink.begin_object()?;
+        sink.field("class", SinkValue::UInt(tlv.tag_class as u64))?;
+        sink.field("num", SinkValue::UInt(tlv.tag_num as u64))?;
+        sink.field("constructed", SinkValue::Bool(tlv.constructed))?;
+        sink.end_object()?;
+        sink.key("value")
+    }
+
+    /// Schema-less view of a TLV: constructed values become `{"field_0":...,"field_1":...}` in
+    /// encoding order, primitives and unparseable leftovers hex.
+    fn visit_generic<S: OutputSink + ?Sized>(&self, tlv: &Tlv, depth: usize, sink: &mut S) -> Result<()> {
+        if !tlv.constructed || depth >= MAX_GENERIC_DEPTH {
+            return sink.value(SinkValue::Bytes(tlv.value));
+        }
+        sink.begin_object()?;
+        let mut itoa_buf = itoa::Buffer::new();
+        let mut key = String::with_capacity(16);
+        let mut off = 0usize;
+        let mut idx = 0usize;
+        while off < tlv.value.len() {
+            key.clear();
+            key.push_str("field_");
+            key.push_str(itoa_buf.format(idx));
+            match self.parse_tlv(tlv.value, off) {
+                Some((child, next)) if next > off => {
+                    sink.key(&key)?;
+                    self.visit_generic(&child, depth + 1, sink)?;
+                    off = next;
+                }
+                _ => {
+                    sink.field(&key, SinkValue::Bytes(&tlv.value[off..]))?;
+                    break;
+                }
+            }
+            idx += 1;
+        }
+        sink.end_object()
+    }
+
+    fn visit_root<S: OutputSink + ?Sized>(&self, tlv: &Tlv, root_type: &str, depth: usize, sink: &mut S) -> Result<()> {
+        let rt = self.schema.resolve_alias(root_type);
+
+        if !self.schema.knows_type(rt) {
+            return Err(anyhow!("root-type '{}' not found in schema", root_type));
+        }
+
+        if self.schema.type_outer_tag.contains_key(rt) {
+            return self.visit_type(tlv.value, tlv.raw, root_type, depth, sink);
+        }
+
+        if self.schema.choices.contains_key(rt) {
+            self.visit_type(tlv.raw, tlv.raw, root_type, depth, sink)
+        } else {
+            self.visit_type(tlv.value, tlv.raw, root_type, depth, sink)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// DER TLV with a one-byte identifier and a minimal definite length.
+    fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if value.len() < 0x80 {
+            out.push(value.len() as u8);
+        } else {
+            let len = value.len().to_be_bytes();
+            let skip = len.iter().take_while(|&&b| b == 0).count();
+            out.push(0x80 | (len.len() - skip) as u8);
+            out.extend_from_slice(&len[skip..]);
+        }
+        out.extend_from_slice(value);
+        out
+    }
+
+    fn seq(items: &[&[u8]]) -> Vec<u8> {
+        tlv(0x30, &items.concat())
+    }
+
+    fn module(body: &str) -> String {
+        format!("Test DEFINITIONS IMPLICIT TAGS ::= BEGIN\n{}\nEND\n", body)
+    }
+
+    fn decoder_with(schema: &str, opts: DecodeOptions) -> DerDecoder<'static> {
+        DerDecoder::new(Asn1Schema::parse(schema, HashMap::new()).unwrap(), opts)
+    }
+
+    fn decode_with(schema: &str, root: &str, data: &[u8], opts: DecodeOptions) -> Vec<Value> {
+        let mut out = Vec::new();
+        decoder_with(schema, opts).decode_to_writer(data, root, &mut out).unwrap();
+        out.split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect()
+    }
+
+    fn decode(schema: &str, root: &str, data: &[u8]) -> Vec<Value> {
+        decode_with(schema, root, data, DecodeOptions::default())
+    }
+
+    fn field_names<'a>(schema: &'a Asn1Schema, t: &str) -> Vec<&'a str> {
+        schema.fields_in_order(t).unwrap().into_iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn block_comments_inside_a_body_are_not_fields() {
+        let schema = module(
+            "R ::= SEQUENCE {\n  first [0] INTEGER,\n  /* note */\n  /* bogus [5] INTEGER,\n     other [6] OCTET STRING */\n  second [1] OCTET STRING /* trailing, words */\n}",
+        );
+        let schema = Asn1Schema::parse(&schema, HashMap::new()).unwrap();
+        assert_eq!(field_names(&schema, "R"), ["first", "second"]);
+        assert!(schema.sequences["R"].keys().all(|&(_, num)| num < 2));
+    }
+
+    #[test]
+    fn bitstring_int_shifts_out_unused_bits() {
+        let schema = module("R ::= SEQUENCE {\n  flags [0] BIT STRING\n}");
+        let opts = || DecodeOptions { bitstring_int: true, ..Default::default() };
+        let data = [seq(&[&tlv(0x80, &[0x00, 0xA5])]), seq(&[&tlv(0x80, &[0x03, 0xA8])])].concat();
+        assert_eq!(
+            decode_with(&schema, "R", &data, opts()),
+            [
+                json!({"flags": {"valueHex": "a5", "unusedBits": 0, "value": 165}}),
+                json!({"flags": {"valueHex": "a8", "unusedBits": 3, "value": 21}}),
+            ]
+        );
+
+        // Nine content octets don't fit in 64 bits: the integer is left out.
+        let wide = seq(&[&tlv(0x80, &[0u8; 10])]);
+        assert_eq!(
+            decode_with(&schema, "R", &wide, opts()),
+            [json!({"flags": {"valueHex": "000000000000000000", "unusedBits": 0}})]
+        );
+    }
+
+    #[test]
+    fn context_tagged_alias_of_integer_decodes_as_a_number() {
+        let schema = module(
+            "SerialNumber ::= INTEGER\nSerial ::= SerialNumber\nR ::= SEQUENCE {\n  serial [1] SerialNumber,\n  other [2] Serial\n}",
+        );
+        let data = seq(&[&tlv(0x81, &[0x01, 0x2C]), &tlv(0x82, &[0xFF])]);
+        assert_eq!(decode(&schema, "R", &data), [json!({"serial": 300, "other": -1})]);
+    }
+
+    const SHORT_INPUT_SCHEMA: &str = "Test DEFINITIONS IMPLICIT TAGS ::= BEGIN
+R ::= SEQUENCE {
+  a [0] INTEGER,
+  b [1] OCTET STRING
+}
+C ::= CHOICE {
+  r [APPLICATION 1] R,
+  n [APPLICATION 2] INTEGER
+}
+END
+";
+
+    /// Runs `data` through every public decode path for both roots. Nothing may panic; returns
+    /// the records `decode_buffer` produced per root.
+    fn decode_every_path(decoder: &DerDecoder, data: &[u8]) -> [usize; 2] {
+        ["R", "C"].map(|root| {
+            let records = decode_buffer(&decoder.schema, root, data).unwrap();
+            let iterated: Vec<Result<&[u8]>> = decoder.records(data, root).collect();
+            let each = decoder.records(data, root).decode_each(|_| Ok(true));
+            assert_eq!(each.is_ok(), iterated.iter().all(Result::is_ok));
+            for offset in 0..=data.len() {
+                decoder.parse_tlv(data, offset);
+                decoder.truncated_root_at(data, offset, root);
+                find_eoc(data, offset);
+            }
+            records.len()
+        })
+    }
+
+    #[test]
+    fn short_inputs_never_panic() {
+        let decoder = decoder_with(SHORT_INPUT_SCHEMA, DecodeOptions::default());
+        assert_eq!(decode_every_path(&decoder, &[]), [0, 0]);
+        for a in 0..=255u8 {
+            assert_eq!(decode_every_path(&decoder, &[a]), [0, 0], "input {:02x}", a);
+            for b in 0..=255u8 {
+                decode_every_path(&decoder, &[a, b]);
+            }
+        }
+        // Three bytes: every tail after the identifiers that lead somewhere (SEQUENCE, the
+        // CHOICE tags, a long-form tag and a context tag).
+        for a in [0x30u8, 0x61, 0x42, 0x1F, 0x3F, 0x9F, 0xA0, 0x80] {
+            for b in 0..=255u8 {
+                for c in 0..=255u8 {
+                    decode_every_path(&decoder, &[a, b, c]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn short_inputs_decode_or_report_truncation() {
+        let decoder = decoder_with(SHORT_INPUT_SCHEMA, DecodeOptions::default());
+        assert_eq!(decode_every_path(&decoder, &[0x30, 0x00]), [1, 0]);
+        assert_eq!(decode_every_path(&decoder, &[0x42, 0x01, 0x07]), [0, 1]);
+        assert_eq!(decode_buffer(&decoder.schema, "C", &[0x42, 0x01, 0x07]).unwrap(), [json!({"n": 7})]);
+
+        // A root header whose length runs past the end is reported, not silently dropped.
+        let err = decoder.records(&[0x30, 0x02, 0x80], "R").decode_each(|_| Ok(true)).unwrap_err();
+        assert!(err.to_string().contains("truncated root record at offset 0"), "{}", err);
+        // An indefinite-length root without its end-of-contents octets is no record at all.
+        assert_eq!(decode_every_path(&decoder, &[0x30, 0x80, 0x00]), [0, 0]);
+    }
+
+    #[test]
+    fn length_with_more_octets_than_a_usize_is_rejected() {
+        let decoder = decoder_with(SHORT_INPUT_SCHEMA, DecodeOptions::default());
+        let octets = std::mem::size_of::<usize>() as u8;
+
+        // The widest length a usize holds still parses.
+        let mut fits = vec![0x04, 0x80 | octets];
+        fits.extend(std::iter::repeat_n(0, octets as usize - 1));
+        fits.extend_from_slice(&[0x05, b'h', b'e', b'l', b'l', b'o']);
+        assert_eq!(decoder.parse_tlv_header(&fits, 0).unwrap().length, Some(5));
+
+        // One more octet, with a set bit that would be shifted out, must not read as 5.
+        let mut overlong = vec![0x04, 0x80 | (octets + 1), 0x01];
+        overlong.extend(std::iter::repeat_n(0, octets as usize - 1));
+        overlong.extend_from_slice(&[0x05, b'h', b'e', b'l', b'l', b'o']);
+        assert!(decoder.parse_tlv_header(&overlong, 0).is_none());
+        assert!(decoder.parse_tlv(&overlong, 0).is_none());
+
+        // The same header inside an indefinite-length body goes through `find_eoc`.
+        let indefinite = [&[0x30, 0x80][..], &overlong, &[0x00, 0x00]].concat();
+        assert!(find_eoc(&indefinite, 2).is_none());
+        assert!(decoder.parse_tlv(&indefinite, 0).is_none());
+        assert_eq!(decode_every_path(&decoder, &indefinite), [0, 0]);
+    }
+
+    #[test]
+    fn decode_to_sink_reports_a_truncated_last_record() {
+        let decoder = decoder_with(SHORT_INPUT_SCHEMA, DecodeOptions::default());
+        let complete = seq(&[&tlv(0x80, &[0x01])]);
+        let mut out = Vec::new();
+        let count = decoder.decode_to_sink(&[complete.clone(), complete.clone()].concat(), "R", &mut JsonSink::new(&mut out));
+        assert_eq!(count.unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"a\":1}\n{\"a\":1}\n");
+
+        let truncated = [&complete[..], &[0x30, 0x05, 0x80]].concat();
+        let mut out = Vec::new();
+        let err = decoder.decode_to_sink(&truncated, "R", &mut JsonSink::new(&mut out)).unwrap_err();
+        assert!(err.to_string().contains("truncated root record at offset 5"), "{}", err);
+        // The complete record before it still went out.
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"a\":1}\n");
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn arrow_batch_reports_a_truncated_last_record() {
+        let decoder = decoder_with(SHORT_INPUT_SCHEMA, DecodeOptions::default());
+        let complete = seq(&[&tlv(0x80, &[0x01])]);
+        let batch = decoder.decode_to_arrow(&[complete.clone(), complete.clone()].concat(), "R").unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let truncated = [&complete[..], &[0x30, 0x05, 0x80]].concat();
+        let err = decoder.decode_to_arrow(&truncated, "R").unwrap_err();
+        assert!(err.to_string().contains("truncated root record at offset 5"), "{}", err);
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn arrow_batch_columns_come_from_the_asn1_schema() {
+        use arrow::array::{Array, BinaryArray, Int64Array, ListArray, StringArray};
+        use arrow::datatypes::DataType;
+
+        let schema = module(
+            "Rec ::= SEQUENCE {\n  id [0] INTEGER,\n  name [1] IA5String OPTIONAL,\n  data [2] OCTET STRING OPTIONAL,\n  ids [3] SEQUENCE OF INTEGER OPTIONAL,\n  child [4] Inner OPTIONAL,\n  next [5] Rec OPTIONAL\n}\nInner ::= SEQUENCE {\n  x [0] INTEGER\n}",
+        );
+        let decoder = decoder_with(&schema, DecodeOptions::default());
+        let data = [
+            seq(&[&tlv(0x80, &[0x07]), &tlv(0x82, &[0xDE, 0xAD]), &tlv(0xA3, &[tlv(0x02, &[0x01]), tlv(0x02, &[0x02])].concat())]),
+            seq(&[&tlv(0x80, &[0x08]), &tlv(0x81, b"x")]),
+        ]
+        .concat();
+        let batch = decoder.decode_to_arrow(&data, "Rec").unwrap();
+        let arrow_schema = batch.schema();
+        let columns: Vec<(&str, &DataType)> = arrow_schema.fields().iter().map(|f| (f.name().as_str(), f.data_type())).collect();
+        assert_eq!(columns.iter().map(|c| c.0).collect::<Vec<_>>(), ["id", "name", "data", "ids", "child", "next"]);
+        assert_eq!(columns[0].1, &DataType::Int64);
+        assert_eq!(columns[1].1, &DataType::Utf8);
+        assert_eq!(columns[2].1, &DataType::Binary);
+        assert!(matches!(columns[3].1, DataType::List(item) if item.data_type() == &DataType::Int64));
+        assert!(matches!(columns[4].1, DataType::Struct(members) if members[0].data_type() == &DataType::Int64));
+        // A type nested in itself stops at a JSON text column.
+        assert_eq!(columns[5].1, &DataType::Utf8);
+        assert!(arrow_schema.fields().iter().all(|f| f.is_nullable()));
+
+        let ids = batch.column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(ids.values(), &[7, 8]);
+        let names = batch.column(1).as_any().downcast_ref::<StringArray>().unwrap();
+        assert!(names.is_null(0));
+        assert_eq!(names.value(1), "x");
+        let octets = batch.column(2).as_any().downcast_ref::<BinaryArray>().unwrap();
+        assert_eq!(octets.value(0), [0xDE, 0xAD]);
+        assert!(octets.is_null(1));
+        let lists = batch.column(3).as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(lists.value(0).as_any().downcast_ref::<Int64Array>().unwrap().values(), &[1, 2]);
+        assert!(lists.is_null(1));
+        // No record carries `child`, and it still gets its column.
+        assert_eq!(batch.column(4).null_count(), 2);
+
+        // An empty buffer gives an empty batch with the same columns.
+        let empty = decoder.decode_to_arrow(&[], "Rec").unwrap();
+        assert_eq!(empty.num_rows(), 0);
+        assert_eq!(empty.schema(), arrow_schema);
+        assert_eq!(decoder.arrow_schema("Rec").unwrap(), *arrow_schema);
+
+        // A value the column can't hold is an error, not a panic.
+        let big = seq(&[&tlv(0x80, &[0x01; 9])]);
+        let err = decoder.decode_to_arrow(&big, "Rec").unwrap_err();
+        assert!(err.to_string().contains("is not a 64-bit integer"), "{}", err);
+        assert!(decoder.decode_to_arrow(&[], "INTEGER").is_err());
+    }
+
+    #[test]
+    fn application_and_context_tags_with_the_same_number_do_not_collide() {
+        let schema = module(
+            "Rec ::= CHOICE {\n  moCall [APPLICATION 5] Call,\n  smsCall [5] Sms\n}\nCall ::= SEQUENCE {\n  duration [0] INTEGER\n}\nSms ::= SEQUENCE {\n  text [0] OCTET STRING\n}",
+        );
+        let data = [tlv(0x65, &tlv(0x80, &[0x0A])), tlv(0xA5, &tlv(0x80, &[0x68, 0x69])), tlv(0x65, &tlv(0x80, &[0x14]))].concat();
+        assert_eq!(
+            decode(&schema, "Rec", &data),
+            [
+                json!({"moCall": {"duration": 10}}),
+                json!({"smsCall": {"text": "6869"}}),
+                json!({"moCall": {"duration": 20}}),
+            ]
+        );
+    }
+
+    #[test]
+    fn null_choice_alternative_is_json_null() {
+        let schema = module(
+            "Presence ::= CHOICE {\n  absent [0] NULL,\n  value [1] INTEGER\n}\nR ::= SEQUENCE {\n  presence [0] Presence\n}",
+        );
+        let data = [seq(&[&tlv(0xA0, &tlv(0x80, &[]))]), seq(&[&tlv(0xA0, &tlv(0x81, &[0x03]))])].concat();
+        assert_eq!(
+            decode(&schema, "R", &data),
+            [json!({"presence": {"absent": null}}), json!({"presence": {"value": 3}})]
+        );
+        assert_eq!(decode(&schema, "Presence", &tlv(0x80, &[])), [json!({"absent": null})]);
+    }
+
+    #[test]
+    fn semantic_mode_renders_address_fields_as_ip() {
+        let schema = module(
+            "R ::= SEQUENCE {\n  servingNodeAddress [0] OCTET STRING,\n  pdpIPAddress [1] OCTET STRING,\n  chargingId [2] OCTET STRING,\n  shipCount [3] OCTET STRING\n}",
+        );
+        let v6 = [0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01];
+        let data = seq(&[
+            &tlv(0x80, &[10, 0, 0, 1]),
+            &tlv(0x81, &v6),
+            &tlv(0x82, &[0xC0, 0xA8, 0x00, 0x01]),
+            &tlv(0x83, &[0, 0, 1, 0]),
+        ]);
+        let semantic = DecodeOptions { semantic: true, ..Default::default() };
+        assert_eq!(
+            decode_with(&schema, "R", &data, semantic),
+            [json!({
+                "servingNodeAddress": "10.0.0.1",
+                "pdpIPAddress": "2001:db8::1",
+                "chargingId": "c0a80001",
+                "shipCount": "00000100"
+            })]
+        );
+        // The name alone is not enough: a 5-byte address stays hex, and nothing changes without --semantic.
+        let odd = seq(&[&tlv(0x80, &[10, 0, 0, 1, 0])]);
+        let semantic = DecodeOptions { semantic: true, ..Default::default() };
+        assert_eq!(decode_with(&schema, "R", &odd, semantic), [json!({"servingNodeAddress": "0a00000100"})]);
+        assert_eq!(decode(&schema, "R", &data)[0]["servingNodeAddress"], "0a000001");
+    }
+
+    #[test]
+    fn automatic_tags_number_untagged_components_from_zero() {
+        let schema = "Auto DEFINITIONS AUTOMATIC TAGS ::= BEGIN\nR ::= SEQUENCE {\n  id INTEGER,\n  name OCTET STRING OPTIONAL,\n  count INTEGER\n}\nEND\n";
+        let parsed = Asn1Schema::parse(schema, HashMap::new()).unwrap();
+        assert_eq!(parsed.tag_default, TagDefault::Automatic);
+        let mut tags: Vec<(TagKey, &str)> = parsed.sequences["R"].iter().map(|(k, f)| (*k, f.name.as_str())).collect();
+        tags.sort();
+        assert_eq!(tags, [((2, 0), "id"), ((2, 1), "name"), ((2, 2), "count")]);
+
+        let data = seq(&[&tlv(0x80, &[0x07]), &tlv(0x81, b"ab"), &tlv(0x82, &[0x01, 0x00])]);
+        assert_eq!(decode(schema, "R", &data), [json!({"id": 7, "name": "6162", "count": 256})]);
+    }
+
+    #[test]
+    fn repeated_tag_in_one_sequence_gives_valid_json() {
+        let schema = module("R ::= SEQUENCE {\n  a [0] INTEGER,\n  c [1] OCTET STRING\n}");
+        let data = seq(&[&tlv(0x80, &[0x01]), &tlv(0x81, b"x"), &tlv(0x80, &[0x02]), &tlv(0x80, &[0x03])]);
+        // Compare the text: a JSON parser would quietly keep only the last of two duplicate keys.
+        let text = |repeated_tags| {
+            let mut out = Vec::new();
+            let opts = DecodeOptions { repeated_tags, ..Default::default() };
+            decoder_with(&schema, opts).decode_to_writer(&data, "R", &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(text(RepeatedTags::Suffix), "{\"a\":1,\"c\":\"78\",\"a_2\":2,\"a_3\":3}\n");
+        assert_eq!(text(RepeatedTags::Array), "{\"a\":[1,2,3],\"c\":\"78\"}\n");
+    }
+
+    #[test]
+    fn repeated_named_integers_get_a_parallel_name_array() {
+        let schema = module("R ::= SEQUENCE {\n  cause [0] Cause\n}\nCause ::= INTEGER { normal(0), busy(17) }");
+        let data = seq(&[&tlv(0x80, &[0x11]), &tlv(0x80, &[0x05]), &tlv(0x80, &[0x00])]);
+        let opts = DecodeOptions { repeated_tags: RepeatedTags::Array, ..Default::default() };
+        assert_eq!(
+            decode_with(&schema, "R", &data, opts),
+            [json!({"cause": [17, 5, 0], "causeName": ["busy", null, "normal"]})]
+        );
+    }
+
+    #[test]
+    fn application_tagged_root_choice_names_each_record() {
+        let schema = module(
+            "CallEventRecord ::= CHOICE {\n  moCallRecord [APPLICATION 20] MOCallRecord,\n  mtCallRecord [APPLICATION 21] MTCallRecord,\n  ssActionRecord [APPLICATION 24] SSActionRecord\n}\nMOCallRecord ::= SEQUENCE {\n  duration [0] INTEGER\n}\nMTCallRecord ::= SEQUENCE {\n  duration [0] INTEGER\n}\nSSActionRecord ::= SEQUENCE {\n  action [0] INTEGER\n}",
+        );
+        let data = [
+            tlv(0x74, &tlv(0x80, &[0x01])),
+            tlv(0x78, &tlv(0x80, &[0x02])),
+            tlv(0x75, &tlv(0x80, &[0x03])),
+            // Context tag 20 is not a root record.
+            tlv(0xB4, &tlv(0x80, &[0x04])),
+        ]
+        .concat();
+        assert_eq!(
+            decode(&schema, "CallEventRecord", &data),
+            [
+                json!({"moCallRecord": {"duration": 1}}),
+                json!({"ssActionRecord": {"action": 2}}),
+                json!({"mtCallRecord": {"duration": 3}}),
+            ]
+        );
+    }
+
+    #[test]
+    fn enumerated_names_resolve_for_zero_and_negative_values() {
+        let schema = module(
+            "Result ::= ENUMERATED { error(-1), ok(0), retry(2) }\nR ::= SEQUENCE {\n  result [0] Result\n}",
+        );
+        let data = [
+            seq(&[&tlv(0x80, &[0xFF])]),
+            seq(&[&tlv(0x80, &[0x00])]),
+            seq(&[&tlv(0x80, &[0x02])]),
+            seq(&[&tlv(0x80, &[0x7F])]),
+        ]
+        .concat();
+        assert_eq!(
+            decode(&schema, "R", &data),
+            [
+                json!({"result": {"value": -1, "name": "error"}}),
+                json!({"result": {"value": 0, "name": "ok"}}),
+                json!({"result": {"value": 2, "name": "retry"}}),
+                json!({"result": {"value": 127}}),
+            ]
+        );
+    }
+
+    #[test]
+    fn tagged_choice_field_with_and_without_explicit_wrapper() {
+        let schema = module(
+            "Status ::= CHOICE {\n  code [1] INTEGER,\n  text [2] OCTET STRING\n}\nOuter ::= CHOICE {\n  inner [0] Inner,\n  n [1] INTEGER\n}\nInner ::= CHOICE {\n  a [0] INTEGER,\n  b [1] OCTET STRING\n}\nR ::= SEQUENCE {\n  status [1] Status OPTIONAL,\n  nested [3] Outer OPTIONAL\n}",
+        );
+        let data = [
+            // EXPLICIT wrapper whose tag equals the tag of the alternative inside it.
+            seq(&[&tlv(0xA1, &tlv(0x81, &[0x05]))]),
+            seq(&[&tlv(0xA1, &tlv(0x82, b"hi"))]),
+            // No wrapper: the primitive field TLV is matched against the alternatives itself.
+            seq(&[&tlv(0x81, &[0x07])]),
+            // Two levels of CHOICE under one wrapper keep both alternative names.
+            seq(&[&tlv(0xA3, &tlv(0xA0, &tlv(0x81, b"x")))]),
+            seq(&[&tlv(0xA3, &tlv(0x81, &[0x09]))]),
+        ]
+        .concat();
+        assert_eq!(
+            decode(&schema, "R", &data),
+            [
+                json!({"status": {"code": 5}}),
+                json!({"status": {"text": "6869"}}),
+                json!({"status": {"code": 7}}),
+                json!({"nested": {"inner": {"b": "78"}}}),
+                json!({"nested": {"n": 9}}),
+            ]
+        );
+    }
+
+    #[test]
+    fn special_reals_follow_the_special_floats_policy() {
+        let schema = module("R ::= SEQUENCE {\n  v [0] REAL\n}");
+        let record = |content: &[u8]| seq(&[&tlv(0x80, content)]);
+        // 1.5 (binary, base 2), +inf, -inf, NaN, -0.
+        let contents: [&[u8]; 5] = [&[0x80, 0xFF, 0x03], &[0x40], &[0x41], &[0x42], &[0x43]];
+        let data: Vec<u8> = contents.iter().flat_map(|c| record(c)).collect();
+        let text = |special_floats| {
+            let mut out = Vec::new();
+            let opts = DecodeOptions { special_floats, ..Default::default() };
+            let res = decoder_with(&schema, opts).decode_to_writer(&data, "R", &mut out);
+            (res.map_err(|e| format!("{:#}", e)), String::from_utf8(out).unwrap())
+        };
+
+        let (res, out) = text(SpecialFloats::String);
+        assert_eq!(res, Ok(5));
+        assert_eq!(out, "{\"v\":1.5}\n{\"v\":\"Infinity\"}\n{\"v\":\"-Infinity\"}\n{\"v\":\"NaN\"}\n{\"v\":-0.0}\n");
+
+        let (res, out) = text(SpecialFloats::Null);
+        assert_eq!(res, Ok(5));
+        assert_eq!(out, "{\"v\":1.5}\n{\"v\":null}\n{\"v\":null}\n{\"v\":null}\n{\"v\":-0.0}\n");
+
+        // Every line written under either policy is strict JSON.
+        for policy in [SpecialFloats::String, SpecialFloats::Null] {
+            for line in text(policy).1.lines() {
+                serde_json::from_str::<Value>(line).unwrap();
+            }
+        }
+
+        // `error` fails on the first infinity, after the finite record before it.
+        let (res, out) = text(SpecialFloats::Error);
+        let err = res.unwrap_err();
+        assert!(err.contains("root record at offset 7") && err.contains("REAL value Infinity"), "{}", err);
+        assert!(out.starts_with("{\"v\":1.5}\n"));
+        // -0 is an ordinary JSON number, so even `error` writes it.
+        let opts = DecodeOptions { special_floats: SpecialFloats::Error, ..Default::default() };
+        assert_eq!(decode_with(&schema, "R", &record(&[0x43]), opts), [json!({"v": -0.0})]);
+    }
+
+    #[test]
+    fn overlong_tag_numbers_are_rejected() {
+        let decoder = decoder_with(SHORT_INPUT_SCHEMA, DecodeOptions::default());
+        // Continuation bytes past 32 bits would wrap the tag number around to [0].
+        let overlong = [0x9F, 0x90, 0x80, 0x80, 0x80, 0x80, 0x00, 0x01, 0x05];
+        let largest = [0x9F, 0x8F, 0xFF, 0xFF, 0xFF, 0x7F, 0x01, 0x05];
+        assert!(decoder.parse_tlv_header(&overlong, 0).is_none());
+        assert_eq!(decoder.parse_tlv_header(&largest, 0).unwrap().tag_num, u32::MAX);
+
+        // Inside an indefinite-length body the tag is read by `find_eoc`.
+        let indefinite = [&[0x30, 0x80][..], &overlong, &[0x00, 0x00]].concat();
+        assert!(find_eoc(&indefinite, 2).is_none());
+        assert!(decoder.parse_tlv(&indefinite, 0).is_none());
+        let indefinite_ok = [&[0x30, 0x80][..], &largest, &[0x00, 0x00]].concat();
+        assert_eq!(find_eoc(&indefinite_ok, 2), Some(indefinite_ok.len()));
+
+        // Without the guard the wrapped tag would be read as field `a [0]` with value 5.
+        let record = seq(&[&overlong]);
+        assert_eq!(decode_buffer(&decoder.schema, "R", &record).unwrap(), [json!({})]);
+    }
+
+    #[test]
+    fn visible_and_graphic_strings_decode_printable_text_only() {
+        let schema = module(
+            "R ::= SEQUENCE {\n  visible [0] VisibleString OPTIONAL,\n  graphic [1] GraphicString OPTIONAL\n}",
+        );
+        let data = [
+            seq(&[&tlv(0x80, b"Hello, World!"), &tlv(0x81, b"Hello, World!")]),
+            // 0xE9 is Latin-1 'é': printable GraphicString, but outside VisibleString's range.
+            seq(&[&tlv(0x80, b"caf\xE9"), &tlv(0x81, b"caf\xE9")]),
+            // Control bytes, DEL and the C1 range fall back to hex in both.
+            seq(&[&tlv(0x80, b"a\nb"), &tlv(0x81, b"a\nb")]),
+            seq(&[&tlv(0x80, b"a\x7F"), &tlv(0x81, b"\x85a")]),
+        ]
+        .concat();
+        assert_eq!(
+            decode(&schema, "R", &data),
+            [
+                json!({"visible": "Hello, World!", "graphic": "Hello, World!"}),
+                json!({"visible": "636166e9", "graphic": "café"}),
+                json!({"visible": "610a62", "graphic": "610a62"}),
+                json!({"visible": "617f", "graphic": "8561"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn octet_as_text_accepts_printable_utf8_only() {
+        let schema =
+            module("TBCD-STRING ::= OCTET STRING\nR ::= SEQUENCE {\n  v [0] OCTET STRING,\n  imsi [1] TBCD-STRING\n}");
+        let opts = || DecodeOptions { octet_as_text: true, ..Default::default() };
+        let record = |v: &[u8]| seq(&[&tlv(0x80, v), &tlv(0x81, &[0x21, 0x43])]);
+        let data =
+            [record(b"internet.mnc001"), record("Z\u{fc}rich".as_bytes()), record(b"a\nb"), record(&[0x00, 0xFF])].concat();
+        assert_eq!(
+            decode_with(&schema, "R", &data, opts()),
+            [
+                json!({"v": "internet.mnc001", "imsi": "2143"}),
+                json!({"v": "Zürich", "imsi": "2143"}),
+                json!({"v": "610a62", "imsi": "2143"}),
+                json!({"v": "00ff", "imsi": "2143"}),
+            ]
+        );
+        assert_eq!(decode(&schema, "R", &data)[0]["v"], "696e7465726e65742e6d6e63303031");
+    }
+
+    #[test]
+    fn named_integer_adds_a_name_sibling_when_mapped() {
+        let schema = module(
+            "Status ::= INTEGER { active(1), suspended(2) }\nR ::= SEQUENCE {\n  status [0] Status,\n  count [1] INTEGER\n}",
+        );
+        let record = |v: u8| seq(&[&tlv(0x80, &[v]), &tlv(0x81, &[v])]);
+        let data = [record(1), record(2), record(7)].concat();
+        assert_eq!(
+            decode(&schema, "R", &data),
+            [
+                json!({"status": 1, "statusName": "active", "count": 1}),
+                json!({"status": 2, "statusName": "suspended", "count": 2}),
+                json!({"status": 7, "count": 7}),
+            ]
+        );
+    }
+
+    // (encoded PLMN-Id, MCC, MNC) from 3GPP TS 24.008 10.5.1.3.
+    const PLMN_VECTORS: [([u8; 3], &str, &str); 5] = [
+        ([0x62, 0xF2, 0x20], "262", "02"),  // Germany, Vodafone: 2-digit MNC, filler 0xF
+        ([0x62, 0x02, 0x22], "262", "220"), // Germany, 3-digit MNC with a trailing 0
+        ([0x13, 0x00, 0x14], "310", "410"), // US, AT&T
+        ([0x13, 0x01, 0x84], "311", "480"), // US, Verizon
+        ([0x13, 0x00, 0x62], "310", "260"), // US, T-Mobile
+    ];
+
+    #[test]
+    fn plmn_id_nibbles_give_mcc_and_mnc() {
+        for (bytes, mcc, mnc) in PLMN_VECTORS {
+            assert_eq!(decode_plmn_id(&bytes), Some((mcc.to_string(), mnc.to_string())), "{:02x?}", bytes);
+        }
+        assert_eq!(decode_plmn_id(&[0x62, 0xF2]), None);
+        assert_eq!(decode_plmn_id(&[0x62, 0xF2, 0x20, 0x00]), None);
+        assert_eq!(decode_plmn_id(&[0x6A, 0xF2, 0x20]), None);
+    }
+
+    #[test]
+    fn plmn_id_renders_as_string_or_object() {
+        let schema = module(
+            "PLMN-Id ::= OCTET STRING (SIZE (3))\nServingPLMN ::= PLMN-Id\nR ::= SEQUENCE {\n  plmn [0] ServingPLMN\n}",
+        );
+        let data: Vec<u8> = PLMN_VECTORS.iter().flat_map(|(bytes, _, _)| seq(&[&tlv(0x80, bytes)])).collect();
+        let with = |format| DecodeOptions { plmn_id: Some(format), ..Default::default() };
+
+        let strings = decode_with(&schema, "R", &data, with(PlmnFormat::String));
+        let objects = decode_with(&schema, "R", &data, with(PlmnFormat::Object));
+        for (i, (_, mcc, mnc)) in PLMN_VECTORS.iter().enumerate() {
+            assert_eq!(strings[i], json!({"plmn": format!("{}-{}", mcc, mnc)}));
+            assert_eq!(objects[i], json!({"plmn": {"mcc": mcc, "mnc": mnc}}));
+        }
+        assert_eq!(strings.len(), PLMN_VECTORS.len());
+
+        // Without the flag, or with content that isn't a PLMN-Id, the value stays hex.
+        assert_eq!(decode(&schema, "R", &data)[0], json!({"plmn": "62f220"}));
+        let bad = seq(&[&tlv(0x80, &[0x62, 0xF2])]);
+        assert_eq!(decode_with(&schema, "R", &bad, with(PlmnFormat::Object)), [json!({"plmn": "62f2"})]);
+    }
+
+    #[test]
+    fn pathological_nesting_stops_at_max_depth() {
+        let schema = module(
+            "Node ::= SEQUENCE {\n  child [0] Node OPTIONAL,\n  leaf [1] INTEGER OPTIONAL\n}\nTree ::= SEQUENCE OF Tree",
+        );
+        // 5000 levels of a self-referential type, and 5000 levels of a SEQUENCE OF of itself.
+        let levels = 5000;
+        let mut node = tlv(0x81, &[0x01]);
+        for _ in 0..levels {
+            node = tlv(0xA0, &node);
+        }
+        let node = tlv(0x30, &node);
+        let mut tree = Vec::new();
+        for _ in 0..levels {
+            tree = tlv(0x30, &tree);
+        }
+
+        // Run on a thread with the CLI's 8 MiB worker stack (not the test harness's), so an
+        // unbounded recursion would abort the test binary.
+        let thread_schema = schema.clone();
+        let handle = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || {
+                let decoder = decoder_with(&thread_schema, DecodeOptions::default());
+                let text = |data: &[u8], root: &str| {
+                    let mut out = Vec::new();
+                    assert_eq!(decoder.decode_to_writer(data, root, &mut out).unwrap(), 1);
+                    String::from_utf8(out).unwrap()
+                };
+                (text(&node, "Node"), text(&tree, "Tree"))
+            })
+            .unwrap();
+        let (node_out, tree_out) = handle.join().unwrap();
+
+        // Depth 0 is the root; every level below it adds one `{"child":`.
+        let cut = "{\"_error\":\"max_depth_exceeded\"}";
+        let expected = format!("{}{}{}\n", "{\"child\":".repeat(DEFAULT_MAX_DEPTH), cut, "}".repeat(DEFAULT_MAX_DEPTH));
+        assert_eq!(node_out, expected);
+        let expected = format!("{}{}{}\n", "[".repeat(DEFAULT_MAX_DEPTH), cut, "]".repeat(DEFAULT_MAX_DEPTH));
+        assert_eq!(tree_out, expected);
+
+        // A lower --max-depth cuts off sooner and still gives valid JSON.
+        let node = [&[0x30, 0x0C, 0xA0, 0x0A, 0xA0, 0x08, 0xA0, 0x06, 0xA0, 0x04, 0xA0, 0x02][..], &[0x81, 0x00]].concat();
+        let opts = DecodeOptions { max_depth: 2, ..Default::default() };
+        assert_eq!(
+            decode_with(&schema, "Node", &node, opts),
+            [json!({"child": {"child": {"_error": "max_depth_exceeded"}}})]
+        );
+    }
+
+    #[test]
+    fn null_optionals_follow_declaration_order() {
+        let schema = module(
+            "R ::= SEQUENCE {\n  id [5] INTEGER,\n  zeta [9] INTEGER OPTIONAL,\n  alpha [1] INTEGER OPTIONAL,\n  mid [3] INTEGER OPTIONAL,\n  dflt [0] INTEGER DEFAULT 0\n}",
+        );
+        let mut out = Vec::new();
+        let opts = DecodeOptions { emit_null_optionals: true, ..Default::default() };
+        decoder_with(&schema, opts).decode_to_writer(&seq(&[&tlv(0x85, &[0x01])]), "R", &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"id\":1,\"zeta\":null,\"alpha\":null,\"mid\":null}\n");
+    }
+
+    #[test]
+    fn generated_rust_compiles_and_decodes_recursive_indefinite_input() {
+        let schema = module(
+            "Rec ::= SEQUENCE {\n  id [0] INTEGER,\n  name [1] IA5String OPTIONAL,\n  next [2] Rec OPTIONAL,\n  kids [3] SEQUENCE OF Node OPTIONAL,\n  expr [4] Expr OPTIONAL,\n  pair [5] A OPTIONAL\n}\nNode ::= SEQUENCE {\n  parent [0] Rec OPTIONAL\n}\nExpr ::= CHOICE {\n  num [0] INTEGER,\n  neg [1] Expr\n}\nA ::= SEQUENCE {\n  b [0] B OPTIONAL\n}\nB ::= SEQUENCE {\n  a [0] A OPTIONAL\n}",
+        );
+        let code = codegen::generate(&Asn1Schema::parse(&schema, HashMap::new()).unwrap(), "test").unwrap();
+        // Rec { id 1, next: Rec { id 2 } (indefinite length), expr: neg(neg(num 5)) }, itself indefinite.
+        let main = r#"
+#[allow(dead_code)]
+mod gen {
+    include!("gen.rs");
+}
+
+fn main() {
+    let data = [
+        0x30, 0x80, 0x80, 0x01, 0x01, 0xA2, 0x80, 0x80, 0x01, 0x02, 0x00, 0x00,
+        0xA4, 0x07, 0xA1, 0x05, 0xA1, 0x03, 0x80, 0x01, 0x05, 0x00, 0x00,
+    ];
+    let rec = gen::Rec::decode(&data).unwrap();
+    assert_eq!(rec.id, 1);
+    assert_eq!(rec.next.as_ref().map(|n| n.id), Some(2));
+    assert_eq!(rec.expr, Some(gen::Expr::Neg(Box::new(gen::Expr::Neg(Box::new(gen::Expr::Num(5)))))));
+    assert!(gen::Rec::decode(&data[..data.len() - 2]).is_err());
+}
+"#;
+        let dir = std::env::temp_dir().join(format!("asn1-codegen-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("gen.rs"), &code).unwrap();
+        std::fs::write(dir.join("main.rs"), main).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+        let build = std::process::Command::new(rustc)
+            .args(["--edition", "2021", "-o"])
+            .arg(dir.join("main"))
+            .arg(dir.join("main.rs"))
+            .output()
+            .unwrap();
+        let run = build.status.success().then(|| std::process::Command::new(dir.join("main")).output().unwrap());
+        // Cleaned up before the asserts, so a failure doesn't leave the directory behind.
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(build.status.success(), "{}", String::from_utf8_lossy(&build.stderr));
+        let run = run.unwrap();
+        assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    }
+}
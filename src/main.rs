@@ -1,990 +1,1477 @@
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use flate2::read::MultiGzDecoder;
 use memmap2::Mmap;
 use rayon::prelude::*;
-use regex::Regex;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
-use serde::{Serialize, Deserialize};
+
+use asn1_der_schema_fast::charset::Charset;
+use asn1_der_schema_fast::codegen;
+use asn1_der_schema_fast::output::{JsonlFileSink, RecordSink, StdoutSink};
+use asn1_der_schema_fast::sink::{CsvSink, JsonSink, NumberFormat, OutputSink, SinkValue, SoaSink, SpecialFloats, ValueEncoding};
+use asn1_der_schema_fast::{
+    load_universal_overrides, offset_in, BitStringFormat, standard_universal_tag, tag_notation, write_json_key, Asn1Schema, DecodeOptions, DerDecoder, NibbleOrder, PlmnFormat, RepeatedTags, RootScan,
+    TbcdOptions, Tlv, DEFAULT_IP_NAME_PATTERNS, DEFAULT_MAX_ARRAY_ELEMENTS, DEFAULT_MAX_DEPTH, DEFAULT_UNWRAP_DEPTH, DEFAULT_UTCTIME_PIVOT,
+};
 
 #[derive(Parser, Debug)]
 #[command(
     author,
     version,
-    about = "Ultra-fast ASN.1 DER/BER Decoder -> JSONL (schema-based, hex-only values)",
+    about = "Ultra-fast ASN.1 DER/BER Decoder -> JSONL (schema-based; numbers, text, times and OIDs decoded, other bytes as hex)",
     long_about = None
 )]
 struct Cli {
-    #[arg(long = "schema")]
-    schema: Option<PathBuf>,
+    // Text schema file(s); repeat the flag or give a comma-separated list to merge several modules
+    #[arg(long = "schema", value_delimiter = ',')]
+    schema: Vec<PathBuf>,
 
     // New flag: Path to save the compiled binary schema
     #[arg(long = "compile-schema")]
     compile_schema: Option<PathBuf>,
 
+    // Write Rust structs/enums with a decode() per schema type to this file
+    #[arg(long = "generate-rust")]
+    generate_rust: Option<PathBuf>,
+
     // New flag: Path to load a pre-compiled binary schema
     #[arg(long = "load-compiled")]
     load_compiled: Option<PathBuf>,
 
-    #[arg(long = "root-type")]
-    root_type: String,
+    #[arg(long = "root-type", required_unless_present = "validate_schema")]
+    root_type: Option<String>,
+
+    // Only parse and check the schema, print a summary and exit; no input is decoded
+    #[arg(long = "validate-schema")]
+    validate_schema: bool,
 
+    // Fail instead of warning when the schema references types it never defines or repeats a tag
+    #[arg(long = "strict-schema")]
+    strict_schema: bool,
+
+    // Root type to use for files in which --root-type never occurs
+    #[arg(long = "root-type-fallback")]
+    root_type_fallback: Option<String>,
+
+    // Where output files go; without it, output is written to stdout (only when the input is `-`)
     #[arg(long = "output-dir")]
-    output_dir: PathBuf,
+    output_dir: Option<PathBuf>,
 
     #[arg(long = "ext")]
     ext: Option<String>,
 
-    #[arg(required = true)]
-    inputs: Vec<PathBuf>,
-}
+    // Cap on elements decoded per SEQUENCE OF / SET OF (guards against corrupt lengths)
+    #[arg(long = "max-array-elements", default_value_t = DEFAULT_MAX_ARRAY_ELEMENTS)]
+    max_array_elements: usize,
 
-type TagKey = (u8, u32);
-const SYNTH_CHOICE_BASE: u32 = 0xFFFF_FF00;
+    // Treat a SEQUENCE OF / SET OF root as a container and emit one line per element
+    #[arg(long = "container-root")]
+    container_root: bool,
 
-#[inline]
-fn is_synth_choice_tag(t: u32) -> bool {
-    t >= SYNTH_CHOICE_BASE
-}
+    // Emit BIT STRING as {valueHex, unusedBits, value} with the integer value (<= 64 bits)
+    #[arg(long = "bitstring-int", conflicts_with = "bitstring_format")]
+    bitstring_int: bool,
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct FieldSpec {
-    name: String,
-    field_type: String,
-    #[allow(dead_code)]
-    optional: bool,
-    is_sequence_of: bool,
-    is_set_of: bool,
-}
+    // BIT STRING as named-bit booleans where the type declares them, always hex, or a 0/1 digit string
+    #[arg(long = "bitstring-format", value_enum, default_value_t = BitStringFormat::Named)]
+    bitstring_format: BitStringFormat,
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct Asn1Schema {
-    choices: HashMap<String, HashMap<TagKey, (String, String)>>,
-    sequences: HashMap<String, HashMap<TagKey, FieldSpec>>,
-    sets: HashMap<String, HashMap<TagKey, FieldSpec>>,
+    // Emit "<field>":null for declared SEQUENCE/SET fields absent from a record (DEFAULT fields excluded)
+    #[arg(long = "emit-null-optionals")]
+    emit_null_optionals: bool,
 
-    seq_of_types: HashMap<String, String>,
-    set_of_types: HashMap<String, String>,
+    // Reject root records whose total encoded size exceeds this many bytes
+    #[arg(long = "max-record-bytes")]
+    max_record_bytes: Option<usize>,
 
-    primitives: HashMap<String, String>,
-    aliases: HashMap<String, String>,
+    // Attach a synthetic "__id" to each record: per-file sequence, FNV-1a hash of the raw bytes, or byte offset
+    #[arg(long = "record-id", value_enum)]
+    record_id: Option<RecordId>,
 
-    type_outer_tag: HashMap<String, TagKey>,
-}
+    // Decode a field's content as another type: Parent.field=ChildType (repeatable)
+    #[arg(long = "force-type")]
+    force_type: Vec<String>,
 
-#[inline]
-fn tag_class_from_word(word: Option<&str>) -> u8 {
-    match word.map(|s| s.to_ascii_uppercase()) {
-        Some(w) if w == "APPLICATION" => 1,
-        Some(w) if w == "UNIVERSAL" => 0,
-        Some(w) if w == "PRIVATE" => 3,
-        Some(w) if w == "CONTEXT" || w == "CONTEXT-SPECIFIC" || w == "CONTEXTSPECIFIC" => 2,
-        None => 2, // Default to Context-Specific if only a number is given [x]
-        _ => 2,
-    }
-}
+    // Decode a field's bytes as legacy text: Parent.field=gsm7|ucs2|latin1 (repeatable)
+    #[arg(long = "charset-field")]
+    charset_field: Vec<String>,
 
-impl Asn1Schema {
-    fn parse(schema_text: &str) -> Result<Self> {
-        let snacc_directive_re = Regex::new(r"(?is)--\s*snacc\b.*?--")?;
-        let comment_strip_re = Regex::new(r"(?m)--.*?$")?;
-        let no_snacc = snacc_directive_re.replace_all(schema_text, " ");
-        let stripped = comment_strip_re.replace_all(&no_snacc, "");
-
-        // Updated regex to handle (IMPLICIT|EXPLICIT) and any identifier type
-        let type_assign_re = Regex::new(
-            r"(?s)([\w-]+)\s*::=\s*(?:\[\s*(?:(APPLICATION|UNIVERSAL|PRIVATE|CONTEXT|CONTEXT-SPECIFIC)\s+)?(\d+)\s*\]\s*)?(?:IMPLICIT|EXPLICIT)?\s*(CHOICE|SEQUENCE|SET|ENUMERATED|INTEGER|OCTET STRING|BIT STRING|IA5String|UTF8String|BOOLEAN|NULL|TBCD-STRING|OBJECT IDENTIFIER|[\w-]+)\s*(?:OF\s+([\w-]+))?\s*(?:\(([^)]*)\))?\s*(\{.*?\})?",
-        )?;
-
-        let alias_re = Regex::new(r"(?m)^\s*([\w-]+)\s*::=\s*([\w-]+)\s*$")?;
-
-        // Updated choice regex to allow 0 whitespace before '[' e.g. "sIP-URI[0]"
-        let choice_tagged_re = Regex::new(
-            r"([\w-]+)\s*\[\s*(?:(APPLICATION|UNIVERSAL|PRIVATE|CONTEXT|CONTEXT-SPECIFIC)\s+)?(\d+)\s*\]\s*([\w-]+)",
-        )?;
-        let choice_untagged_re = Regex::new(r"([\w-]+)\s+([\w-]+)")?;
-
-        // Updated field regex to handle optional IMPLICIT/EXPLICIT and tags
-        let field_re = Regex::new(
-            r"(?m)^\s*([\w-]+)\s*(?:\[\s*(?:(APPLICATION|UNIVERSAL|PRIVATE|CONTEXT|CONTEXT-SPECIFIC)\s+)?(\d+)\s*\])?\s*(?:IMPLICIT|EXPLICIT)?\s+((?:SET|SEQUENCE)\s+OF\s+[\w-]+|[\w-]+)\s*(?:DEFAULT\s+[^,\n]+)?\s*(OPTIONAL)?",
-        )?;
-        
-        // Handle COMPONENTS OF (simple inheritance)
-        let components_of_re = Regex::new(r"(?m)^\s*COMPONENTS\s+OF\s+([\w-]+)")?;
-
-        let mut schema = Asn1Schema::default();
-
-        // 1. Parse Aliases
-        for cap in alias_re.captures_iter(&stripped) {
-            let lhs = cap.get(1).unwrap().as_str().to_string();
-            let rhs = cap.get(2).unwrap().as_str().to_string();
-            let rhs_upper = rhs.to_ascii_uppercase();
-            // Filter out keywords
-            let is_keyword = matches!(
-                rhs_upper.as_str(),
-                "CHOICE" | "SEQUENCE" | "SET" | "ENUMERATED" | "INTEGER" | "OCTET" | "BIT" 
-                | "IA5STRING" | "UTF8STRING" | "BOOLEAN" | "NULL" | "OBJECT" | "IDENTIFIER" | "BEGIN" | "END"
-            );
-            if !is_keyword && lhs != rhs {
-                schema.aliases.insert(lhs, rhs);
-            }
-        }
+    // Flag DER violations in each record (SEQUENCE components out of declaration order)
+    #[arg(long = "check-der")]
+    check_der: bool,
 
-        #[derive(Clone)]
-        struct Def {
-            type_name: String,
-            type_kind: String,
-            of_type: Option<String>,
-            body: String,
-        }
-        let mut defs: Vec<Def> = Vec::new();
-
-        // 2. Parse Type Definitions
-        for caps in type_assign_re.captures_iter(&stripped) {
-            let type_name = caps.get(1).unwrap().as_str().to_string();
-            let tag_class_word = caps.get(2).map(|m| m.as_str());
-            let tag_num_opt = caps.get(3).map(|m| m.as_str());
-            let type_kind = caps.get(4).unwrap().as_str().trim().to_string();
-            let of_type = caps.get(5).map(|m| m.as_str().to_string());
-            let body = caps.get(7).map(|m| m.as_str()).unwrap_or("").to_string();
-
-            if let Some(tag_num_str) = tag_num_opt {
-                if let Ok(num) = tag_num_str.parse::<u32>() {
-                    let cls = tag_class_from_word(tag_class_word);
-                    schema.type_outer_tag.insert(type_name.clone(), (cls, num));
-                }
-            }
+    // Add a "_warning" when a field breaks its SIZE or INTEGER range constraint
+    #[arg(long = "validate-constraints")]
+    validate_constraints: bool,
 
-            match type_kind.as_str() {
-                "CHOICE" | "SEQUENCE" | "SET" => {}
-                kind => {
-                    schema.primitives.insert(type_name.clone(), kind.to_string());
-                }
-            }
+    // Write OCTET STRINGs whose content is printable UTF-8 as strings; anything else stays hex
+    #[arg(long = "octet-as-text")]
+    octet_as_text: bool,
 
-            defs.push(Def {
-                type_name,
-                type_kind,
-                of_type,
-                body,
-            });
-        }
+    // How a tag repeated within one SEQUENCE / SET is written: field, field_2, ... or one array
+    #[arg(long = "repeated-tags", value_enum, default_value_t = RepeatedTags::Suffix)]
+    repeated_tags: RepeatedTags,
 
-        let mut components_queue: Vec<(String, String)> = Vec::new();
-
-        // 3. Process Structures
-        for d in defs {
-            match d.type_kind.as_str() {
-                "SEQUENCE" | "SET" => {
-                    let is_set = d.type_kind == "SET";
-                    if let Some(elem) = d.of_type.clone() {
-                        if is_set {
-                            schema.set_of_types.insert(d.type_name, elem);
-                        } else {
-                            schema.seq_of_types.insert(d.type_name, elem);
-                        }
-                        continue;
-                    }
+    // Report records that use BER-only encodings (indefinite lengths, ...) as malformed
+    #[arg(long = "strict-der")]
+    strict_der: bool,
 
-                    let mut fields: HashMap<TagKey, FieldSpec> = HashMap::new();
-                    for c in field_re.captures_iter(&d.body) {
-                        let field_name = c.get(1).unwrap().as_str().to_string();
-                        let cls_word = c.get(2).map(|m| m.as_str());
-                        let tag_opt = c.get(3).map(|m| m.as_str());
-                        let type_spec = c.get(4).unwrap().as_str().trim().to_string();
-                        let optional = c.get(5).is_some();
-
-                        let mut is_sequence_of = false;
-                        let mut is_set_of = false;
-                        let mut element_type = type_spec.clone();
-
-                        if let Some(rest) = type_spec.strip_prefix("SEQUENCE OF ") {
-                            is_sequence_of = true;
-                            element_type = rest.trim().to_string();
-                        } else if let Some(rest) = type_spec.strip_prefix("SET OF ") {
-                            is_set_of = true;
-                            element_type = rest.trim().to_string();
-                        }
+    // Show an unmatched constructed CHOICE value as {"field_0":...} instead of hex
+    #[arg(long = "generic-unknown")]
+    generic_unknown: bool,
 
-                        let key: TagKey = if let Some(tag_str) = tag_opt {
-                            let cls = tag_class_from_word(cls_word);
-                            (cls, tag_str.parse::<u32>()?)
-                        } else {
-                            match schema.tag_for_type(&element_type) {
-                                Some(tk) => tk,
-                                None => continue,
-                            }
-                        };
-
-                        fields.insert(
-                            key,
-                            FieldSpec {
-                                name: field_name,
-                                field_type: element_type,
-                                optional,
-                                is_sequence_of,
-                                is_set_of,
-                            },
-                        );
-                    }
-                    
-                    for c in components_of_re.captures_iter(&d.body) {
-                        let source_type = c.get(1).unwrap().as_str().to_string();
-                        components_queue.push((d.type_name.clone(), source_type));
-                    }
+    // Max nested OCTET STRING layers peeled when matching CHOICE alternatives
+    #[arg(long = "unwrap-depth", default_value_t = DEFAULT_UNWRAP_DEPTH)]
+    unwrap_depth: usize,
 
-                    if is_set {
-                        schema.sets.insert(d.type_name, fields);
-                    } else {
-                        schema.sequences.insert(d.type_name, fields);
-                    }
-                }
-                "CHOICE" => {
-                    let mut alts: HashMap<TagKey, (String, String)> = HashMap::new();
-
-                    for c in choice_tagged_re.captures_iter(&d.body) {
-                        let field_name = c.get(1).unwrap().as_str().to_string();
-                        let cls_word = c.get(2).map(|m| m.as_str());
-                        let tag: u32 = c.get(3).unwrap().as_str().parse()?;
-                        let field_type = c.get(4).unwrap().as_str().to_string();
-                        let cls = tag_class_from_word(cls_word);
-                        alts.insert((cls, tag), (field_name, field_type));
-                    }
+    // Nesting levels decoded before a subtree is replaced by {"_error":"max_depth_exceeded"}
+    #[arg(long = "max-depth", default_value_t = DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
 
-                    if alts.is_empty() {
-                        let mut idx: u32 = 0;
-                        for c in choice_untagged_re.captures_iter(&d.body) {
-                            let field_name = c.get(1).unwrap().as_str().to_string();
-                            let field_type = c.get(2).unwrap().as_str().to_string();
-                            if field_name == "isPdu" || field_name == "TRUE" { continue; }
-                            if !field_name.is_empty() && !field_type.is_empty() {
-                                alts.insert((3u8, SYNTH_CHOICE_BASE + idx), (field_name, field_type));
-                                idx += 1;
-                            }
-                        }
-                    }
+    // Attach "__decodeNanos" (time spent decoding the record) to each record
+    #[arg(long = "emit-timing")]
+    emit_timing: bool,
 
-                    schema.choices.insert(d.type_name, alts);
-                }
-                _ => {}
-            }
-        }
-        
-        // 4. Resolve COMPONENTS OF
-        for (target, source) in components_queue {
-            let source_fields = if let Some(f) = schema.sequences.get(&source) {
-                Some(f.clone())
-            } else if let Some(f) = schema.sets.get(&source) {
-                Some(f.clone())
-            } else {
-                None
-            };
-            
-            if let Some(src) = source_fields {
-                if let Some(tgt) = schema.sequences.get_mut(&target) {
-                    tgt.extend(src);
-                } else if let Some(tgt) = schema.sets.get_mut(&target) {
-                    tgt.extend(src);
-                }
-            }
-        }
+    // Wrap each field's value as {"_tag":{"class":..,"num":..,"constructed":..},"value":...}
+    #[arg(long = "with-tags")]
+    with_tags: bool,
 
-        Ok(schema)
-    }
+    // Add a "_raw" copy of the encoding to every decoded SEQUENCE, SET and CHOICE
+    #[arg(long = "with-raw")]
+    with_raw: bool,
 
-    #[inline]
-    fn resolve_alias<'a>(&'a self, mut t: &'a str) -> &'a str {
-        for _ in 0..32 {
-            if let Some(next) = self.aliases.get(t) {
-                t = next;
-            } else {
-                break;
-            }
-        }
-        t
-    }
+    // Wrap each record as {"offset":N,"record":{...}} with the file offset of its TLV
+    #[arg(long = "with-offset")]
+    with_offset: bool,
 
-    #[inline]
-    fn knows_type(&self, t: &str) -> bool {
-        let rt = self.resolve_alias(t);
-        self.choices.contains_key(rt)
-            || self.sequences.contains_key(rt)
-            || self.sets.contains_key(rt)
-            || self.seq_of_types.contains_key(rt)
-            || self.set_of_types.contains_key(rt)
-            || self.primitives.contains_key(rt)
-    }
+    // Check every input is readable, non-empty and starts with a root-type tag before decoding
+    #[arg(long = "preflight")]
+    preflight: bool,
 
-    #[inline]
-    fn tag_for_type(&self, t: &str) -> Option<TagKey> {
-        let rt = self.resolve_alias(t);
-        if let Some(tk) = self.type_outer_tag.get(rt) {
-            return Some(*tk);
-        }
-        self.universal_tag_for_type(rt)
-    }
+    // Like --preflight, but abort without decoding anything if a file fails the check
+    #[arg(long = "preflight-strict")]
+    preflight_strict: bool,
 
-    #[inline]
-    fn universal_tag_for_type(&self, t: &str) -> Option<TagKey> {
-        let rt = self.resolve_alias(t);
+    // Output layout: JSON Lines, one struct-of-arrays object per file, CSV, or one JSON array per file
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Jsonl)]
+    format: OutputFormat,
 
-        if self.sequences.contains_key(rt) || self.seq_of_types.contains_key(rt) {
-            return Some((0u8, 16u32));
-        }
-        if self.sets.contains_key(rt) || self.set_of_types.contains_key(rt) {
-            return Some((0u8, 17u32));
-        }
-        if self.choices.contains_key(rt) {
-            return None;
-        }
+    // Text between SEQUENCE OF elements within one --format csv cell
+    #[arg(long = "csv-list-separator", default_value = ";")]
+    csv_list_separator: String,
 
-        let kind = self.primitives.get(rt).map(|s| s.as_str()).unwrap_or(rt);
-
-        match kind {
-            "INTEGER" => Some((0u8, 2u32)),
-            "OCTET STRING" => Some((0u8, 4u32)),
-            "BIT STRING" => Some((0u8, 3u32)),
-            "BOOLEAN" => Some((0u8, 1u32)),
-            "NULL" => Some((0u8, 5u32)),
-            "ENUMERATED" => Some((0u8, 10u32)),
-            "IA5String" => Some((0u8, 22u32)),
-            "UTF8String" => Some((0u8, 12u32)),
-            "OBJECT IDENTIFIER" => Some((0u8, 6u32)),
-            "TBCD-STRING" => Some((0u8, 4u32)),
-            "GraphicString" => Some((0u8, 25u32)),
-            "VisibleString" => Some((0u8, 26u32)),
-            _ => None,
-        }
-    }
+    // Write up to N records per output line as a JSON array (1 = plain JSONL)
+    #[arg(long = "batch-size", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    // Each input file holds exactly one root record starting at offset 0 (no scanning)
+    #[arg(long = "one-record-per-file")]
+    one_record_per_file: bool,
+
+    // Decode the entire file as one value of --root-type, without checking its outer tag
+    #[arg(long = "whole-file", conflicts_with = "one_record_per_file")]
+    whole_file: bool,
+
+    // Read inputs into memory instead of mmapping them (safe if collectors truncate files mid-run)
+    #[arg(long = "copy-input")]
+    copy_input: bool,
+
+    // Treat every input as gzip, whatever its name or first bytes
+    #[arg(long = "gunzip")]
+    gunzip: bool,
+
+    // Only count the root records of each input; nothing is decoded or written (--output-dir not needed)
+    #[arg(long = "count-only")]
+    count_only: bool,
+
+    // Stop each file after this many records (elements with --container-root) have been written
+    #[arg(long = "max-records")]
+    max_records: Option<usize>,
+
+    // Pass over the first N records (elements with --container-root) of each file without writing them
+    #[arg(long = "skip", default_value_t = 0)]
+    skip: usize,
+
+    // Fail a compressed input whose decompressed size exceeds this many bytes
+    #[arg(long = "max-decompressed")]
+    max_decompressed: Option<u64>,
+
+    // Write a schema coverage report (types decoded, fields present) to this file
+    #[arg(long = "coverage-out")]
+    coverage_out: Option<PathBuf>,
+
+    // Remap universal tag numbers per type kind (KIND = TAG per line) for nonstandard vendor schemas
+    #[arg(long = "universal-overrides", conflicts_with = "load_compiled")]
+    universal_overrides: Option<PathBuf>,
+
+    // Write a JSON manifest of every output file (source, records, errors, size) after the run
+    #[arg(long = "manifest")]
+    manifest: Option<PathBuf>,
+
+    // Write a JSON summary of the run (files, records, bytes, failures, wall time) after all files finish
+    #[arg(long = "stats")]
+    stats: Option<PathBuf>,
+
+    // Fail a file on framing problems (truncated or oversized root records) instead of skipping
+    #[arg(long = "strict")]
+    strict: bool,
+
+    // Keep resyncing past bad or unrecognised bytes and report per file how much was skipped
+    #[arg(long = "skip-errors", conflicts_with = "strict")]
+    skip_errors: bool,
+
+    // Decode INTEGERs whose range constraint excludes negatives, e.g. (0..MAX), as unsigned
+    #[arg(long = "unsigned-constrained")]
+    unsigned_constrained: bool,
+
+    // Interpret values by meaning where the schema alone can't (e.g. IP addresses in OCTET STRINGs)
+    #[arg(long = "semantic")]
+    semantic: bool,
+
+    // Field-name words that mark a 4/16-byte OCTET STRING as an IP address under --semantic
+    #[arg(long = "ip-field-patterns", value_delimiter = ',', default_values_t = DEFAULT_IP_NAME_PATTERNS.iter().map(|s| s.to_string()))]
+    ip_field_patterns: Vec<String>,
+
+    // Quote integers beyond +/-(2^53 - 1) so JavaScript/jq consumers don't lose precision
+    #[arg(long = "big-ints-as-strings")]
+    big_ints_as_strings: bool,
+
+    // Quote every integer in the output
+    #[arg(long = "all-numbers-as-strings")]
+    all_numbers_as_strings: bool,
+
+    // How REAL infinities and NaN are written: as strings, as null, or fail the file
+    #[arg(long = "special-floats", value_enum, default_value_t = SpecialFloats::String)]
+    special_floats: SpecialFloats,
+
+    // How OCTET STRING and other binary values are written
+    #[arg(long = "value-encoding", value_enum, default_value_t = ValueEncoding::Hex)]
+    value_encoding: ValueEncoding,
+
+    // Write hex values with uppercase digits
+    #[arg(long = "hex-upper")]
+    hex_upper: bool,
+
+    // Pretty-print each record over several indented lines
+    #[arg(long = "pretty")]
+    pretty: bool,
+
+    // Spaces per nesting level for --pretty
+    #[arg(long = "indent", requires = "pretty", default_value_t = 2)]
+    indent: usize,
+
+    // Render TBCD-STRING values (IMSI, MSISDN, ...) as digit strings instead of hex
+    #[arg(long = "tbcd")]
+    tbcd: bool,
+
+    // Which nibble of each TBCD octet holds the earlier digit
+    #[arg(long = "tbcd-nibble-order", value_enum, default_value_t = NibbleOrder::LowFirst, requires = "tbcd")]
+    tbcd_nibble_order: NibbleOrder,
+
+    // End a TBCD string at the first 0xF filler nibble instead of dropping fillers
+    #[arg(long = "tbcd-stop-at-filler", requires = "tbcd")]
+    tbcd_stop_at_filler: bool,
+
+    // Render TBCD nibbles 0xA-0xE as * # a b c instead of dropping them
+    #[arg(long = "tbcd-extended-digits", requires = "tbcd")]
+    tbcd_extended_digits: bool,
+
+    // Render PLMN-Id values as "mcc-mnc" strings or {"mcc":...,"mnc":...} objects instead of hex
+    #[arg(long = "plmn-id", value_enum)]
+    plmn_id: Option<PlmnFormat>,
+
+    // Write GeneralizedTime/UTCTime values as {"iso":...,"epoch":...} with Unix seconds alongside the ISO text
+    #[arg(long = "timestamp-epoch")]
+    timestamp_epoch: bool,
+
+    // First year of the 100-year window two-digit UTCTime years fall into
+    #[arg(long = "utctime-pivot", default_value_t = DEFAULT_UTCTIME_PIVOT, value_parser = clap::value_parser!(u16).range(0..=9900))]
+    utctime_pivot: u16,
+
+    // Only print errors, warnings and the final summary
+    #[arg(long = "quiet", short = 'q', conflicts_with = "verbose")]
+    quiet: bool,
+
+    // Print progress and per-file success lines (the default)
+    #[arg(long = "verbose", short = 'v')]
+    verbose: bool,
+
+    // Keep a files-done / records-decoded line updated on stderr (only on a terminal unless =always)
+    #[arg(long = "progress", value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "auto")]
+    progress: Option<ProgressMode>,
+
+    #[arg(required_unless_present = "validate_schema")]
+    inputs: Vec<PathBuf>,
 }
 
-#[derive(Debug, Clone)]
-struct Tlv<'a> {
-    tag_class: u8,
-    constructed: bool,
-    tag_num: u32,
-    #[allow(dead_code)]
-    length: usize,
-    value: &'a [u8],
-    raw: &'a [u8],
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum OutputFormat {
+    /// One JSON object per line
+    #[default]
+    Jsonl,
+    /// One struct-of-arrays JSON object per file
+    Soa,
+    /// A header row plus one row per record; the root type must be a SEQUENCE or SET
+    Csv,
+    /// One JSON array of all records per file
+    JsonArray,
 }
 
-#[inline]
-fn write_json_key<W: Write>(w: &mut W, key: &str) -> Result<()> {
-    w.write_all(b"\"")?;
-    for &b in key.as_bytes() {
-        match b {
-            b'"' => w.write_all(b"\\\"")?,
-            b'\\' => w.write_all(b"\\\\")?,
-            b'\n' => w.write_all(b"\\n")?,
-            b'\r' => w.write_all(b"\\r")?,
-            b'\t' => w.write_all(b"\\t")?,
-            c if c < 0x20 => {
-                const HEX: &[u8; 16] = b"0123456789abcdef";
-                let esc = [b'\\', b'u', b'0', b'0', HEX[(c >> 4) as usize], HEX[(c & 0x0F) as usize]];
-                w.write_all(&esc)?;
-            }
-            c => w.write_all(&[c])?,
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Soa => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::JsonArray => "json",
         }
     }
-    w.write_all(b"\"")?;
-    Ok(())
 }
 
-#[inline(always)]
-fn hex_encode_into<'a>(bytes: &[u8], scratch: &'a mut Vec<u8>) -> &'a [u8] {
-    const HEX: &[u8; 16] = b"0123456789abcdef";
-    scratch.clear();
-    scratch.reserve(bytes.len() * 2);
-    unsafe { scratch.set_len(bytes.len() * 2) };
-    let mut j = 0usize;
-    for &b in bytes {
-        scratch[j] = HEX[(b >> 4) as usize];
-        scratch[j + 1] = HEX[(b & 0x0F) as usize];
-        j += 2;
-    }
-    &scratch[..j]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ProgressMode {
+    /// Only when stderr is a terminal
+    Auto,
+    /// Even when stderr is redirected
+    Always,
 }
 
-#[inline]
-fn write_hex_json<W: Write>(w: &mut W, data: &[u8], scratch: &mut Vec<u8>) -> Result<()> {
-    w.write_all(b"\"")?;
-    let hex = hex_encode_into(data, scratch);
-    w.write_all(hex)?;
-    w.write_all(b"\"")?;
-    Ok(())
+/// Stack size of the decoding threads; the same as a typical main thread.
+const WORKER_STACK_BYTES: usize = 8 * 1024 * 1024;
+
+/// How often the `--progress` line is redrawn.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `--progress` counters, bumped by the rayon workers as each file finishes.
+struct Progress {
+    total_files: usize,
+    files_done: AtomicUsize,
+    records: AtomicUsize,
+    finished: AtomicBool,
 }
 
-#[inline(always)]
-fn find_eoc(data: &[u8], mut off: usize) -> Option<usize> {
-    let mut depth: i32 = 1;
-    while off + 1 < data.len() {
-        if data[off] == 0x00 && data[off + 1] == 0x00 {
-            depth -= 1;
-            off += 2;
-            if depth == 0 {
-                return Some(off);
-            }
-            continue;
-        }
+impl Progress {
+    fn new(total_files: usize) -> Self {
+        Self { total_files, files_done: AtomicUsize::new(0), records: AtomicUsize::new(0), finished: AtomicBool::new(false) }
+    }
 
-        let start = off;
-        let tag_byte = *data.get(off)?;
-        off += 1;
+    fn file_done(&self, records: usize) {
+        self.records.fetch_add(records, Ordering::Relaxed);
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+    }
 
-        let constructed = ((tag_byte >> 5) & 0x01) != 0;
-        let mut tag_num = (tag_byte & 0x1F) as u32;
+    fn draw(&self) {
+        eprint!(
+            "\r{}/{} files, {} records",
+            self.files_done.load(Ordering::Relaxed),
+            self.total_files,
+            self.records.load(Ordering::Relaxed)
+        );
+    }
 
-        if tag_num == 0x1F {
-            tag_num = 0;
-            while off < data.len() {
-                let b = data[off];
-                off += 1;
-                tag_num = (tag_num << 7) | (b & 0x7F) as u32;
-                if (b & 0x80) == 0 {
-                    break;
-                }
-            }
+    /// Redraws the line until `finished` is set, then draws the final counts and ends the line.
+    fn report(&self) {
+        while !self.finished.load(Ordering::Relaxed) {
+            self.draw();
+            std::thread::park_timeout(PROGRESS_INTERVAL);
         }
+        self.draw();
+        eprintln!();
+    }
+}
 
-        let len_byte = *data.get(off)?;
-        off += 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RecordId {
+    Sequence,
+    Hash,
+    Offset,
+}
 
-        if len_byte == 0x80 {
-            if !constructed {
-                return None;
-            }
-            depth += 1;
+/// Splits a `Parent.field=value` hint and checks that `Parent` is a SEQUENCE/SET with that
+/// field. Returns (resolved parent, field, value), or `None` after warning about a mismatch.
+fn parse_field_hint<'a>(
+    flag: &str,
+    usage: &str,
+    spec: &'a str,
+    schema: &'a Asn1Schema,
+) -> Result<Option<(&'a str, &'a str, &'a str)>> {
+    let bad_spec = || anyhow!("{} '{}' must look like {}", flag, spec, usage);
+    let (target, value) = spec.split_once('=').ok_or_else(bad_spec)?;
+    let (parent, field) = target.split_once('.').ok_or_else(bad_spec)?;
+    let (parent, field, value) = (parent.trim(), field.trim(), value.trim());
+
+    let parent_rt = schema.resolve_alias(parent);
+    let fields = schema.sequences.get(parent_rt).or_else(|| schema.sets.get(parent_rt));
+    let Some(fields) = fields else {
+        eprintln!("Warning: {} '{}': '{}' is not a SEQUENCE/SET in the schema; ignored", flag, spec, parent);
+        return Ok(None);
+    };
+    if !fields.values().any(|f| f.name == field) {
+        eprintln!("Warning: {} '{}': '{}' has no field '{}'; ignored", flag, spec, parent, field);
+        return Ok(None);
+    }
+    Ok(Some((parent_rt, field, value)))
+}
+
+fn parse_type_overrides(specs: &[String], schema: &Asn1Schema) -> Result<HashMap<String, HashMap<String, String>>> {
+    let mut overrides: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for spec in specs {
+        let Some((parent_rt, field, forced)) = parse_field_hint("--force-type", "Parent.field=ChildType", spec, schema)? else {
+            continue;
+        };
+        if !schema.knows_type(forced) && schema.universal_tag_for_type(forced).is_none() {
+            eprintln!("Warning: --force-type '{}': type '{}' not found in schema; ignored", spec, forced);
             continue;
         }
 
-        let len: usize;
-        if (len_byte & 0x80) != 0 {
-            let n = (len_byte & 0x7F) as usize;
-            if n == 0 || off + n > data.len() {
-                return None;
+        overrides
+            .entry(parent_rt.to_string())
+            .or_default()
+            .insert(field.to_string(), forced.to_string());
+    }
+    Ok(overrides)
+}
+
+/// Parses `Parent.field=gsm7|ucs2|latin1` charset hints.
+fn parse_charset_fields(specs: &[String], schema: &Asn1Schema) -> Result<HashMap<String, HashMap<String, Charset>>> {
+    let mut hints: HashMap<String, HashMap<String, Charset>> = HashMap::new();
+    for spec in specs {
+        let Some((parent_rt, field, charset)) =
+            parse_field_hint("--charset-field", "Parent.field=gsm7|ucs2|latin1", spec, schema)?
+        else {
+            continue;
+        };
+        let charset: Charset = charset.parse().with_context(|| format!("--charset-field '{}'", spec))?;
+        hints.entry(parent_rt.to_string()).or_default().insert(field.to_string(), charset);
+    }
+    Ok(hints)
+}
+
+fn expand_inputs(inputs: &[PathBuf], allowed_exts: Option<&HashSet<String>>) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for p in inputs {
+        if is_stdin(p) {
+            files.push(p.clone());
+        } else if p.is_file() || p.is_dir() {
+            add_input(p, allowed_exts, &mut files)?;
+        } else if is_glob_pattern(p) {
+            let pattern = p.to_string_lossy();
+            let mut matched = false;
+            for entry in glob::glob(&pattern).with_context(|| format!("Invalid input pattern {:?}", pattern))? {
+                let path = entry?;
+                if path.is_file() || path.is_dir() {
+                    add_input(&path, allowed_exts, &mut files)?;
+                    matched = true;
+                }
             }
-            let mut l = 0usize;
-            for _ in 0..n {
-                l = (l << 8) | data[off] as usize;
-                off += 1;
+            if !matched {
+                return Err(anyhow!("Input pattern matched no files or directories: {:?}", p));
             }
-            len = l;
         } else {
-            len = len_byte as usize;
+            return Err(anyhow!("Input path is not a file or directory: {:?}", p));
         }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
 
-        if off + len > data.len() {
-            return None;
+/// Adds one existing file, or every file under a directory, subject to `--ext`.
+fn add_input(p: &Path, allowed_exts: Option<&HashSet<String>>, files: &mut Vec<PathBuf>) -> Result<()> {
+    if p.is_file() {
+        if should_include(p, allowed_exts) {
+            files.push(p.to_path_buf());
         }
-        off += len;
-
-        if off <= start {
-            return None;
+    } else {
+        for entry in WalkDir::new(p).follow_links(false) {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_file() && should_include(path, allowed_exts) {
+                files.push(path.to_path_buf());
+            }
         }
     }
-    None
+    Ok(())
+}
+
+/// An input that isn't an existing path but contains `*`, `?` or `[` is expanded as a glob
+/// (`data/*.dat`, `**/*.ber`), so it works without shell globbing.
+fn is_glob_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// An input path of `-` means standard input.
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+#[inline]
+fn should_include(path: &Path, allowed_exts: Option<&HashSet<String>>) -> bool {
+    let Some(set) = allowed_exts else { return true; };
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else { return false; };
+    set.contains(&ext.to_ascii_lowercase())
 }
 
-struct DerDecoder {
-    schema: Asn1Schema,
+#[derive(Debug, Clone, Default)]
+struct ProcessOptions {
+    container_root: bool,
+    max_record_bytes: Option<usize>,
+    record_id: Option<RecordId>,
+    emit_timing: bool,
+    with_offset: bool,
+    one_record_per_file: bool,
+    whole_file: bool,
+    copy_input: bool,
+    gunzip: bool,
+    max_decompressed: Option<u64>,
+    count_only: bool,
+    max_records: Option<usize>,
+    skip: usize,
+    root_type_fallback: Option<String>,
+    strict: bool,
+    format: OutputFormat,
+    batch_size: usize,
+    csv_list_separator: String,
 }
 
-impl DerDecoder {
-    fn new(schema: Asn1Schema) -> Self {
-        Self { schema }
+impl ProcessOptions {
+    #[inline]
+    fn wants_record_meta(&self) -> bool {
+        self.record_id.is_some() || self.emit_timing
     }
+}
 
-    #[inline(always)]
-    fn parse_tlv<'a>(&self, data: &'a [u8], mut offset: usize) -> Option<(Tlv<'a>, usize)> {
-        let data_len = data.len();
-        if offset >= data_len {
-            return None;
-        }
+#[inline]
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h
+}
 
-        let start = offset;
-        let tag_byte = data[offset];
-        offset += 1;
-
-        let tag_class = (tag_byte >> 6) & 0x03;
-        let constructed = ((tag_byte >> 5) & 0x01) != 0;
-        let mut tag_num = (tag_byte & 0x1F) as u32;
-
-        if tag_num == 0x1F {
-            tag_num = 0;
-            while offset < data_len {
-                let b = data[offset];
-                offset += 1;
-                tag_num = (tag_num << 7) | (b & 0x7F) as u32;
-                if (b & 0x80) == 0 {
-                    break;
+/// Writes the metadata requested in `popts` as one JSON object.
+fn write_record_meta<W: Write>(
+    out: &mut JsonSink<W>,
+    popts: &ProcessOptions,
+    raw: &[u8],
+    index: usize,
+    offset: usize,
+    decode_nanos: u128,
+) -> Result<()> {
+    out.begin_record()?;
+    out.begin_object()?;
+    match popts.record_id {
+        Some(RecordId::Sequence) => out.field("__id", SinkValue::UInt(index as u64))?,
+        Some(RecordId::Offset) => out.field("__id", SinkValue::UInt(offset as u64))?,
+        Some(RecordId::Hash) => out.field("__id", SinkValue::Str(&format!("{:016x}", fnv1a64(raw))))?,
+        None => {}
+    }
+    if popts.emit_timing {
+        out.field("__decodeNanos", SinkValue::UInt(decode_nanos as u64))?;
+    }
+    out.end_object()
+}
+
+/// Merges the `meta` object into the front of a decoded record. Non-object records are wrapped
+/// under `__value`. `indent` is the `--pretty` width both were written with.
+fn splice_record_meta<W: Write>(out: &mut W, body: &[u8], meta: &[u8], indent: Option<usize>) -> Result<()> {
+    // `meta` without its closing brace (and, when pretty, the line break before it).
+    let open = meta.strip_suffix(b"}").unwrap_or(meta).trim_ascii_end();
+    match body.strip_prefix(b"{") {
+        Some(b"}") => out.write_all(meta)?,
+        Some(rest) => {
+            out.write_all(open)?;
+            out.write_all(b",")?;
+            out.write_all(rest)?;
+        }
+        None => {
+            out.write_all(open)?;
+            match indent {
+                Some(width) => {
+                    write!(out, ",\n{:width$}\"__value\": ", "", width = width)?;
+                    write_nested(out, body, width)?;
+                    out.write_all(b"\n}")?;
+                }
+                None => {
+                    out.write_all(b",\"__value\":")?;
+                    out.write_all(body)?;
+                    out.write_all(b"}")?;
                 }
-            }
-            if offset >= data_len {
-                return None;
             }
         }
+    }
+    Ok(())
+}
 
-        if offset >= data_len {
-            return None;
+/// Writes pretty-printed `json`, laid out at depth 0, as a member value one level deep.
+fn write_nested<W: Write>(out: &mut W, json: &[u8], width: usize) -> Result<()> {
+    for (i, line) in json.split(|&b| b == b'\n').enumerate() {
+        if i > 0 {
+            write!(out, "\n{:width$}", "", width = width)?;
         }
+        out.write_all(line)?;
+    }
+    Ok(())
+}
 
-        let length_byte = data[offset];
-        offset += 1;
-
-        if length_byte == 0x80 {
-            if !constructed {
-                return None;
-            }
-            let content_start = offset;
-            let eoc_end = find_eoc(data, offset)?;
-            let content_end = eoc_end.checked_sub(2)?;
-            let length = content_end.checked_sub(content_start)?;
-            let value = &data[content_start..content_end];
-            let raw = &data[start..eoc_end];
-            return Some((
-                Tlv {
-                    tag_class,
-                    constructed,
-                    tag_num,
-                    length,
-                    value,
-                    raw,
-                },
-                eoc_end,
-            ));
+/// `--with-offset`: writes `{"offset":N,"record":...}` around an encoded record.
+fn write_offset_wrapper<W: Write>(out: &mut W, offset: usize, record: &[u8], indent: Option<usize>) -> Result<()> {
+    let mut itoa_buf = itoa::Buffer::new();
+    let offset = itoa_buf.format(offset);
+    match indent {
+        Some(width) => {
+            write!(out, "{{\n{:width$}\"offset\": {},\n{:width$}\"record\": ", "", offset, "", width = width)?;
+            write_nested(out, record, width)?;
+            out.write_all(b"\n}")?;
         }
-
-        let length: usize;
-        if (length_byte & 0x80) != 0 {
-            let num_octets = (length_byte & 0x7F) as usize;
-            if num_octets == 0 || offset + num_octets > data_len {
-                return None;
-            }
-            let mut l: usize = 0;
-            let end_len = offset + num_octets;
-            while offset < end_len {
-                l = (l << 8) | data[offset] as usize;
-                offset += 1;
-            }
-            length = l;
-        } else {
-            length = length_byte as usize;
+        None => {
+            out.write_all(b"{\"offset\":")?;
+            out.write_all(offset.as_bytes())?;
+            out.write_all(b",\"record\":")?;
+            out.write_all(record)?;
+            out.write_all(b"}")?;
         }
+    }
+    Ok(())
+}
 
-        if offset + length > data_len {
-            return None;
-        }
+/// Reusable buffers for records that carry metadata.
+#[derive(Default)]
+struct RecordBufs {
+    body: Vec<u8>,
+    meta: Vec<u8>,
+    // The whole record, staged for `--with-offset`.
+    record: Vec<u8>,
+}
 
-        let value = &data[offset..offset + length];
-        offset += length;
-        let raw = &data[start..offset];
+#[allow(clippy::too_many_arguments)]
+fn emit_record<W: Write>(
+    decoder: &DerDecoder,
+    tlv: &Tlv,
+    root_type: &str,
+    container_elem: Option<&str>,
+    index: usize,
+    offset: usize,
+    popts: &ProcessOptions,
+    bufs: &mut RecordBufs,
+    writer: &mut W,
+    scratch: &mut Vec<u8>,
+) -> Result<()> {
+    if !popts.with_offset {
+        return write_record_json(decoder, tlv, root_type, container_elem, index, offset, popts, bufs, writer, scratch);
+    }
+    let mut record = std::mem::take(&mut bufs.record);
+    record.clear();
+    let res = write_record_json(decoder, tlv, root_type, container_elem, index, offset, popts, bufs, &mut record, scratch)
+        .and_then(|()| write_offset_wrapper(writer, offset, &record, decoder.opts.pretty_indent));
+    bufs.record = record;
+    res
+}
 
-        Some((
-            Tlv {
-                tag_class,
-                constructed,
-                tag_num,
-                length,
-                value,
-                raw,
-            },
-            offset,
-        ))
+/// Writes one record as JSON, with any `--record-id` / `--emit-timing` metadata merged in.
+#[allow(clippy::too_many_arguments)]
+fn write_record_json<W: Write>(
+    decoder: &DerDecoder,
+    tlv: &Tlv,
+    root_type: &str,
+    container_elem: Option<&str>,
+    index: usize,
+    offset: usize,
+    popts: &ProcessOptions,
+    bufs: &mut RecordBufs,
+    writer: &mut W,
+    scratch: &mut Vec<u8>,
+) -> Result<()> {
+    if popts.wants_record_meta() {
+        bufs.body.clear();
+        let started = popts.emit_timing.then(Instant::now);
+        decoder.write_record(tlv, root_type, container_elem, &mut bufs.body, scratch)?;
+        let decode_nanos = started.map_or(0, |t| t.elapsed().as_nanos());
+        bufs.meta.clear();
+        let indent = decoder.opts.pretty_indent;
+        write_record_meta(&mut JsonSink::new(&mut bufs.meta).indent(indent), popts, tlv.raw, index, offset, decode_nanos)?;
+        splice_record_meta(writer, &bufs.body, &bufs.meta, indent)?;
+    } else {
+        decoder.write_record(tlv, root_type, container_elem, writer, scratch)?;
     }
+    Ok(())
+}
 
-    fn choice_alt_matches_tlv(&self, alt_type: &str, tlv: &Tlv) -> bool {
-        let rt = self.schema.resolve_alias(alt_type);
+/// Groups records into output lines: one record per line, or `[r1,r2,...]` per `--batch-size` records.
+/// Each line is staged in `line` and handed to the sink whole, so a record that fails halfway
+/// never reaches the output.
+struct LineBatcher {
+    size: usize,
+    pending: usize,
+    line: Vec<u8>,
+}
 
-        if let Some((cls, tag)) = self.schema.type_outer_tag.get(rt) {
-            return tlv.tag_class == *cls && tlv.tag_num == *tag;
-        }
+impl LineBatcher {
+    fn new(size: usize) -> Self {
+        Self { size, pending: 0, line: Vec::new() }
+    }
 
-        if let Some(sub_alts) = self.schema.choices.get(rt) {
-            if sub_alts.contains_key(&(tlv.tag_class, tlv.tag_num)) {
-                return true;
-            }
+    fn before_record(&mut self) {
+        if self.size > 1 {
+            self.line.push(if self.pending == 0 { b'[' } else { b',' });
         }
+    }
 
-        if self.schema.sequences.contains_key(rt) || self.schema.seq_of_types.contains_key(rt) {
-            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 16;
-        }
-        if self.schema.sets.contains_key(rt) || self.schema.set_of_types.contains_key(rt) {
-            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 17;
+    fn after_record<S: RecordSink>(&mut self, sink: &mut S) -> Result<()> {
+        if self.size <= 1 {
+            sink.write_record(&self.line)?;
+            self.line.clear();
+            return Ok(());
         }
-        
-        // Match Universal tags
-        if let Some((cls, tag)) = self.schema.universal_tag_for_type(rt) {
-             if tlv.tag_class == cls && tlv.tag_num == tag {
-                 return true;
-             }
+        self.pending += 1;
+        if self.pending == self.size {
+            self.finish(sink)?;
         }
-
-        false
+        Ok(())
     }
 
-    #[inline]
-    fn tlv_matches_root(&self, tlv: &Tlv, root_type: &str) -> bool {
-        let rt = self.schema.resolve_alias(root_type);
-
-        if let Some((cls, num)) = self.schema.type_outer_tag.get(rt) {
-            return tlv.tag_class == *cls && tlv.tag_num == *num;
+    /// Closes a partial final batch.
+    fn finish<S: RecordSink>(&mut self, sink: &mut S) -> Result<()> {
+        if self.pending > 0 {
+            self.line.push(b']');
+            sink.write_record(&self.line)?;
+            self.line.clear();
+            self.pending = 0;
         }
+        Ok(())
+    }
+}
 
-        if let Some(alts) = self.schema.choices.get(rt) {
-            if alts.contains_key(&(tlv.tag_class, tlv.tag_num)) {
-                return true;
-            }
-            for ((cls, tag), (_fname, ftype)) in alts {
-                if *cls == 3u8 && is_synth_choice_tag(*tag) {
-                    if self.choice_alt_matches_tlv(ftype, tlv) {
-                        return true;
-                    }
-                }
-            }
-            return false;
-        }
+/// `--format json-array`: one record per line between `[` and `]` lines. Each record is held
+/// back until the next one arrives, so it can be written with its trailing comma.
+#[derive(Default)]
+struct ArrayJoiner {
+    started: bool,
+    held: Vec<u8>,
+    line: Vec<u8>,
+}
 
-        if self.schema.sequences.contains_key(rt) || self.schema.seq_of_types.contains_key(rt) {
-            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 16;
-        }
-        if self.schema.sets.contains_key(rt) || self.schema.set_of_types.contains_key(rt) {
-            return tlv.tag_class == 0 && tlv.constructed && tlv.tag_num == 17;
+impl ArrayJoiner {
+    /// Queues the record just written to `line`, writing out the one before it.
+    fn after_record<S: RecordSink>(&mut self, sink: &mut S) -> Result<()> {
+        if self.started {
+            self.held.push(b',');
+            sink.write_record(&self.held)?;
+        } else {
+            sink.write_record(b"[")?;
+            self.started = true;
         }
-
-        self.schema.primitives.contains_key(rt)
+        std::mem::swap(&mut self.held, &mut self.line);
+        self.line.clear();
+        Ok(())
     }
 
-    fn find_next_root_tlv<'a>(&self, data: &'a [u8], mut start: usize, root_type: &str) -> Option<(Tlv<'a>, usize)> {
-        while start < data.len() {
-            if let Some((tlv, end)) = self.parse_tlv(data, start) {
-                if end > start && self.tlv_matches_root(&tlv, root_type) {
-                    return Some((tlv, end));
-                }
-            }
-            start += 1;
+    fn finish<S: RecordSink>(&mut self, sink: &mut S) -> Result<()> {
+        if !self.started {
+            return sink.write_record(b"[]");
         }
-        None
+        sink.write_record(&self.held)?;
+        sink.write_record(b"]")
     }
+}
 
-    #[inline]
-    fn write_type<W: Write>(&self, data: &[u8], type_name: &str, out: &mut W, scratch: &mut Vec<u8>) -> Result<()> {
-        let rt = self.schema.resolve_alias(type_name);
+/// `--format soa`: feeds one record, with its metadata columns, into the file's [`SoaSink`].
+#[allow(clippy::too_many_arguments)]
+fn emit_soa_record(
+    decoder: &DerDecoder,
+    tlv: &Tlv,
+    root_type: &str,
+    container_elem: Option<&str>,
+    index: usize,
+    offset: usize,
+    popts: &ProcessOptions,
+    soa: &mut SoaSink,
+) -> Result<()> {
+    soa.begin_record()?;
+    match popts.record_id {
+        Some(RecordId::Sequence) => soa.meta("__id", SinkValue::UInt(index as u64))?,
+        Some(RecordId::Offset) => soa.meta("__id", SinkValue::UInt(offset as u64))?,
+        Some(RecordId::Hash) => soa.meta("__id", SinkValue::Str(&format!("{:016x}", fnv1a64(tlv.raw))))?,
+        None => {}
+    }
+    let started = popts.emit_timing.then(Instant::now);
+    decoder.visit_record(tlv, root_type, container_elem, soa)?;
+    if let Some(t) = started {
+        soa.meta("__decodeNanos", SinkValue::UInt(t.elapsed().as_nanos() as u64))?;
+    }
+    soa.end_record()
+}
 
-        if let Some(elem) = self.schema.seq_of_types.get(rt) {
-            self.write_sequence_of(data, elem, out, scratch)?;
-            return Ok(());
-        }
-        if let Some(elem) = self.schema.set_of_types.get(rt) {
-            self.write_sequence_of(data, elem, out, scratch)?;
-            return Ok(());
-        }
+/// `--format csv`: writes one record's row (preceded by the header for the first record)
+/// through the file's [`CsvSink`], then hands it to the record sink.
+#[allow(clippy::too_many_arguments)]
+fn emit_csv_record<S: RecordSink>(
+    decoder: &DerDecoder,
+    tlv: &Tlv,
+    root_type: &str,
+    container_elem: Option<&str>,
+    index: usize,
+    offset: usize,
+    popts: &ProcessOptions,
+    csv: &mut CsvSink<Vec<u8>>,
+    sink: &mut S,
+) -> Result<()> {
+    csv.begin_record()?;
+    match popts.record_id {
+        Some(RecordId::Sequence) => csv.meta("__id", SinkValue::UInt(index as u64))?,
+        Some(RecordId::Offset) => csv.meta("__id", SinkValue::UInt(offset as u64))?,
+        Some(RecordId::Hash) => csv.meta("__id", SinkValue::Str(&format!("{:016x}", fnv1a64(tlv.raw))))?,
+        None => {}
+    }
+    let started = popts.emit_timing.then(Instant::now);
+    decoder.visit_record(tlv, root_type, container_elem, csv)?;
+    if let Some(t) = started {
+        csv.meta("__decodeNanos", SinkValue::UInt(t.elapsed().as_nanos() as u64))?;
+    }
+    csv.end_record()?;
+    flush_csv(csv, sink)
+}
 
-        if let Some(alts) = self.schema.choices.get(rt) {
-            self.write_choice(data, alts, out, scratch)?;
-            return Ok(());
-        }
-        if let Some(fields) = self.schema.sequences.get(rt) {
-            self.write_sequence(data, fields, out, scratch)?;
-            return Ok(());
-        }
-        if let Some(fields) = self.schema.sets.get(rt) {
-            self.write_sequence(data, fields, out, scratch)?;
-            return Ok(());
-        }
+/// Passes the rows buffered in `csv` to `sink` as one unit, minus the final line break.
+fn flush_csv<S: RecordSink>(csv: &mut CsvSink<Vec<u8>>, sink: &mut S) -> Result<()> {
+    let rows = csv.get_mut();
+    if !rows.is_empty() {
+        sink.write_record(rows.strip_suffix(b"\n").unwrap_or(rows))?;
+        rows.clear();
+    }
+    Ok(())
+}
 
-        write_hex_json(out, data, scratch)?;
-        Ok(())
+/// The `--format csv` sink for records of `record_type`: metadata columns, then the type's
+/// components in declaration order.
+fn csv_sink(decoder: &DerDecoder, record_type: &str, popts: &ProcessOptions) -> Result<CsvSink<Vec<u8>>> {
+    let fields = decoder
+        .schema
+        .fields_in_order(record_type)
+        .ok_or_else(|| anyhow!("--format csv needs a SEQUENCE or SET record type, not '{}'", record_type))?;
+    let mut columns = Vec::with_capacity(fields.len() + 2);
+    if popts.record_id.is_some() {
+        columns.push("__id".to_string());
     }
+    if popts.emit_timing {
+        columns.push("__decodeNanos".to_string());
+    }
+    columns.extend(fields.into_iter().map(|f| f.name.clone()));
+    Ok(CsvSink::new(Vec::new(), columns, decoder.opts.number_format, decoder.opts.special_floats)
+        .value_encoding(decoder.opts.value_encoding)
+        .list_separator(&popts.csv_list_separator))
+}
+
+/// Where `process_file` sends decoded records.
+enum RecordOut {
+    Lines(LineBatcher),
+    Array(ArrayJoiner),
+    Soa(SoaSink),
+    Csv(CsvSink<Vec<u8>>),
+}
 
-    fn write_sequence<W: Write>(
-        &self,
-        data: &[u8],
-        field_spec: &HashMap<TagKey, FieldSpec>,
-        out: &mut W,
+impl RecordOut {
+    #[allow(clippy::too_many_arguments)]
+    fn emit<S: RecordSink>(
+        &mut self,
+        decoder: &DerDecoder,
+        tlv: &Tlv,
+        root_type: &str,
+        container_elem: Option<&str>,
+        index: usize,
+        offset: usize,
+        popts: &ProcessOptions,
+        bufs: &mut RecordBufs,
+        sink: &mut S,
         scratch: &mut Vec<u8>,
     ) -> Result<()> {
-        out.write_all(b"{")?;
-        let mut offset = 0usize;
-        let mut first = true;
-
-        let mut itoa_buf = itoa::Buffer::new();
-        let mut itoa_buf2 = itoa::Buffer::new();
-
-        while offset < data.len() {
-            let (tlv, new_off) = match self.parse_tlv(data, offset) {
-                Some(t) => t,
-                None => break,
-            };
-            if new_off <= offset {
-                break;
+        match self {
+            RecordOut::Lines(batcher) => {
+                batcher.before_record();
+                emit_record(decoder, tlv, root_type, container_elem, index, offset, popts, bufs, &mut batcher.line, scratch)?;
+                batcher.after_record(sink)
             }
-
-            if !first {
-                out.write_all(b",")?;
-            }
-            first = false;
-
-            let key: TagKey = (tlv.tag_class, tlv.tag_num);
-
-            if let Some(field) = field_spec.get(&key) {
-                write_json_key(out, &field.name)?;
-                out.write_all(b":")?;
-
-                let resolved_field_type = self.schema.resolve_alias(&field.field_type);
-
-                if field.is_sequence_of || field.is_set_of {
-                    self.write_sequence_of(tlv.value, &field.field_type, out, scratch)?;
-                } else if self.schema.choices.contains_key(resolved_field_type) {
-                    // CHOICE special handling: 
-                    // If the CHOICE field itself has a tag (Context 101), that tag is EXPLICIT.
-                    // Meaning the content `tlv.value` contains the *inner* TLV (e.g. Context 1).
-                    // We must pass `tlv.raw` so `write_choice` can parse the wrapper (if it matches)
-                    // OR if `tlv` is the wrapper, `write_choice` needs to peel it.
-                    // Actually, `write_choice` looks at `candidates`. 
-                    // If we pass `tlv.raw` (the wrapper), `candidates[0]` is wrapper, 
-                    // `candidates[1]` is inner.
-                    self.write_type(tlv.raw, &field.field_type, out, scratch)?;
-                } else if tlv.constructed {
-                    self.write_type(tlv.value, &field.field_type, out, scratch)?;
-                } else {
-                    write_hex_json(out, tlv.value, scratch)?;
-                }
-            } else {
-                out.write_all(b"\"unknown_tag_")?;
-                out.write_all(itoa_buf.format(tlv.tag_class as u32).as_bytes())?;
-                out.write_all(b"_")?;
-                out.write_all(itoa_buf2.format(tlv.tag_num).as_bytes())?;
-                out.write_all(b"\":")?;
-                write_hex_json(out, tlv.value, scratch)?;
+            RecordOut::Array(joiner) => {
+                emit_record(decoder, tlv, root_type, container_elem, index, offset, popts, bufs, &mut joiner.line, scratch)?;
+                joiner.after_record(sink)
             }
-
-            offset = new_off;
+            RecordOut::Soa(soa) => emit_soa_record(decoder, tlv, root_type, container_elem, index, offset, popts, soa),
+            RecordOut::Csv(csv) => emit_csv_record(decoder, tlv, root_type, container_elem, index, offset, popts, csv, sink),
         }
-
-        out.write_all(b"}")?;
-        Ok(())
     }
 
-    fn write_sequence_of<W: Write>(&self, data: &[u8], element_type: &str, out: &mut W, scratch: &mut Vec<u8>) -> Result<()> {
-        out.write_all(b"[")?;
-        let mut arr_first = true;
-        let mut offset = 0usize;
-
-        let is_choice = self.schema.choices.contains_key(self.schema.resolve_alias(element_type));
-
-        while offset < data.len() {
-            let (tlv, new_off) = match self.parse_tlv(data, offset) {
-                Some(t) => t,
-                None => break,
-            };
-            if new_off <= offset {
-                break;
-            }
-
-            if !arr_first {
-                out.write_all(b",")?;
+    fn finish<S: RecordSink>(self, sink: &mut S) -> Result<()> {
+        match self {
+            RecordOut::Lines(mut batcher) => batcher.finish(sink),
+            RecordOut::Array(mut joiner) => joiner.finish(sink),
+            // A file without records still gets `{}` so every input has a parseable output.
+            RecordOut::Soa(soa) => {
+                let mut doc = Vec::new();
+                soa.finish(&mut doc)?;
+                sink.write_record(&doc)
             }
-            arr_first = false;
-
-            if is_choice {
-                // For Sequence Of Choice, the items are direct choices.
-                // We pass `tlv.raw` because the tag we found (e.g. [1]) IS the choice tag.
-                self.write_type(tlv.raw, element_type, out, scratch)?;
-            } else if tlv.constructed {
-                self.write_type(tlv.value, element_type, out, scratch)?;
-            } else {
-                write_hex_json(out, tlv.value, scratch)?;
+            // An input without records still gets its header row.
+            RecordOut::Csv(mut csv) => {
+                csv.finish()?;
+                flush_csv(&mut csv, sink)
             }
-
-            offset = new_off;
         }
+    }
+}
 
-        out.write_all(b"]")?;
-        Ok(())
+fn truncation_message(offset: usize, need: usize, have: usize) -> String {
+    format!("truncated root record at offset {}: need {} bytes, have {}", offset, need, have)
+}
+
+/// `--one-record-per-file`: the root TLV must start at offset 0. Trailing bytes are only a warning.
+fn single_root_tlv<'a>(decoder: &DerDecoder, data: &'a [u8], root_type: &str, in_path: &Path) -> Result<Tlv<'a>> {
+    if let Some(need) = decoder.truncated_root_at(data, 0, root_type) {
+        return Err(anyhow!("{}", truncation_message(0, need, data.len())));
+    }
+    let (tlv, end) = decoder
+        .parse_tlv(data, 0)
+        .filter(|(tlv, _)| decoder.tlv_matches_root(tlv, root_type))
+        .ok_or_else(|| anyhow!("first TLV in {:?} does not match root type '{}'", in_path, root_type))?;
+    if end < data.len() {
+        eprintln!("Warning: {} trailing bytes after the record in {:?}", data.len() - end, in_path);
     }
+    Ok(tlv)
+}
 
-    fn write_choice<W: Write>(
-        &self,
-        data: &[u8],
-        alts: &HashMap<TagKey, (String, String)>,
-        out: &mut W,
-        scratch: &mut Vec<u8>,
-    ) -> Result<()> {
-        let (outer, _) = match self.parse_tlv(data, 0) {
-            Some(t) => t,
-            None => {
-                out.write_all(b"null")?;
-                return Ok(());
-            }
-        };
+/// Input file contents: memory-mapped, or read into memory under `--copy-input`.
+enum InputBytes {
+    Mapped(Mmap),
+    // A mapping faults (SIGBUS) if another process truncates the file under it; a copy can't.
+    Owned(Vec<u8>),
+}
 
-        let mut candidates: [Option<Tlv>; 3] = [None, None, None];
-        candidates[0] = Some(outer.clone());
+impl std::ops::Deref for InputBytes {
+    type Target = [u8];
 
-        // If the outer tag is a constructed wrapper (Explicit tagging), look inside.
-        if outer.constructed {
-            candidates[1] = self.parse_tlv(outer.value, 0).map(|(inner, _)| inner);
-        }
-        // Special case for TAP: sometimes double wrapped?
-        if outer.tag_class == 0 && !outer.constructed && outer.tag_num == 4 {
-             if !outer.value.is_empty() && outer.value[0] != 0x00 {
-                candidates[2] = self.parse_tlv(outer.value, 0).map(|(inner, _)| inner);
-             }
+    fn deref(&self) -> &[u8] {
+        match self {
+            InputBytes::Mapped(m) => m,
+            InputBytes::Owned(v) => v,
         }
+    }
+}
 
-        out.write_all(b"{")?;
+/// What `process_file` did with one input.
+struct FileOutcome {
+    // `--root-type`, or `--root-type-fallback` if the primary type never occurs in the file
+    root_type: String,
+    records: usize,
+    // root records skipped because they were truncated or over --max-record-bytes
+    skipped: usize,
+    // bytes that were not part of any root record: filler, junk, and skipped records
+    bytes_skipped: usize,
+    // records written after the first skipped byte
+    recovered: usize,
+    // input size after any decompression
+    bytes_read: usize,
+}
 
-        // 1. Tagged CHOICE: direct match
-        for cand in candidates.iter().flatten() {
-            if let Some((field_name, type_name)) = alts.get(&(cand.tag_class, cand.tag_num)) {
-                write_json_key(out, field_name)?;
-                out.write_all(b":")?;
-                self.write_type(cand.value, type_name, out, scratch)?;
-                out.write_all(b"}")?;
-                return Ok(());
-            }
+fn output_path(in_path: &Path, out_dir: &Path, format: OutputFormat) -> Result<PathBuf> {
+    if is_stdin(in_path) {
+        return Ok(out_dir.join(format!("stdin.{}", format.extension())));
+    }
+    let file_name = in_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Input path has no filename: {:?}", in_path))?
+        .to_string_lossy()
+        .to_string();
+    Ok(out_dir.join(format!("{}.{}", file_name, format.extension())))
+}
+
+/// `--whole-file`: the file is one encoding of the root type. The outer tag isn't checked, and
+/// a declared length that disagrees with the file size only warns; content runs to end of file.
+fn whole_file_tlv<'a>(decoder: &DerDecoder, data: &'a [u8], in_path: &Path) -> Result<Tlv<'a>> {
+    if let Some((tlv, end)) = decoder.parse_tlv(data, 0) {
+        if end == data.len() {
+            return Ok(tlv);
         }
+    }
+    let hdr = decoder
+        .parse_tlv_header(data, 0)
+        .ok_or_else(|| anyhow!("no TLV header at offset 0 of {:?}", in_path))?;
+    let value = &data[hdr.content_start..];
+    match hdr.length {
+        Some(len) => eprintln!(
+            "Warning: {:?}: declared length {} but the file holds {} content bytes; decoding to end of file",
+            in_path,
+            len,
+            value.len()
+        ),
+        None => eprintln!("Warning: {:?}: indefinite length without a closing end-of-contents; decoding to end of file", in_path),
+    }
+    Ok(Tlv {
+        tag_class: hdr.tag_class,
+        constructed: hdr.constructed,
+        tag_num: hdr.tag_num,
+        length: value.len(),
+        value,
+        raw: data,
+    })
+}
 
-        // 2. Untagged CHOICE (Synthetic)
-        let mut synth_keys: Vec<u32> = alts
-            .keys()
-            .filter(|(cls, tag)| *cls == 3u8 && is_synth_choice_tag(*tag))
-            .map(|(_, tag)| *tag)
-            .collect();
-        synth_keys.sort_unstable();
-
-        for k in synth_keys {
-            let (fname, ftype) = &alts[&(3u8, k)];
-            let f_rt = self.schema.resolve_alias(ftype);
-
-            for cand in candidates.iter().flatten() {
-                if self.choice_alt_matches_tlv(ftype, cand) {
-                    write_json_key(out, fname)?;
-                    out.write_all(b":")?;
-                    
-                    if self.schema.type_outer_tag.contains_key(f_rt) {
-                        self.write_type(cand.value, ftype, out, scratch)?;
-                    } else if self.schema.choices.contains_key(f_rt) {
-                         self.write_type(cand.raw, ftype, out, scratch)?;
-                    } else {
-                        self.write_type(cand.value, ftype, out, scratch)?;
-                    }
+/// `--root-type-fallback`: use the fallback type for a file in which the primary root type
+/// never occurs. The extra scan only happens for such files.
+fn pick_root_type<'a>(decoder: &DerDecoder, data: &[u8], root_type: &'a str, popts: &'a ProcessOptions) -> &'a str {
+    let Some(fallback) = popts.root_type_fallback.as_deref() else {
+        return root_type;
+    };
+    // `--whole-file` never checks the outer tag, so there is nothing to fall back from.
+    let primary_found = popts.whole_file
+        || if popts.one_record_per_file {
+            decoder.parse_tlv(data, 0).is_some_and(|(tlv, _)| decoder.tlv_matches_root(&tlv, root_type))
+        } else {
+            decoder.find_next_root_tlv(data, 0, root_type).is_some()
+        };
+    if primary_found {
+        root_type
+    } else {
+        fallback
+    }
+}
 
-                    out.write_all(b"}")?;
-                    return Ok(());
-                }
-            }
+/// Bytes read from the start of each file by `--preflight`; enough for any sane TLV header.
+const PREFLIGHT_PREFIX: u64 = 4096;
+
+/// `--preflight`: cheap per-file check that the input is readable, non-empty and (unless
+/// `--whole-file`) starts with a TLV whose tag matches the root type or its fallback.
+fn preflight_file(decoder: &DerDecoder, in_path: &Path, root_type: &str, popts: &ProcessOptions) -> Result<()> {
+    let mut file = File::open(in_path).context("cannot open")?;
+    let mut magic = [0u8; ZSTD_MAGIC.len()];
+    let mut n = 0;
+    while n < magic.len() {
+        match file.read(&mut magic[n..]).context("cannot read")? {
+            0 => break,
+            k => n += k,
         }
-
-        write_json_key(out, "unknown_alternative")?;
-        out.write_all(b":")?;
-        write_hex_json(out, outer.raw, scratch)?;
-        out.write_all(b"}")?;
-        Ok(())
     }
+    file.seek(SeekFrom::Start(0)).context("cannot read")?;
+    let mut prefix = Vec::new();
+    let compression = if n > 0 { detect_compression(in_path, &magic[..n], popts) } else { None };
+    if let Some(compression) = compression {
+        decompressor(compression, file)?.take(PREFLIGHT_PREFIX).read_to_end(&mut prefix).context("cannot decompress")?;
+    } else {
+        file.take(PREFLIGHT_PREFIX).read_to_end(&mut prefix).context("cannot read")?;
+    }
+    if prefix.is_empty() {
+        return Err(anyhow!("file is empty"));
+    }
+    if popts.whole_file {
+        return Ok(());
+    }
+    let hdr = decoder
+        .parse_tlv_header(&prefix, 0)
+        .ok_or_else(|| anyhow!("does not start with a valid TLV header"))?;
+    let content_end = hdr.length.map_or(prefix.len(), |l| hdr.content_start.saturating_add(l).min(prefix.len()));
+    let first = Tlv {
+        tag_class: hdr.tag_class,
+        constructed: hdr.constructed,
+        tag_num: hdr.tag_num,
+        length: content_end - hdr.content_start,
+        value: &prefix[hdr.content_start..content_end],
+        raw: &prefix[..content_end],
+    };
+    let matches = std::iter::once(root_type)
+        .chain(popts.root_type_fallback.as_deref())
+        .any(|rt| decoder.tlv_matches_root(&first, rt));
+    if !matches {
+        return Err(anyhow!(
+            "first tag (class {}, number {}) does not match root type '{}'",
+            first.tag_class,
+            first.tag_num,
+            root_type
+        ));
+    }
+    Ok(())
+}
 
-    fn write_root_tlv_with_type<W: Write>(&self, tlv: &Tlv, root_type: &str, out: &mut W, scratch: &mut Vec<u8>) -> Result<()> {
-        let rt = self.schema.resolve_alias(root_type);
+/// Gzip member magic bytes.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-        if !self.schema.knows_type(rt) {
-            return Err(anyhow!("root-type '{}' not found in schema", root_type));
-        }
+/// Zstandard frame magic bytes.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
 
-        if self.schema.type_outer_tag.contains_key(rt) {
-            self.write_type(tlv.value, root_type, out, scratch)?;
-            return Ok(());
-        }
+/// How a compressed input is packed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
 
-        if self.schema.choices.contains_key(rt) {
-            self.write_type(tlv.raw, root_type, out, scratch)?;
-        } else {
-            self.write_type(tlv.value, root_type, out, scratch)?;
-        }
-        Ok(())
+/// Detects compressed input: `--gunzip`, the gzip or zstd magic bytes, or a `.gz` extension.
+fn detect_compression(in_path: &Path, data: &[u8], popts: &ProcessOptions) -> Option<Compression> {
+    if popts.gunzip || data.starts_with(&GZIP_MAGIC) {
+        Some(Compression::Gzip)
+    } else if data.starts_with(&ZSTD_MAGIC) {
+        Some(Compression::Zstd)
+    } else if in_path.extension().is_some_and(|e| e.eq_ignore_ascii_case("gz")) {
+        Some(Compression::Gzip)
+    } else {
+        None
     }
 }
 
-fn expand_inputs(inputs: &[PathBuf], allowed_exts: Option<&HashSet<String>>) -> Result<Vec<PathBuf>> {
-    let mut files: Vec<PathBuf> = Vec::new();
-    for p in inputs {
-        if p.is_file() {
-            if should_include(p, allowed_exts) {
-                files.push(p.clone());
-            }
-        } else if p.is_dir() {
-            for entry in WalkDir::new(p).follow_links(false) {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_file() && should_include(path, allowed_exts) {
-                    files.push(path.to_path_buf());
-                }
-            }
-        } else {
-            return Err(anyhow!("Input path is not a file or directory: {:?}", p));
+/// A reader that decompresses `inner` as `compression`.
+fn decompressor<'a, R: Read + 'a>(compression: Compression, inner: R) -> Result<Box<dyn Read + 'a>> {
+    Ok(match compression {
+        // Concatenated gzip members (e.g. appended archives) decode as one stream.
+        Compression::Gzip => Box::new(MultiGzDecoder::new(inner)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(inner)?),
+    })
+}
+
+/// Decompresses all of `data`, failing once the output passes `--max-decompressed`.
+fn decompress(compression: Compression, data: &[u8], in_path: &Path, popts: &ProcessOptions) -> Result<Vec<u8>> {
+    // One byte over the limit is enough to tell.
+    let limit = popts.max_decompressed.map_or(u64::MAX, |max| max.saturating_add(1));
+    let mut buf = Vec::new();
+    decompressor(compression, data)?
+        .take(limit)
+        .read_to_end(&mut buf)
+        .with_context(|| format!("Failed to decompress input {:?}", in_path))?;
+    if let Some(max) = popts.max_decompressed {
+        if buf.len() as u64 > max {
+            return Err(anyhow!("decompressed size exceeds --max-decompressed {} bytes", max));
         }
     }
-    files.sort();
-    files.dedup();
-    Ok(files)
+    Ok(buf)
 }
 
-#[inline]
-fn should_include(path: &Path, allowed_exts: Option<&HashSet<String>>) -> bool {
-    let Some(set) = allowed_exts else { return true; };
-    let Some(ext) = path.extension().and_then(|e| e.to_str()) else { return false; };
-    set.contains(&ext.to_ascii_lowercase())
+/// Loads one input: stdin, a memory map, or a copy under `--copy-input`. Gzip and zstd input
+/// is decompressed into memory; everything else keeps the zero-copy path.
+fn read_input(in_path: &Path, popts: &ProcessOptions) -> Result<InputBytes> {
+    let input = if is_stdin(in_path) {
+        // Stdin is usually a pipe, which can't be mapped.
+        let mut buf = Vec::new();
+        io::stdin().lock().read_to_end(&mut buf).context("Failed to read standard input")?;
+        InputBytes::Owned(buf)
+    } else if popts.copy_input {
+        let file = File::open(in_path).with_context(|| format!("Failed to open input file {:?}", in_path))?;
+        let mut buf = Vec::new();
+        (&file).read_to_end(&mut buf).with_context(|| format!("Failed to read input file {:?}", in_path))?;
+        InputBytes::Owned(buf)
+    } else {
+        let file = File::open(in_path).with_context(|| format!("Failed to open input file {:?}", in_path))?;
+        InputBytes::Mapped(unsafe { Mmap::map(&file)? })
+    };
+    if input.is_empty() {
+        return Ok(input);
+    }
+    match detect_compression(in_path, &input, popts) {
+        Some(compression) => Ok(InputBytes::Owned(decompress(compression, &input, in_path, popts)?)),
+        None => Ok(input),
+    }
 }
 
-fn process_file(decoder: &DerDecoder, root_type: &str, in_path: &Path, out_dir: &Path) -> Result<usize> {
-    let file = File::open(in_path).with_context(|| format!("Failed to open input file {:?}", in_path))?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    let data: &[u8] = &mmap;
-
-    if data.is_empty() {
-        return Ok(0);
+fn process_file<S: RecordSink>(
+    decoder: &DerDecoder,
+    root_type: &str,
+    in_path: &Path,
+    sink: &mut S,
+    popts: &ProcessOptions,
+) -> Result<FileOutcome> {
+    let input = read_input(in_path, popts)?;
+    let data: &[u8] = &input;
+
+    // Smallest possible TLV is a tag byte plus a length byte. An empty input has no records
+    // but still gets the format's empty output (`[]`, `{}`, a CSV header).
+    if data.len() == 1 {
+        return Err(anyhow!("input too short to contain a TLV ({} byte)", data.len()));
     }
 
-    let file_name = in_path
-        .file_name()
-        .ok_or_else(|| anyhow!("Input path has no filename: {:?}", in_path))?
-        .to_string_lossy()
-        .to_string();
-
-    let out_path = out_dir.join(format!("{}.jsonl", file_name));
-    let out_file = File::create(&out_path).with_context(|| format!("Failed to create output file {:?}", out_path))?;
+    let root_type = if data.is_empty() { root_type } else { pick_root_type(decoder, data, root_type, popts) };
 
-    let mut writer = BufWriter::with_capacity(64 * 1024 * 1024, out_file);
+    if !popts.count_only {
+        sink.begin_file(in_path)?;
+    }
     let mut hex_scratch: Vec<u8> = Vec::with_capacity(8 * 1024 * 1024);
 
+    let container_elem = if popts.container_root {
+        decoder.container_element_type(root_type)
+    } else {
+        None
+    };
+
+    let mut bufs = RecordBufs::default();
+    // `--count-only` walks the records exactly as a full run would but never decodes or writes them.
+    let mut records_out = match popts.format {
+        _ if popts.count_only => None,
+        OutputFormat::Jsonl => Some(RecordOut::Lines(LineBatcher::new(popts.batch_size))),
+        OutputFormat::Soa => Some(RecordOut::Soa(
+            SoaSink::new(decoder.opts.number_format, decoder.opts.special_floats).value_encoding(decoder.opts.value_encoding),
+        )),
+        OutputFormat::JsonArray => Some(RecordOut::Array(ArrayJoiner::default())),
+        OutputFormat::Csv => Some(RecordOut::Csv(csv_sink(decoder, container_elem.unwrap_or(root_type), popts)?)),
+    };
     let mut offset = 0usize;
     let mut count = 0usize;
+    // Records passed so far, including the first `--skip` ones that aren't written; this keeps
+    // `--record-id sequence` numbering the same as in a full run.
+    let mut seen = 0usize;
+    let mut skipped = 0usize;
+    let mut truncation_reported = false;
+    // Bytes outside any written or counted root record, and `count` when the first of them was met.
+    let mut bytes_skipped = 0usize;
+    let mut count_at_first_skip: Option<usize> = None;
+    let mut skip_bytes = |from: usize, to: usize, count: usize| {
+        if to > from {
+            bytes_skipped += to - from;
+            count_at_first_skip.get_or_insert(count);
+        }
+    };
 
-    while offset < data.len() {
-        let (tlv, new_off) = match decoder.find_next_root_tlv(data, offset, root_type) {
-            Some(t) => t,
-            None => break,
+    let limit_reached = |count: usize| popts.max_records.is_some_and(|max| count >= max);
+
+    while offset < data.len() && !limit_reached(count) {
+        let (tlv, new_off) = if popts.whole_file {
+            (whole_file_tlv(decoder, data, in_path)?, data.len())
+        } else if popts.one_record_per_file {
+            // The whole file is this one record; anything after it was reported by `single_root_tlv`.
+            (single_root_tlv(decoder, data, root_type, in_path)?, data.len())
+        } else {
+            match decoder.scan_root_tlv(data, offset, root_type) {
+                RootScan::Record(tlv, end) => {
+                    skip_bytes(offset, offset_in(data, tlv.raw), count);
+                    (tlv, end)
+                }
+                RootScan::Truncated { offset: at, need } => {
+                    let msg = truncation_message(at, need, data.len() - at);
+                    if popts.strict {
+                        return Err(anyhow!("{}", msg));
+                    }
+                    // Everything after a truncated record lies inside it; report it once and
+                    // keep scanning as before.
+                    if !truncation_reported {
+                        eprintln!("Warning: {:?}: {}", in_path, msg);
+                        truncation_reported = true;
+                        skipped += 1;
+                    }
+                    skip_bytes(offset, at + 1, count);
+                    offset = at + 1;
+                    continue;
+                }
+                RootScan::End => {
+                    skip_bytes(offset, data.len(), count);
+                    break;
+                }
+            }
         };
 
-        decoder.write_root_tlv_with_type(&tlv, root_type, &mut writer, &mut hex_scratch)?;
-        writer.write_all(b"\n")?;
+        if let Some(max) = popts.max_record_bytes {
+            if tlv.raw.len() > max {
+                let start = offset_in(data, tlv.raw);
+                if popts.strict {
+                    return Err(anyhow!(
+                        "root record at offset {}: {} bytes exceeds --max-record-bytes {}",
+                        start,
+                        tlv.raw.len(),
+                        max
+                    ));
+                }
+                eprintln!(
+                    "Skipping oversized root record in {:?} at offset {}: {} bytes exceeds --max-record-bytes {}",
+                    in_path,
+                    start,
+                    tlv.raw.len(),
+                    max
+                );
+                skipped += 1;
+                skip_bytes(offset_in(data, tlv.raw), new_off, count);
+                offset = new_off;
+                continue;
+            }
+        }
+
+        if decoder.opts.strict_der {
+            let start = offset_in(data, tlv.raw);
+            if let Some((at, violation)) = decoder.strict_der_violation(tlv.raw, start) {
+                if popts.strict {
+                    return Err(anyhow!("malformed root record at offset {}: {} at offset {}", start, violation, at));
+                }
+                eprintln!(
+                    "Skipping malformed root record in {:?} at offset {}: {} at offset {}",
+                    in_path, start, violation, at
+                );
+                skipped += 1;
+                skip_bytes(start, new_off, count);
+                offset = new_off;
+                continue;
+            }
+        }
+
+        if container_elem.is_some() {
+            let mut elem_off = 0usize;
+            while elem_off < tlv.value.len() && !limit_reached(count) {
+                let (elem, next) = match decoder.parse_tlv(tlv.value, elem_off) {
+                    Some(t) => t,
+                    None => break,
+                };
+                if next <= elem_off {
+                    break;
+                }
+                if seen >= popts.skip {
+                    let abs = offset_in(data, elem.raw);
+                    if let Some(records_out) = &mut records_out {
+                        records_out.emit(decoder, &elem, root_type, container_elem, seen, abs, popts, &mut bufs, sink, &mut hex_scratch)?;
+                    }
+                    count += 1;
+                }
+                seen += 1;
+                elem_off = next;
+            }
+        } else {
+            if seen >= popts.skip {
+                let abs = offset_in(data, tlv.raw);
+                if let Some(records_out) = &mut records_out {
+                    records_out.emit(decoder, &tlv, root_type, None, seen, abs, popts, &mut bufs, sink, &mut hex_scratch)?;
+                }
+                count += 1;
+            }
+            seen += 1;
+        }
 
         offset = new_off;
-        count += 1;
     }
 
-    writer.flush()?;
-    Ok(count)
+    if let Some(records_out) = records_out {
+        records_out.finish(sink)?;
+        sink.end_file()?;
+    }
+    let recovered = count_at_first_skip.map_or(0, |at| count - at);
+    Ok(FileOutcome { root_type: root_type.to_string(), records: count, skipped, bytes_skipped, recovered, bytes_read: data.len() })
+}
+
+/// The `--stats` summary of a whole run.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunStats {
+    total_files: usize,
+    failed_files: usize,
+    total_records: usize,
+    total_bytes_read: usize,
+    elapsed_seconds: f64,
+    files: Vec<FileStats>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileStats {
+    input: String,
+    records: usize,
+    bytes_read: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl RunStats {
+    fn new(results: &[(PathBuf, Option<PathBuf>, Result<FileOutcome>)], elapsed_seconds: f64) -> Self {
+        let files: Vec<FileStats> = results
+            .iter()
+            .map(|(input, _, res)| {
+                let input = input.to_string_lossy().into_owned();
+                match res {
+                    Ok(outcome) => FileStats { input, records: outcome.records, bytes_read: outcome.bytes_read, error: None },
+                    Err(e) => FileStats { input, records: 0, bytes_read: 0, error: Some(format!("{:#}", e)) },
+                }
+            })
+            .collect();
+        RunStats {
+            total_files: files.len(),
+            failed_files: files.iter().filter(|f| f.error.is_some()).count(),
+            total_records: files.iter().map(|f| f.records).sum(),
+            total_bytes_read: files.iter().map(|f| f.bytes_read).sum(),
+            elapsed_seconds,
+            files,
+        }
+    }
+}
+
+/// Writes the `--manifest` JSON: one entry per input with its output file, then the totals.
+fn write_manifest<W: Write>(
+    out: &mut W,
+    results: &[(PathBuf, Option<PathBuf>, Result<FileOutcome>)],
+    total_records: usize,
+    elapsed_secs: f64,
+) -> Result<()> {
+    let mut total_errors = 0usize;
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"files\": [")?;
+    for (i, (input, output, res)) in results.iter().enumerate() {
+        out.write_all(b"    {\"input\": ")?;
+        write_json_key(out, &input.to_string_lossy())?;
+        out.write_all(b", \"output\": ")?;
+        match output {
+            Some(p) => write_json_key(out, &p.to_string_lossy())?,
+            None => out.write_all(b"null")?,
+        }
+        // A failed file may still have left a partial output behind.
+        let bytes = output.as_ref().and_then(|p| std::fs::metadata(p).ok()).map_or(0, |m| m.len());
+        match res {
+            Ok(outcome) => {
+                total_errors += outcome.skipped;
+                out.write_all(b", \"rootType\": ")?;
+                write_json_key(out, &outcome.root_type)?;
+                write!(
+                    out,
+                    ", \"records\": {}, \"errors\": {}, \"bytes\": {}}}",
+                    outcome.records, outcome.skipped, bytes
+                )?;
+            }
+            Err(e) => {
+                total_errors += 1;
+                write!(out, ", \"records\": 0, \"errors\": 1, \"bytes\": {}, \"error\": ", bytes)?;
+                write_json_key(out, &format!("{:#}", e))?;
+                out.write_all(b"}")?;
+            }
+        }
+        out.write_all(if i + 1 < results.len() { b",\n" } else { b"\n" })?;
+    }
+    writeln!(out, "  ],")?;
+    writeln!(out, "  \"totalRecords\": {},", total_records)?;
+    writeln!(out, "  \"totalErrors\": {},", total_errors)?;
+    writeln!(out, "  \"elapsedSeconds\": {:.3}", elapsed_secs)?;
+    writeln!(out, "}}")?;
+    Ok(())
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
     let overall_start = Instant::now();
 
+    // Rayon's default 2 MB worker stacks don't fit the default --max-depth in debug builds.
+    rayon::ThreadPoolBuilder::new()
+        .stack_size(WORKER_STACK_BYTES)
+        .build_global()
+        .context("Failed to start the worker thread pool")?;
+
+    // With records going to stdout, progress messages move to stderr so they don't mix in.
+    let to_stdout = cli.output_dir.is_none() && !cli.count_only;
+    macro_rules! status {
+        ($($arg:tt)*) => {
+            if to_stdout {
+                eprintln!($($arg)*);
+            } else {
+                println!($($arg)*);
+            }
+        };
+    }
+    if to_stdout && !cli.inputs.iter().all(|p| is_stdin(p)) {
+        return Err(anyhow!("--output-dir is required unless the only input is - (stdin)"));
+    }
+
     let allowed_exts: Option<HashSet<String>> = cli.ext.as_ref().map(|s| {
         s.split(',')
             .map(|x| x.trim().trim_start_matches('.').to_ascii_lowercase())
@@ -995,42 +1482,201 @@ fn main() -> Result<()> {
     // LOGIC: Decide whether to Load Binary or Parse Text
     let schema = if let Some(bin_path) = &cli.load_compiled {
         // FAST PATH: Load from binary
-        println!("Loading pre-compiled schema from {:?}", bin_path);
+        if !cli.quiet {
+            status!("Loading pre-compiled schema from {:?}", bin_path);
+        }
         let file = File::open(bin_path).with_context(|| "Failed to open compiled schema")?;
         let decoded: Asn1Schema = bincode::deserialize_from(file)
             .with_context(|| "Failed to deserialize schema")?;
         decoded
-    } else if let Some(text_path) = &cli.schema {
+    } else if !cli.schema.is_empty() {
         // SLOW PATH: Parse text
-        println!("Parsing text schema from {:?}", text_path);
-        let schema_text = std::fs::read_to_string(text_path)
-            .with_context(|| format!("Failed to read schema file {:?}", text_path))?;
-        let parsed = Asn1Schema::parse(&schema_text)?;
+        let mut modules = Vec::with_capacity(cli.schema.len());
+        for text_path in &cli.schema {
+            if !cli.quiet {
+                status!("Parsing text schema from {:?}", text_path);
+            }
+            let schema_text = std::fs::read_to_string(text_path)
+                .with_context(|| format!("Failed to read schema file {:?}", text_path))?;
+            modules.push((text_path.display().to_string(), schema_text));
+        }
+        let universal_overrides = match &cli.universal_overrides {
+            Some(path) => load_universal_overrides(path)?,
+            None => HashMap::new(),
+        };
+        let parsed = Asn1Schema::parse_modules(&modules, universal_overrides)?;
+        for kind in parsed.universal_overrides.keys() {
+            if standard_universal_tag(kind).is_none() && !parsed.knows_type(kind) {
+                eprintln!("Warning: universal override for unknown type kind '{}' ignored", kind);
+            }
+        }
 
         // OPTIONAL: Save to binary if requested
         if let Some(save_path) = &cli.compile_schema {
-            println!("Saving compiled schema to {:?}", save_path);
+            status!("Saving compiled schema to {:?}", save_path);
             let file = File::create(save_path).with_context(|| "Failed to create schema dump file")?;
             bincode::serialize_into(file, &parsed).with_context(|| "Failed to serialize schema")?;
-            println!("Schema saved. You can now use --load-compiled next time.");
+            status!("Schema saved. You can now use --load-compiled next time.");
         }
         parsed
     } else {
         return Err(anyhow!("You must provide either --schema or --load-compiled"));
     };
 
-    let decoder = DerDecoder::new(schema);
+    let undefined = schema.undefined_references();
+    for (type_name, used_by) in &undefined {
+        eprintln!("Warning: type '{}' (used by {}) is not defined in the schema; its values will be hex", type_name, used_by);
+    }
+    if cli.strict_schema && !undefined.is_empty() {
+        return Err(anyhow!("{} referenced types are not defined in the schema (--strict-schema)", undefined.len()));
+    }
+    for (type_name, tag, kept, dropped) in &schema.duplicate_tags {
+        eprintln!(
+            "Warning: {} has more than one component tagged {}; '{}' is dropped in favour of '{}'",
+            type_name, tag_notation(*tag), dropped, kept
+        );
+    }
+    if cli.strict_schema && !schema.duplicate_tags.is_empty() {
+        return Err(anyhow!("{} components are lost to duplicate tags (--strict-schema)", schema.duplicate_tags.len()));
+    }
 
-    std::fs::create_dir_all(&cli.output_dir)?;
+    if let Some(path) = &cli.generate_rust {
+        let source = if cli.schema.is_empty() {
+            cli.load_compiled.as_ref().map(|p| p.display().to_string()).unwrap_or_default()
+        } else {
+            cli.schema.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+        };
+        let code = codegen::generate(&schema, &source)?;
+        std::fs::write(path, code).with_context(|| format!("Failed to write generated Rust to {:?}", path))?;
+        if !cli.quiet {
+            status!("Generated Rust decoders written to {:?}", path);
+        }
+    }
 
-    let root_type = cli.root_type.clone();
-    if !decoder.schema.knows_type(&root_type) {
-        return Err(anyhow!(
-            "root-type '{}' does not appear in parsed schema (check spelling / module).",
-            root_type
-        ));
+    if cli.validate_schema {
+        println!(
+            "Schema parsed: {} SEQUENCE, {} SET, {} CHOICE, {} SEQUENCE OF / SET OF, {} primitive and {} alias types",
+            schema.sequences.len(),
+            schema.sets.len(),
+            schema.choices.len(),
+            schema.seq_of_types.len() + schema.set_of_types.len(),
+            schema.primitives.len(),
+            schema.aliases.len(),
+        );
+        if let Some(rt) = cli.root_type.as_ref().filter(|rt| !schema.knows_type(rt)) {
+            return Err(anyhow!("root-type '{}' does not appear in parsed schema (check spelling / module).", rt));
+        }
+        let problems = undefined.len() + schema.duplicate_tags.len();
+        if problems > 0 {
+            return Err(anyhow!("schema has {} problem(s), see the warnings above", problems));
+        }
+        println!("Schema OK");
+        return Ok(());
+    }
+
+    let type_overrides = parse_type_overrides(&cli.force_type, &schema)?;
+    let charset_fields = parse_charset_fields(&cli.charset_field, &schema)?;
+
+    let opts = DecodeOptions {
+        max_array_elements: cli.max_array_elements,
+        bitstring_int: cli.bitstring_int,
+        bitstring_format: cli.bitstring_format,
+        emit_null_optionals: cli.emit_null_optionals,
+        type_overrides,
+        unwrap_depth: cli.unwrap_depth,
+        max_depth: cli.max_depth,
+        track_coverage: cli.coverage_out.is_some(),
+        unsigned_constrained: cli.unsigned_constrained,
+        semantic: cli.semantic,
+        ip_name_patterns: cli.ip_field_patterns.iter().map(|p| p.to_ascii_lowercase()).collect(),
+        number_format: if cli.all_numbers_as_strings {
+            NumberFormat::String
+        } else if cli.big_ints_as_strings {
+            NumberFormat::BigAsString
+        } else {
+            NumberFormat::Number
+        },
+        special_floats: cli.special_floats,
+        pretty_indent: cli.pretty.then_some(cli.indent),
+        with_tags: cli.with_tags,
+        with_raw: cli.with_raw,
+        value_encoding: match cli.value_encoding {
+            ValueEncoding::Hex if cli.hex_upper => ValueEncoding::HexUpper,
+            encoding => encoding,
+        },
+        utctime_pivot: cli.utctime_pivot,
+        charset_fields,
+        check_der: cli.check_der,
+        strict_der: cli.strict_der,
+        generic_unknown: cli.generic_unknown,
+        tbcd: cli.tbcd.then_some(TbcdOptions {
+            nibble_order: cli.tbcd_nibble_order,
+            stop_at_filler: cli.tbcd_stop_at_filler,
+            extended_digits: cli.tbcd_extended_digits,
+        }),
+        plmn_id: cli.plmn_id,
+        timestamp_epoch: cli.timestamp_epoch,
+        validate_constraints: cli.validate_constraints,
+        repeated_tags: cli.repeated_tags,
+        octet_as_text: cli.octet_as_text,
+    };
+    let decoder = DerDecoder::new(schema, opts);
+
+    if let (Some(dir), false) = (&cli.output_dir, cli.count_only) {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    // clap requires --root-type unless --validate-schema, which has returned by now
+    let root_type = cli.root_type.clone().unwrap_or_default();
+    for rt in std::iter::once(&root_type).chain(cli.root_type_fallback.as_ref()) {
+        if !decoder.schema.knows_type(rt) {
+            return Err(anyhow!(
+                "root-type '{}' does not appear in parsed schema (check spelling / module).",
+                rt
+            ));
+        }
+        if cli.container_root && decoder.container_element_type(rt).is_none() {
+            return Err(anyhow!(
+                "--container-root requires root-type '{}' to be a SEQUENCE OF / SET OF type",
+                rt
+            ));
+        }
+    }
+
+    if !matches!(cli.format, OutputFormat::Jsonl | OutputFormat::JsonArray) && cli.with_offset {
+        return Err(anyhow!("--with-offset only applies to --format jsonl and json-array; use --record-id offset"));
+    }
+    if cli.hex_upper && cli.value_encoding != ValueEncoding::Hex {
+        return Err(anyhow!("--hex-upper only applies to --value-encoding hex"));
+    }
+    if cli.format != OutputFormat::Jsonl && cli.batch_size > 1 {
+        return Err(anyhow!("--batch-size only applies to --format jsonl"));
+    }
+    if !matches!(cli.format, OutputFormat::Jsonl | OutputFormat::JsonArray) && cli.pretty {
+        return Err(anyhow!("--pretty only applies to --format jsonl and json-array"));
     }
 
+    let popts = ProcessOptions {
+        container_root: cli.container_root,
+        max_record_bytes: cli.max_record_bytes,
+        record_id: cli.record_id,
+        emit_timing: cli.emit_timing,
+        with_offset: cli.with_offset,
+        one_record_per_file: cli.one_record_per_file,
+        whole_file: cli.whole_file,
+        copy_input: cli.copy_input,
+        gunzip: cli.gunzip,
+        max_decompressed: cli.max_decompressed,
+        count_only: cli.count_only,
+        max_records: cli.max_records,
+        skip: cli.skip,
+        root_type_fallback: cli.root_type_fallback.clone(),
+        strict: cli.strict,
+        format: cli.format,
+        batch_size: cli.batch_size as usize,
+        csv_list_separator: cli.csv_list_separator.clone(),
+    };
+
     let input_files = expand_inputs(&cli.inputs, allowed_exts.as_ref())
         .with_context(|| "Failed to expand input files/directories")?;
 
@@ -1039,20 +1685,82 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    println!("Found {} input files", input_files.len());
+    if !cli.quiet {
+        status!("Found {} input files", input_files.len());
+    }
+
+    if cli.preflight || cli.preflight_strict {
+        let problems: Vec<(&PathBuf, anyhow::Error)> = input_files
+            .par_iter()
+            .filter(|p| !is_stdin(p))
+            .filter_map(|p| preflight_file(&decoder, p, &root_type, &popts).err().map(|e| (p, e)))
+            .collect();
+        for (path, e) in &problems {
+            eprintln!("Preflight failed for {:?}: {:#}", path, e);
+        }
+        status!(
+            "Preflight: {} of {} input files passed",
+            input_files.len() - problems.len(),
+            input_files.len()
+        );
+        if cli.preflight_strict && !problems.is_empty() {
+            return Err(anyhow!("preflight failed for {} input files; nothing was decoded", problems.len()));
+        }
+    }
+
+    let progress = match cli.progress {
+        Some(ProgressMode::Always) => Some(Progress::new(input_files.len())),
+        Some(ProgressMode::Auto) if io::stderr().is_terminal() => Some(Progress::new(input_files.len())),
+        _ => None,
+    };
 
-    let out_dir = cli.output_dir.clone();
-    let results: Vec<(PathBuf, Result<usize>)> = input_files
-        .par_iter()
-        .map(|p| (p.clone(), process_file(&decoder, &root_type, p, &out_dir)))
-        .collect();
+    let out_dir = if cli.count_only { None } else { cli.output_dir.clone() };
+    let results: Vec<(PathBuf, Option<PathBuf>, Result<FileOutcome>)> = std::thread::scope(|scope| {
+        let reporter = progress.as_ref().map(|progress| scope.spawn(|| progress.report()));
+        let results = input_files
+            .par_iter()
+            .map(|p| {
+                let result = match &out_dir {
+                    None => (p.clone(), None, process_file(&decoder, &root_type, p, &mut StdoutSink::new(), &popts)),
+                    Some(dir) => match output_path(p, dir, popts.format) {
+                        Ok(out) => {
+                            let res = process_file(&decoder, &root_type, p, &mut JsonlFileSink::new(&out), &popts);
+                            (p.clone(), Some(out), res)
+                        }
+                        Err(e) => (p.clone(), None, Err(e)),
+                    },
+                };
+                if let Some(progress) = &progress {
+                    progress.file_done(result.2.as_ref().map_or(0, |outcome| outcome.records));
+                }
+                result
+            })
+            .collect();
+        if let (Some(progress), Some(reporter)) = (&progress, reporter) {
+            progress.finished.store(true, Ordering::Relaxed);
+            reporter.thread().unpark();
+        }
+        results
+    });
 
     let mut total_records = 0usize;
-    for (path, res) in results {
+    for (path, _, res) in &results {
         match res {
-            Ok(count) => {
-                total_records += count;
-                println!("Decoded {} records from {:?}", count, path);
+            Ok(outcome) => {
+                total_records += outcome.records;
+                if !cli.quiet {
+                    if outcome.root_type == root_type {
+                        status!("Decoded {} records from {:?}", outcome.records, path);
+                    } else {
+                        status!("Decoded {} records from {:?} (root type {})", outcome.records, path, outcome.root_type);
+                    }
+                }
+                if cli.skip_errors && outcome.bytes_skipped > 0 {
+                    eprintln!(
+                        "Warning: {:?}: skipped {} bytes outside root records; {} records recovered after the first skip",
+                        path, outcome.bytes_skipped, outcome.recovered
+                    );
+                }
             }
             Err(e) => {
                 eprintln!("Decoding failed for {:?}: {:#}", path, e);
@@ -1060,7 +1768,156 @@ fn main() -> Result<()> {
         }
     }
 
-    println!("Total decoded records: {}", total_records);
-    println!("Total elapsed wall time: {:.3} s", overall_start.elapsed().as_secs_f64());
+    status!("Total decoded records: {}", total_records);
+
+    if let Some(path) = &cli.manifest {
+        let file = File::create(path).with_context(|| format!("Failed to create manifest {:?}", path))?;
+        let mut w = BufWriter::new(file);
+        write_manifest(&mut w, &results, total_records, overall_start.elapsed().as_secs_f64())?;
+        w.flush()?;
+    }
+
+    if let Some(path) = &cli.stats {
+        let file = File::create(path).with_context(|| format!("Failed to create stats file {:?}", path))?;
+        let mut w = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut w, &RunStats::new(&results, overall_start.elapsed().as_secs_f64()))?;
+        w.write_all(b"\n")?;
+        w.flush()?;
+    }
+
+    if let (Some(path), Some(cov)) = (&cli.coverage_out, &decoder.coverage) {
+        let file = File::create(path).with_context(|| format!("Failed to create coverage report {:?}", path))?;
+        let mut w = BufWriter::new(file);
+        cov.write_report(&mut w)?;
+        w.flush()?;
+        let (types_touched, types_total, fields_touched, fields_total) = cov.totals();
+        status!(
+            "Schema coverage: {}/{} types, {}/{} fields (report: {:?})",
+            types_touched, types_total, fields_touched, fields_total, path
+        );
+    }
+    status!("Total elapsed wall time: {:.3} s", overall_start.elapsed().as_secs_f64());
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+
+    /// Keeps the records of one input in memory, one per line.
+    #[derive(Default)]
+    struct CollectSink(Vec<u8>);
+
+    impl RecordSink for CollectSink {
+        fn begin_file(&mut self, _input: &Path) -> Result<()> {
+            Ok(())
+        }
+
+        fn write_record(&mut self, record: &[u8]) -> Result<()> {
+            self.0.extend_from_slice(record);
+            self.0.push(b'\n');
+            Ok(())
+        }
+
+        fn end_file(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    const TAP_SCHEMA: &str = "Tap DEFINITIONS IMPLICIT TAGS ::= BEGIN
+DataInterChange ::= CHOICE {
+  transferBatch TransferBatch,
+  notification Notification
+}
+TransferBatch ::= [APPLICATION 1] SEQUENCE {
+  sender [APPLICATION 196] Sender,
+  recipient [APPLICATION 182] Recipient,
+  fileSequenceNumber [APPLICATION 109] FileSequenceNumber
+}
+Notification ::= [APPLICATION 2] SEQUENCE {
+  sender [APPLICATION 196] Sender,
+  recipient [APPLICATION 182] Recipient
+}
+Sender ::= [APPLICATION 196] PrintableString
+Recipient ::= [APPLICATION 182] PrintableString
+FileSequenceNumber ::= [APPLICATION 109] NumericString
+END
+";
+
+    /// A TransferBatch followed by a Notification, as in a TAP file.
+    fn tap_file() -> Vec<u8> {
+        let sender = [&[0x5F, 0x81, 0x44, 0x05][..], b"DEUD2"].concat();
+        let recipient = [&[0x5F, 0x81, 0x36, 0x05][..], b"GBRCN"].concat();
+        let seq_no = [&[0x5F, 0x6D, 0x05][..], b"00042"].concat();
+        let batch = [sender.as_slice(), &recipient, &seq_no].concat();
+        let notification = [sender.as_slice(), &recipient].concat();
+        [&[0x61, batch.len() as u8][..], &batch, &[0x62, notification.len() as u8], &notification].concat()
+    }
+
+    /// A scratch directory under the system temp dir, removed on drop so a failing assertion
+    /// doesn't leave it behind.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            // Left over from a run that was killed before it could clean up.
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut enc = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn gzipped_input_decodes_like_its_plain_twin() {
+        let schema = Asn1Schema::parse(TAP_SCHEMA, HashMap::new()).unwrap();
+        let decoder = DerDecoder::new(schema, DecodeOptions::default());
+        let popts = ProcessOptions::default();
+        let tmp = TempDir::new("asn1-gunzip-test");
+        let dir = &tmp.0;
+
+        let plain = tap_file();
+        let half = plain.len() / 2;
+        let files = [
+            ("CDDEUD2GBRCN00042", plain.clone()),
+            ("CDDEUD2GBRCN00042.gz", gzip(&plain)),
+            // No extension: detected by the magic bytes alone.
+            ("CDDEUD2GBRCN00042.bin", gzip(&plain)),
+            // Concatenated gzip members decode as one stream.
+            ("CDDEUD2GBRCN00042.multi.gz", [gzip(&plain[..half]), gzip(&plain[half..])].concat()),
+        ];
+        let decode = |name: &str| {
+            let mut sink = CollectSink::default();
+            process_file(&decoder, "DataInterChange", &dir.join(name), &mut sink, &popts).unwrap();
+            String::from_utf8(sink.0).unwrap()
+        };
+        for (name, bytes) in &files {
+            std::fs::write(dir.join(name), bytes).unwrap();
+        }
+
+        let expected = decode(files[0].0);
+        assert_eq!(
+            expected,
+            "{\"transferBatch\":{\"sender\":\"DEUD2\",\"recipient\":\"GBRCN\",\"fileSequenceNumber\":\"00042\"}}\n\
+             {\"notification\":{\"sender\":\"DEUD2\",\"recipient\":\"GBRCN\"}}\n"
+        );
+        for (name, _) in &files[1..] {
+            assert_eq!(decode(name), expected, "{}", name);
+        }
+        // Uncompressed input keeps the zero-copy path.
+        assert!(matches!(read_input(&dir.join(files[0].0), &popts).unwrap(), InputBytes::Mapped(_)));
+    }
+}
@@ -0,0 +1,101 @@
+//! Destinations for finished output records. The CLI's per-file loop hands every encoded
+//! record (or `--batch-size` batch, or `--format soa` document) to a [`RecordSink`], so output
+//! can go to files, stdout or anywhere else without touching the decoding loop.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Receives the output of one input file, one record at a time.
+///
+/// Call sequence: `begin_file`, any number of `write_record`, then `end_file`. If decoding
+/// fails partway through, `end_file` is not called.
+pub trait RecordSink {
+    fn begin_file(&mut self, input: &Path) -> Result<()>;
+    /// One complete record as UTF-8 JSON, without a trailing newline.
+    fn write_record(&mut self, record: &[u8]) -> Result<()>;
+    fn end_file(&mut self) -> Result<()>;
+}
+
+/// Writes one file of JSON Lines per input file; the CLI's default.
+pub struct JsonlFileSink {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl JsonlFileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), writer: None }
+    }
+}
+
+impl RecordSink for JsonlFileSink {
+    fn begin_file(&mut self, _input: &Path) -> Result<()> {
+        let file = File::create(&self.path).with_context(|| format!("Failed to create output file {:?}", self.path))?;
+        self.writer = Some(BufWriter::with_capacity(64 * 1024 * 1024, file));
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        let writer = self.writer.as_mut().expect("write_record called before begin_file");
+        writer.write_all(record)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn end_file(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Above this many buffered bytes, [`StdoutSink`] writes its buffer out.
+const STDOUT_CHUNK: usize = 64 * 1024;
+
+/// Writes JSON Lines to standard output.
+///
+/// Records are buffered and written in whole-line chunks under the stdout lock, so sinks on
+/// different threads never split each other's lines.
+#[derive(Default)]
+pub struct StdoutSink {
+    buf: Vec<u8>,
+}
+
+impl StdoutSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn flush_buf(&mut self) -> Result<()> {
+        if !self.buf.is_empty() {
+            let mut out = io::stdout().lock();
+            out.write_all(&self.buf)?;
+            out.flush()?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+impl RecordSink for StdoutSink {
+    fn begin_file(&mut self, _input: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        self.buf.extend_from_slice(record);
+        self.buf.push(b'\n');
+        if self.buf.len() >= STDOUT_CHUNK {
+            self.flush_buf()?;
+        }
+        Ok(())
+    }
+
+    fn end_file(&mut self) -> Result<()> {
+        self.flush_buf()
+    }
+}
@@ -0,0 +1,86 @@
+//! Streaming access to the root records of a buffer, one at a time, for callers that want
+//! backpressure or an early exit instead of decoding the whole input up front.
+
+use anyhow::{anyhow, Result};
+
+use crate::{DerDecoder, RootScan, Tlv};
+
+/// Iterator over the raw root TLVs of a buffer, returned by [`DerDecoder::records`].
+///
+/// Bytes that don't start a root record are skipped, as in the CLI. A root header whose
+/// declared length runs past the end of the buffer yields one error and ends the iteration.
+pub struct RecordIter<'a> {
+    decoder: &'a DerDecoder<'a>,
+    data: &'a [u8],
+    root_type: &'a str,
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> RecordIter<'a> {
+    pub(crate) fn next_tlv(&mut self) -> Result<Option<Tlv<'a>>> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.decoder.scan_root_tlv(self.data, self.offset, self.root_type) {
+            RootScan::Record(tlv, end) => {
+                self.offset = end;
+                Ok(Some(tlv))
+            }
+            RootScan::Truncated { offset, need } => {
+                self.done = true;
+                Err(anyhow!(
+                    "truncated root record at offset {}: need {} bytes, have {}",
+                    offset,
+                    need,
+                    self.data.len() - offset
+                ))
+            }
+            RootScan::End => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Decodes the remaining records one by one and passes each one's JSON (without a trailing
+    /// newline) to `f`. The buffer is reused between records. Return `Ok(false)` from `f` to
+    /// stop early. Returns the number of records handed to `f`.
+    pub fn decode_each<F>(mut self, mut f: F) -> Result<usize>
+    where
+        F: FnMut(&[u8]) -> Result<bool>,
+    {
+        let mut json = Vec::new();
+        let mut scratch = Vec::new();
+        let mut count = 0usize;
+        while let Some(tlv) = self.next_tlv()? {
+            json.clear();
+            self.decoder.write_record(&tlv, self.root_type, None, &mut json, &mut scratch)?;
+            count += 1;
+            if !f(&json)? {
+                break;
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl<'a> Iterator for RecordIter<'a> {
+    type Item = Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_tlv().map(|tlv| tlv.map(|tlv| tlv.raw)).transpose()
+    }
+}
+
+impl std::iter::FusedIterator for RecordIter<'_> {}
+
+impl<'s> DerDecoder<'s> {
+    /// Lazily walks the `root_type` records of `data`; see [`RecordIter`].
+    pub fn records<'a>(&'a self, data: &'a [u8], root_type: &'a str) -> RecordIter<'a>
+    where
+        's: 'a,
+    {
+        RecordIter { decoder: self, data, root_type, offset: 0, done: false }
+    }
+}
@@ -0,0 +1,781 @@
+//! SAX-style output: the decoder walks each record and drives an [`OutputSink`] with events
+//! instead of writing JSON bytes, so callers can build their own structures (domain structs,
+//! database batches, ...) without a JSON round trip.
+//!
+//! Call sequence for every record, in encoding order:
+//!
+//! * `begin_record`, exactly one value, `end_record`.
+//! * A value is a scalar (`value`), or `begin_object` ... `end_object`, or `begin_array` ... `end_array`.
+//! * Inside an object every member is `key` followed by exactly one value (`field` is the
+//!   scalar shorthand). Keys are unique within an object; members follow the encoding order,
+//!   with `--emit-null-optionals` nulls and then `--check-der`'s `__derViolations` appended last.
+//! * Inside an array the elements are plain values. A truncated array ends with a
+//!   `{"__truncated":true,"__seen":N}` object.
+//! * Begin/end calls are always balanced, even for malformed input; if a sink call returns an
+//!   error, decoding stops and the error is returned as is.
+//!
+//! The JSONL output itself is produced by driving a [`JsonSink`], so any sink sees exactly
+//! what ends up in the `.jsonl` files.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+
+use crate::{base64_encode_into, hex_encode_into, write_json_key, DerDecoder, Tlv};
+
+/// A scalar handed to an [`OutputSink`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SinkValue<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    /// REAL; may be infinite or NaN.
+    Real(f64),
+    /// Decoded text (date/time types).
+    Str(&'a str),
+    /// Raw content octets; the JSONL writer renders these as hex.
+    Bytes(&'a [u8]),
+}
+
+/// Receives decode events. See the module docs for the order guarantees.
+pub trait OutputSink {
+    fn begin_record(&mut self) -> Result<()>;
+    fn end_record(&mut self) -> Result<()>;
+    fn begin_object(&mut self) -> Result<()>;
+    fn end_object(&mut self) -> Result<()>;
+    fn begin_array(&mut self) -> Result<()>;
+    fn end_array(&mut self) -> Result<()>;
+    fn key(&mut self, key: &str) -> Result<()>;
+    fn value(&mut self, value: SinkValue) -> Result<()>;
+
+    fn field(&mut self, key: &str, value: SinkValue) -> Result<()> {
+        self.key(key)?;
+        self.value(value)
+    }
+}
+
+/// Largest integer a JSON consumer using IEEE doubles (JavaScript, jq) reads back exactly.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// How [`JsonSink`] writes `Int` / `UInt` values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// Plain JSON numbers.
+    #[default]
+    Number,
+    /// Quote numbers outside +/-(2^53 - 1) so they survive double-based parsers.
+    BigAsString,
+    /// Quote every number.
+    String,
+}
+
+impl NumberFormat {
+    #[inline]
+    fn quotes(self, magnitude: u64) -> bool {
+        match self {
+            NumberFormat::Number => false,
+            NumberFormat::BigAsString => magnitude > MAX_SAFE_INTEGER,
+            NumberFormat::String => true,
+        }
+    }
+}
+
+/// How [`JsonSink`] writes REAL infinities and NaN, which JSON has no tokens for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SpecialFloats {
+    /// `"Infinity"`, `"-Infinity"`, `"NaN"`.
+    #[default]
+    String,
+    Null,
+    /// Fail the record.
+    Error,
+}
+
+/// How binary (`Bytes`) values are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ValueEncoding {
+    /// Two lowercase hex digits per byte.
+    #[default]
+    Hex,
+    /// Two uppercase hex digits per byte (`--hex-upper`).
+    #[value(skip)]
+    HexUpper,
+    /// Standard Base64 with padding; about a third shorter than hex.
+    Base64,
+}
+
+impl ValueEncoding {
+    /// Encodes `bytes` into `scratch` and returns the text.
+    #[inline]
+    fn encode_into<'a>(self, bytes: &[u8], scratch: &'a mut Vec<u8>) -> &'a [u8] {
+        match self {
+            ValueEncoding::Hex => hex_encode_into::<false>(bytes, scratch),
+            ValueEncoding::HexUpper => hex_encode_into::<true>(bytes, scratch),
+            ValueEncoding::Base64 => base64_encode_into(bytes, scratch),
+        }
+    }
+}
+
+/// The JSONL writer as a sink: one compact JSON object per record, newline terminated.
+/// With [`JsonSink::indent`] each record is pretty-printed over several lines instead.
+pub struct JsonSink<W: Write> {
+    out: W,
+    scratch: Vec<u8>,
+    numbers: NumberFormat,
+    special_floats: SpecialFloats,
+    // Spaces per nesting level; `None` writes compact JSON.
+    indent: Option<usize>,
+    binary: ValueEncoding,
+    // One entry per open object/array: whether it already holds a member.
+    open: Vec<bool>,
+    after_key: bool,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(out: W) -> Self {
+        Self::with_scratch(out, Vec::new())
+    }
+
+    /// Reuses `scratch` for hex encoding; get it back with [`JsonSink::into_scratch`].
+    pub fn with_scratch(out: W, scratch: Vec<u8>) -> Self {
+        Self {
+            out,
+            scratch,
+            numbers: NumberFormat::Number,
+            special_floats: SpecialFloats::String,
+            indent: None,
+            binary: ValueEncoding::Hex,
+            open: Vec::new(),
+            after_key: false,
+        }
+    }
+
+    pub fn number_format(mut self, numbers: NumberFormat) -> Self {
+        self.numbers = numbers;
+        self
+    }
+
+    pub fn special_floats(mut self, special_floats: SpecialFloats) -> Self {
+        self.special_floats = special_floats;
+        self
+    }
+
+    /// Pretty-prints with `indent` spaces per level (`--pretty`); `None` keeps compact output.
+    pub fn indent(mut self, indent: Option<usize>) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    pub fn value_encoding(mut self, binary: ValueEncoding) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    /// Starts a new line indented to the current depth (pretty output only).
+    fn newline(&mut self) -> Result<()> {
+        if let Some(width) = self.indent {
+            self.out.write_all(b"\n")?;
+            for _ in 0..width * self.open.len() {
+                self.out.write_all(b" ")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_real(&mut self, v: f64) -> Result<()> {
+        if v.is_finite() {
+            // `{:?}` is the shortest round-trip form and always valid JSON (`1.0`, `1e-7`).
+            // Doubles survive double-based parsers, so only `String` quotes them.
+            let quote = if self.numbers == NumberFormat::String { "\"" } else { "" };
+            write!(self.out, "{}{:?}{}", quote, v, quote)?;
+            return Ok(());
+        }
+        let name = special_float_name(v);
+        match self.special_floats {
+            SpecialFloats::String => write_json_key(&mut self.out, name),
+            SpecialFloats::Null => Ok(self.out.write_all(b"null")?),
+            SpecialFloats::Error => Err(anyhow!("REAL value {} has no JSON representation (--special-floats error)", name)),
+        }
+    }
+
+    fn write_number(&mut self, digits: &str, magnitude: u64) -> Result<()> {
+        if self.numbers.quotes(magnitude) {
+            self.out.write_all(b"\"")?;
+            self.out.write_all(digits.as_bytes())?;
+            self.out.write_all(b"\"")?;
+        } else {
+            self.out.write_all(digits.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    pub fn into_scratch(self) -> Vec<u8> {
+        self.scratch
+    }
+
+    fn separate(&mut self) -> Result<()> {
+        if self.after_key {
+            self.after_key = false;
+            return Ok(());
+        }
+        if let Some(has_member) = self.open.last_mut() {
+            if *has_member {
+                self.out.write_all(b",")?;
+            }
+            *has_member = true;
+            self.newline()?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, byte: &[u8]) -> Result<()> {
+        if self.open.pop() == Some(true) {
+            self.newline()?;
+        }
+        self.out.write_all(byte)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputSink for JsonSink<W> {
+    fn begin_record(&mut self) -> Result<()> {
+        self.open.clear();
+        self.after_key = false;
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> Result<()> {
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn begin_object(&mut self) -> Result<()> {
+        self.separate()?;
+        self.out.write_all(b"{")?;
+        self.open.push(false);
+        Ok(())
+    }
+
+    fn end_object(&mut self) -> Result<()> {
+        self.close(b"}")
+    }
+
+    fn begin_array(&mut self) -> Result<()> {
+        self.separate()?;
+        self.out.write_all(b"[")?;
+        self.open.push(false);
+        Ok(())
+    }
+
+    fn end_array(&mut self) -> Result<()> {
+        self.close(b"]")
+    }
+
+    fn key(&mut self, key: &str) -> Result<()> {
+        self.separate()?;
+        write_json_key(&mut self.out, key)?;
+        self.out.write_all(if self.indent.is_some() { b": " } else { b":" })?;
+        self.after_key = true;
+        Ok(())
+    }
+
+    fn value(&mut self, value: SinkValue) -> Result<()> {
+        self.separate()?;
+        let mut itoa_buf = itoa::Buffer::new();
+        match value {
+            SinkValue::Null => self.out.write_all(b"null")?,
+            SinkValue::Bool(b) => self.out.write_all(if b { b"true" } else { b"false" })?,
+            SinkValue::Int(v) => self.write_number(itoa_buf.format(v), v.unsigned_abs())?,
+            SinkValue::UInt(v) => self.write_number(itoa_buf.format(v), v)?,
+            SinkValue::Real(v) => self.write_real(v)?,
+            SinkValue::Str(s) => write_json_key(&mut self.out, s)?,
+            SinkValue::Bytes(b) => {
+                let text = self.binary.encode_into(b, &mut self.scratch);
+                self.out.write_all(b"\"")?;
+                self.out.write_all(text)?;
+                self.out.write_all(b"\"")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a non-finite REAL is spelled when written as a string.
+fn special_float_name(v: f64) -> &'static str {
+    if v.is_nan() {
+        "NaN"
+    } else if v > 0.0 {
+        "Infinity"
+    } else {
+        "-Infinity"
+    }
+}
+
+/// One `--format soa` column: the comma-joined JSON values of the records seen so far.
+struct Column {
+    name: String,
+    cells: Vec<u8>,
+    filled: usize,
+}
+
+impl Column {
+    /// Fills the records before `records` that lacked this field with `null`.
+    fn pad_to(&mut self, records: usize) {
+        while self.filled < records {
+            self.push(b"null");
+        }
+    }
+
+    fn push(&mut self, json: &[u8]) {
+        if self.filled > 0 {
+            self.cells.push(b',');
+        }
+        self.cells.extend_from_slice(json);
+        self.filled += 1;
+    }
+}
+
+/// Struct-of-arrays output (`--format soa`): buffers every record of a file and writes one
+/// `{"field":[v1,v2,...],...}` object with a column per top-level field, `null` where a record
+/// lacks the field. Columns appear in first-seen order; a record that isn't an object goes in
+/// the `__value` column. Nested values are rendered by a [`JsonSink`].
+pub struct SoaSink {
+    columns: Vec<Column>,
+    index: HashMap<String, usize>,
+    records: usize,
+    // Open containers in the current record; 1 = inside the record object.
+    depth: usize,
+    current: usize,
+    cell: JsonSink<Vec<u8>>,
+}
+
+impl SoaSink {
+    pub fn new(numbers: NumberFormat, special_floats: SpecialFloats) -> Self {
+        Self {
+            columns: Vec::new(),
+            index: HashMap::new(),
+            records: 0,
+            depth: 0,
+            current: 0,
+            cell: JsonSink::new(Vec::new()).number_format(numbers).special_floats(special_floats),
+        }
+    }
+
+    pub fn value_encoding(mut self, binary: ValueEncoding) -> Self {
+        self.cell = self.cell.value_encoding(binary);
+        self
+    }
+
+    fn select(&mut self, name: &str) {
+        self.current = match self.index.get(name) {
+            Some(&i) => i,
+            None => {
+                self.columns.push(Column { name: name.to_string(), cells: Vec::new(), filled: 0 });
+                self.index.insert(name.to_string(), self.columns.len() - 1);
+                self.columns.len() - 1
+            }
+        };
+    }
+
+    /// A value at record level that isn't the record object goes in `__value`.
+    fn enter_value(&mut self) -> Result<()> {
+        if self.depth == 0 {
+            self.select("__value");
+            self.depth = 1;
+            self.cell.begin_record()?;
+        }
+        Ok(())
+    }
+
+    fn finish_cell(&mut self) {
+        let records = self.records;
+        let column = &mut self.columns[self.current];
+        column.pad_to(records);
+        let bytes = self.cell.get_mut();
+        column.push(bytes);
+        bytes.clear();
+    }
+
+    /// Adds a record-level metadata column value (`__id`, ...) to the current record.
+    pub fn meta(&mut self, key: &str, value: SinkValue) -> Result<()> {
+        self.select(key);
+        self.cell.begin_record()?;
+        self.cell.value(value)?;
+        self.finish_cell();
+        Ok(())
+    }
+
+    /// Writes the buffered columns as one JSON object.
+    pub fn finish<W: Write>(mut self, out: &mut W) -> Result<()> {
+        out.write_all(b"{")?;
+        for (i, column) in self.columns.iter_mut().enumerate() {
+            column.pad_to(self.records);
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            write_json_key(out, &column.name)?;
+            out.write_all(b":[")?;
+            out.write_all(&column.cells)?;
+            out.write_all(b"]")?;
+        }
+        out.write_all(b"}")?;
+        Ok(())
+    }
+}
+
+impl OutputSink for SoaSink {
+    fn begin_record(&mut self) -> Result<()> {
+        self.depth = 0;
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> Result<()> {
+        self.records += 1;
+        Ok(())
+    }
+
+    fn begin_object(&mut self) -> Result<()> {
+        if self.depth == 0 {
+            self.depth = 1;
+            return Ok(());
+        }
+        self.cell.begin_object()?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn end_object(&mut self) -> Result<()> {
+        if self.depth == 1 {
+            self.depth = 0;
+            return Ok(());
+        }
+        self.cell.end_object()?;
+        self.depth -= 1;
+        if self.depth == 1 {
+            self.finish_cell();
+        }
+        Ok(())
+    }
+
+    fn begin_array(&mut self) -> Result<()> {
+        self.enter_value()?;
+        self.cell.begin_array()?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn end_array(&mut self) -> Result<()> {
+        self.cell.end_array()?;
+        self.depth -= 1;
+        if self.depth == 1 {
+            self.finish_cell();
+        }
+        Ok(())
+    }
+
+    fn key(&mut self, key: &str) -> Result<()> {
+        if self.depth == 1 {
+            self.select(key);
+            self.cell.begin_record()
+        } else {
+            self.cell.key(key)
+        }
+    }
+
+    fn value(&mut self, value: SinkValue) -> Result<()> {
+        self.enter_value()?;
+        self.cell.value(value)?;
+        if self.depth == 1 {
+            self.finish_cell();
+        }
+        Ok(())
+    }
+}
+
+/// CSV output (`--format csv`): one row per record with a fixed set of columns, normally the
+/// root SEQUENCE / SET's components in declaration order, so the header doesn't depend on which
+/// OPTIONAL fields the records happen to carry. Scalars are written as plain text (binary per
+/// [`ValueEncoding`], absent fields empty), SEQUENCE OF elements are joined with a separator, and any other
+/// nested value is rendered as compact JSON by a [`JsonSink`]. Members without a column are
+/// dropped.
+pub struct CsvSink<W: Write> {
+    out: W,
+    columns: Vec<String>,
+    index: HashMap<String, usize>,
+    list_separator: String,
+    special_floats: SpecialFloats,
+    binary: ValueEncoding,
+    header_written: bool,
+    // Unquoted text of each cell in the current row.
+    cells: Vec<Vec<u8>>,
+    // Open containers in the current record; 1 = inside the record object, 2 = inside a
+    // top-level array.
+    depth: usize,
+    // Cell of the current top-level member; `None` drops it.
+    current: Option<usize>,
+    // Elements written to the current top-level array.
+    list_items: usize,
+    // Depth at which a JSON-rendered value began, while one is open.
+    json_from: Option<usize>,
+    json: JsonSink<Vec<u8>>,
+    scratch: Vec<u8>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(out: W, columns: Vec<String>, numbers: NumberFormat, special_floats: SpecialFloats) -> Self {
+        let index = columns.iter().enumerate().map(|(i, c)| (c.clone(), i)).collect();
+        Self {
+            out,
+            cells: vec![Vec::new(); columns.len()],
+            columns,
+            index,
+            list_separator: ";".to_string(),
+            special_floats,
+            binary: ValueEncoding::Hex,
+            header_written: false,
+            depth: 0,
+            current: None,
+            list_items: 0,
+            json_from: None,
+            json: JsonSink::new(Vec::new()).number_format(numbers).special_floats(special_floats),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Text between SEQUENCE OF elements within a cell (default `;`).
+    pub fn list_separator(mut self, separator: &str) -> Self {
+        self.list_separator = separator.to_string();
+        self
+    }
+
+    pub fn value_encoding(mut self, binary: ValueEncoding) -> Self {
+        self.binary = binary;
+        self.json = self.json.value_encoding(binary);
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    /// Sets a record-level metadata column (`__id`, ...) of the current record, if it has one.
+    pub fn meta(&mut self, key: &str, value: SinkValue) -> Result<()> {
+        if let Some(&col) = self.index.get(key) {
+            self.current = Some(col);
+            self.cells[col].clear();
+            self.write_text(value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the header row unless a record already did, so an empty input still gets one.
+    pub fn finish(&mut self) -> Result<()> {
+        self.write_header()
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        if !self.header_written {
+            self.header_written = true;
+            for (i, column) in self.columns.iter().enumerate() {
+                if i > 0 {
+                    self.out.write_all(b",")?;
+                }
+                write_csv_field(&mut self.out, column.as_bytes())?;
+            }
+            self.out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Appends `value` as plain text to the current cell.
+    fn write_text(&mut self, value: SinkValue) -> Result<()> {
+        let Some(col) = self.current else {
+            return Ok(());
+        };
+        let cell = &mut self.cells[col];
+        let mut itoa_buf = itoa::Buffer::new();
+        match value {
+            SinkValue::Null => {}
+            SinkValue::Bool(b) => cell.extend_from_slice(if b { b"true" } else { b"false" }),
+            SinkValue::Int(v) => cell.extend_from_slice(itoa_buf.format(v).as_bytes()),
+            SinkValue::UInt(v) => cell.extend_from_slice(itoa_buf.format(v).as_bytes()),
+            SinkValue::Real(v) if v.is_finite() => write!(cell, "{:?}", v)?,
+            SinkValue::Real(v) => match self.special_floats {
+                SpecialFloats::String => cell.extend_from_slice(special_float_name(v).as_bytes()),
+                SpecialFloats::Null => {}
+                SpecialFloats::Error => {
+                    return Err(anyhow!(
+                        "REAL value {} has no JSON representation (--special-floats error)",
+                        special_float_name(v)
+                    ))
+                }
+            },
+            SinkValue::Str(s) => cell.extend_from_slice(s.as_bytes()),
+            SinkValue::Bytes(b) => cell.extend_from_slice(self.binary.encode_into(b, &mut self.scratch)),
+        }
+        Ok(())
+    }
+
+    /// Called before a value inside a top-level array: separates it from the previous element.
+    fn next_list_item(&mut self) {
+        if self.depth == 2 && self.json_from.is_none() {
+            if let Some(col) = self.current {
+                if self.list_items > 0 {
+                    self.cells[col].extend_from_slice(self.list_separator.as_bytes());
+                }
+            }
+            self.list_items += 1;
+        }
+    }
+
+    /// Opens a container: the record itself, a top-level array, or a JSON-rendered value.
+    fn begin_container(&mut self, array: bool) -> Result<()> {
+        match (self.depth, self.json_from) {
+            (0, _) if !array => {}
+            (0, _) => return Err(anyhow!("--format csv needs a SEQUENCE or SET root type")),
+            (1, None) if array => self.list_items = 0,
+            (_, None) => {
+                self.next_list_item();
+                self.json_from = Some(self.depth + 1);
+                self.json.begin_record()?;
+                self.begin_json(array)?;
+            }
+            (_, Some(_)) => self.begin_json(array)?,
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn begin_json(&mut self, array: bool) -> Result<()> {
+        if array {
+            self.json.begin_array()
+        } else {
+            self.json.begin_object()
+        }
+    }
+
+    fn end_container(&mut self, array: bool) -> Result<()> {
+        if self.json_from.is_some() {
+            if array {
+                self.json.end_array()?;
+            } else {
+                self.json.end_object()?;
+            }
+            if self.json_from == Some(self.depth) {
+                self.json_from = None;
+                if let Some(col) = self.current {
+                    self.cells[col].extend_from_slice(self.json.get_mut());
+                }
+                self.json.get_mut().clear();
+            }
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+/// Writes one CSV field, quoted (RFC 4180) when it holds a comma, quote or line break.
+fn write_csv_field<W: Write>(out: &mut W, text: &[u8]) -> Result<()> {
+    if !text.iter().any(|&b| matches!(b, b',' | b'"' | b'\n' | b'\r')) {
+        out.write_all(text)?;
+        return Ok(());
+    }
+    out.write_all(b"\"")?;
+    for part in text.split_inclusive(|&b| b == b'"') {
+        out.write_all(part)?;
+        if part.ends_with(b"\"") {
+            out.write_all(b"\"")?;
+        }
+    }
+    out.write_all(b"\"")?;
+    Ok(())
+}
+
+impl<W: Write> OutputSink for CsvSink<W> {
+    fn begin_record(&mut self) -> Result<()> {
+        self.cells.iter_mut().for_each(Vec::clear);
+        self.depth = 0;
+        self.current = None;
+        self.json_from = None;
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> Result<()> {
+        self.write_header()?;
+        for (i, cell) in self.cells.iter().enumerate() {
+            if i > 0 {
+                self.out.write_all(b",")?;
+            }
+            write_csv_field(&mut self.out, cell)?;
+        }
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn begin_object(&mut self) -> Result<()> {
+        self.begin_container(false)
+    }
+
+    fn end_object(&mut self) -> Result<()> {
+        self.end_container(false)
+    }
+
+    fn begin_array(&mut self) -> Result<()> {
+        self.begin_container(true)
+    }
+
+    fn end_array(&mut self) -> Result<()> {
+        self.end_container(true)
+    }
+
+    fn key(&mut self, key: &str) -> Result<()> {
+        if self.json_from.is_some() {
+            return self.json.key(key);
+        }
+        if self.depth == 1 {
+            self.current = self.index.get(key).copied();
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, value: SinkValue) -> Result<()> {
+        match self.depth {
+            0 => Err(anyhow!("--format csv needs a SEQUENCE or SET root type")),
+            _ if self.json_from.is_some() => self.json.value(value),
+            _ => {
+                self.next_list_item();
+                self.write_text(value)
+            }
+        }
+    }
+}
+
+impl DerDecoder<'_> {
+    /// Decodes every root record in `data` into `sink`. Returns the number of records. A root
+    /// record cut off by the end of `data` is an error, as in [`DerDecoder::records`].
+    pub fn decode_to_sink(&self, data: &[u8], root_type: &str, sink: &mut dyn OutputSink) -> Result<usize> {
+        let mut records = self.records(data, root_type);
+        let mut count = 0usize;
+        while let Some(tlv) = records.next_tlv()? {
+            self.decode_record_to_sink(&tlv, root_type, sink)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn decode_record_to_sink(&self, tlv: &Tlv, root_type: &str, sink: &mut dyn OutputSink) -> Result<()> {
+        sink.begin_record()?;
+        self.visit_root(tlv, root_type, 0, sink)?;
+        sink.end_record()
+    }
+}